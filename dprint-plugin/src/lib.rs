@@ -2,8 +2,11 @@ use anyhow::anyhow;
 use serde::Serialize;
 
 use anyhow::Result;
+use dprint_core::configuration::get_nullable_value;
 use dprint_core::configuration::get_unknown_property_diagnostics;
+use dprint_core::configuration::get_value;
 use dprint_core::configuration::ConfigKeyMap;
+use dprint_core::configuration::ConfigurationDiagnostic;
 use dprint_core::configuration::GlobalConfiguration;
 use dprint_core::plugins::FileMatchingInfo;
 use dprint_core::plugins::PluginInfo;
@@ -15,7 +18,43 @@ use dprint_core::generate_plugin_code;
 
 #[derive(Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Configuration {}
+pub struct Configuration {
+  /// Lines at the start of a file that start with this prefix are comments,
+  /// which are kept as-is. The rest of the file is formatted as a message.
+  comment_prefix: Option<String>,
+  /// How a file is split into multiple messages.
+  message_separator: MessageSeparator,
+  /// If set, every line of the form `key = "message"` (with this separator
+  /// instead of `=`) contains a message in a quoted string, which is formatted
+  /// in place. All other lines are kept as-is.
+  key_value_separator: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageSeparator {
+  /// The entire file is a single message.
+  None,
+  /// Every non-blank line is a separate message.
+  Line,
+  /// Messages are separated by one or more blank lines.
+  BlankLine,
+}
+
+impl std::str::FromStr for MessageSeparator {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "none" => Ok(MessageSeparator::None),
+      "line" => Ok(MessageSeparator::Line),
+      "blankLine" => Ok(MessageSeparator::BlankLine),
+      _ => Err(format!(
+        "expected one of \"none\", \"line\", or \"blankLine\", got \"{s}\""
+      )),
+    }
+  }
+}
 
 pub struct Mf2PluginHandler;
 
@@ -37,13 +76,60 @@ impl SyncPluginHandler<Configuration> for Mf2PluginHandler {
 
   fn resolve_config(
     &mut self,
-    config: ConfigKeyMap,
+    mut config: ConfigKeyMap,
     _global_config: &GlobalConfiguration,
   ) -> PluginResolveConfigurationResult<Configuration> {
-    let diagnostics = get_unknown_property_diagnostics(config);
+    let mut diagnostics = vec![];
+    let mut comment_prefix: Option<String> =
+      get_nullable_value(&mut config, "commentPrefix", &mut diagnostics);
+    if comment_prefix.as_deref() == Some("") {
+      diagnostics.push(ConfigurationDiagnostic {
+        property_name: "commentPrefix".to_string(),
+        message: "Comment prefix must not be empty".to_string(),
+      });
+      comment_prefix = None;
+    }
+    let message_separator = get_value(
+      &mut config,
+      "messageSeparator",
+      MessageSeparator::None,
+      &mut diagnostics,
+    );
+    let mut key_value_separator: Option<String> =
+      get_nullable_value(&mut config, "keyValueSeparator", &mut diagnostics);
+    if key_value_separator.as_deref() == Some("") {
+      diagnostics.push(ConfigurationDiagnostic {
+        property_name: "keyValueSeparator".to_string(),
+        message: "Key value separator must not be empty".to_string(),
+      });
+      key_value_separator = None;
+    }
+    if key_value_separator.is_some() {
+      // Every key value line is a message, and all other lines are kept as-is,
+      // so there is nothing for these options to do.
+      if comment_prefix.is_some() {
+        diagnostics.push(ConfigurationDiagnostic {
+          property_name: "commentPrefix".to_string(),
+          message: "Comment prefix can not be used with keyValueSeparator"
+            .to_string(),
+        });
+      }
+      if message_separator != MessageSeparator::None {
+        diagnostics.push(ConfigurationDiagnostic {
+          property_name: "messageSeparator".to_string(),
+          message: "Message separator can not be used with keyValueSeparator"
+            .to_string(),
+        });
+      }
+    }
+    diagnostics.extend(get_unknown_property_diagnostics(config));
 
     PluginResolveConfigurationResult {
-      config: Configuration {},
+      config: Configuration {
+        comment_prefix,
+        message_separator,
+        key_value_separator,
+      },
       diagnostics,
       file_matching: FileMatchingInfo {
         file_extensions: vec!["mf2".to_string()],
@@ -66,15 +152,8 @@ impl SyncPluginHandler<Configuration> for Mf2PluginHandler {
       dprint_core::plugins::SyncHostFormatRequest,
     ) -> dprint_core::plugins::FormatResult,
   ) -> dprint_core::plugins::FormatResult {
-    let message = std::str::from_utf8(&request.file_bytes)?;
-    let (ast, diagnostics, info) = mf2_parser::parse(message);
-    for diagnostic in diagnostics {
-      if diagnostic.fatal() {
-        return Err(anyhow!("failed to format: {:?}", diagnostic));
-      }
-    }
-
-    let printed = mf2_printer::print(&ast, Some(&info));
+    let text = std::str::from_utf8(&request.file_bytes)?;
+    let printed = format_text(text, request.config)?;
     if printed.as_bytes() != request.file_bytes {
       Ok(Some(printed.into_bytes()))
     } else {
@@ -83,5 +162,425 @@ impl SyncPluginHandler<Configuration> for Mf2PluginHandler {
   }
 }
 
+fn format_text(text: &str, config: &Configuration) -> Result<String> {
+  if let Some(separator) = &config.key_value_separator {
+    return format_key_values(text, separator);
+  }
+
+  let prefix = config.comment_prefix.as_deref();
+  if config.message_separator == MessageSeparator::None {
+    return format_message(text, prefix)
+      .map_err(|err| anyhow!("failed to format: {err}"));
+  }
+
+  // Every message is formatted, so that all messages that can not be formatted
+  // are reported together, with the byte offsets of the message in the file.
+  let segments = split_messages(text, config.message_separator);
+  let mut output = String::with_capacity(text.len());
+  let mut errors = vec![];
+  let mut last_end = 0;
+  for (start, end) in segments {
+    output.push_str(&text[last_end..start]);
+    let segment = &text[start..end];
+    match format_message(segment, prefix) {
+      Ok(printed) => {
+        // The line break after the message is part of the separator.
+        let printed = printed.strip_suffix('\n').unwrap_or(&printed);
+        if config.message_separator == MessageSeparator::Line
+          && printed.contains('\n')
+        {
+          output.push_str(segment);
+        } else {
+          output.push_str(printed);
+        }
+      }
+      Err(err) => errors.push(format!("message at {start}..{end}: {err}")),
+    }
+    last_end = end;
+  }
+  if !errors.is_empty() {
+    return Err(anyhow!(
+      "failed to format {} of the messages:\n{}",
+      errors.len(),
+      errors.join("\n")
+    ));
+  }
+  output.push_str(&text[last_end..]);
+  Ok(output)
+}
+
+/// Format the message in every line of the form `key = "message"`, keeping all
+/// other lines as-is. See [split_key_value] for the exact rules.
+fn format_key_values(text: &str, separator: &str) -> Result<String> {
+  let mut output = String::with_capacity(text.len());
+  let mut errors = vec![];
+  let mut line_start = 0;
+  for line in text.split_inclusive('\n') {
+    let content = line.trim_end_matches(['\n', '\r']);
+    let line_break = &line[content.len()..];
+    match split_key_value(content, separator) {
+      // Lines with an escape that the plugin does not understand, like `\t`,
+      // are kept as-is, as writing them back would change the escape.
+      Some((head, value, tail)) if unescape_string(value).is_some() => {
+        let message = unescape_string(value).unwrap();
+        match format_message(&message, None) {
+          Ok(printed) => {
+            let printed = printed.strip_suffix('\n').unwrap_or(&printed);
+            output.push_str(head);
+            output.push_str(&escape_string(printed));
+            output.push_str(tail);
+          }
+          Err(err) => {
+            let start = line_start + head.len();
+            let end = start + value.len();
+            errors.push(format!("message at {start}..{end}: {err}"));
+            output.push_str(content);
+          }
+        }
+      }
+      _ => output.push_str(content),
+    }
+    output.push_str(line_break);
+    line_start += line.len();
+  }
+  if !errors.is_empty() {
+    return Err(anyhow!(
+      "failed to format {} of the messages:\n{}",
+      errors.len(),
+      errors.join("\n")
+    ));
+  }
+  Ok(output)
+}
+
+/// Split a line of the form `key = "value"` into the text up to and including
+/// the opening quote, the value between the quotes, and the text from the
+/// closing quote to the end of the line. Returns `None` if the line does not
+/// have this form.
+///
+/// The key is everything before the first occurrence of `separator`, and must
+/// not be blank. The value is a string in double quotes, in which `\"` and
+/// `\\` are escapes. Only whitespace and an optional `,` may come after the
+/// closing quote, and whitespace may surround the separator.
+fn split_key_value<'a>(
+  line: &'a str,
+  separator: &str,
+) -> Option<(&'a str, &'a str, &'a str)> {
+  let separator_start = line.find(separator)?;
+  if line[..separator_start].trim().is_empty() {
+    return None;
+  }
+  let after_separator = separator_start + separator.len();
+  let rest = &line[after_separator..];
+  let quote = after_separator + (rest.len() - rest.trim_start().len());
+  if !line[quote..].starts_with('"') {
+    return None;
+  }
+
+  let value_start = quote + 1;
+  let mut chars = line[value_start..].char_indices();
+  let value_end = loop {
+    match chars.next()? {
+      (_, '\\') => {
+        chars.next()?;
+      }
+      (i, '"') => break value_start + i,
+      _ => {}
+    }
+  };
+
+  let tail = &line[value_end..];
+  let after_quote = tail[1..].trim();
+  if !(after_quote.is_empty() || after_quote == ",") {
+    return None;
+  }
+  Some((&line[..value_start], &line[value_start..value_end], tail))
+}
+
+/// Replace the escapes `\"`, `\\`, and `\n` in a quoted value by the
+/// characters they represent. Returns `None` if the value contains any other
+/// backslash, because [escape_string] could not write it back unchanged.
+fn unescape_string(value: &str) -> Option<String> {
+  let mut out = String::with_capacity(value.len());
+  let mut chars = value.chars();
+  while let Some(ch) = chars.next() {
+    match ch {
+      '\\' => match chars.next()? {
+        'n' => out.push('\n'),
+        ch @ ('"' | '\\') => out.push(ch),
+        _ => return None,
+      },
+      ch => out.push(ch),
+    }
+  }
+  Some(out)
+}
+
+/// The inverse of [unescape_string]. Every backslash is escaped, so `\{` in a
+/// message is written as `\\{`.
+fn escape_string(message: &str) -> String {
+  let mut out = String::with_capacity(message.len());
+  for ch in message.chars() {
+    match ch {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      ch => out.push(ch),
+    }
+  }
+  out
+}
+
+fn format_message(text: &str, comment_prefix: Option<&str>) -> Result<String> {
+  let (comments, message) = split_leading_comments(text, comment_prefix);
+
+  // Only lints are allowed: syntax errors and semantic errors mean that the
+  // message is not valid, so it is left as-is instead of being formatted.
+  let (ast, diagnostics, info, _) = mf2_parser::parse_all(message);
+  for diagnostic in diagnostics {
+    if !diagnostic.is_lint() {
+      return Err(anyhow!("{:?}", diagnostic));
+    }
+  }
+
+  let printed = mf2_printer::print(&ast, Some(&info));
+  Ok(format!("{comments}{printed}"))
+}
+
+/// Split the text into the comment lines at the start of the text (including
+/// their line breaks), and the rest of the text. A comment line is a line that
+/// starts with `prefix`. Once a line that is not a comment is found, the rest
+/// of the text is the message, even if it contains lines that start with
+/// `prefix`, because those lines may be part of a pattern.
+fn split_leading_comments<'a>(
+  text: &'a str,
+  prefix: Option<&str>,
+) -> (&'a str, &'a str) {
+  let Some(prefix) = prefix else {
+    return ("", text);
+  };
+
+  let mut end = 0;
+  for line in text.split_inclusive('\n') {
+    if !line.starts_with(prefix) {
+      break;
+    }
+    end += line.len();
+  }
+  text.split_at(end)
+}
+
+/// Split the text into messages, returning the start and end byte offset of
+/// each message. Line breaks and blank lines between messages are not part of
+/// any message. A blank line is a line that is empty or only contains
+/// whitespace.
+fn split_messages(
+  text: &str,
+  separator: MessageSeparator,
+) -> Vec<(usize, usize)> {
+  let mut segments = vec![];
+  let mut current: Option<(usize, usize)> = None;
+  let mut line_start = 0;
+  for line in text.split_inclusive('\n') {
+    let content = line.trim_end_matches(['\n', '\r']);
+    let content_end = line_start + content.len();
+    if content.trim().is_empty() {
+      segments.extend(current.take());
+    } else {
+      match (separator, current) {
+        (MessageSeparator::BlankLine, Some((start, _))) => {
+          current = Some((start, content_end));
+        }
+        _ => {
+          segments.extend(current.take());
+          current = Some((line_start, content_end));
+        }
+      }
+    }
+    line_start += line.len();
+  }
+  segments.extend(current);
+  segments
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(comment_prefix: Option<&str>) -> Configuration {
+    Configuration {
+      comment_prefix: comment_prefix.map(str::to_string),
+      message_separator: MessageSeparator::None,
+      key_value_separator: None,
+    }
+  }
+
+  fn config_with_key_value_separator(separator: &str) -> Configuration {
+    Configuration {
+      key_value_separator: Some(separator.to_string()),
+      ..config(None)
+    }
+  }
+
+  fn config_with_separator(
+    comment_prefix: Option<&str>,
+    message_separator: MessageSeparator,
+  ) -> Configuration {
+    Configuration {
+      message_separator,
+      ..config(comment_prefix)
+    }
+  }
+
+  #[test]
+  fn format_without_comment_prefix() {
+    assert_eq!(
+      format_text("# {$a}", &config(None)).unwrap(),
+      "# {$a}".to_string()
+    );
+  }
+
+  #[test]
+  fn format_with_leading_comments() {
+    let text = "# greeting.mf2\n# Shown on the home page.\n.input   {$name}\n{{Hello {  $name  }!}}";
+    assert_eq!(
+      format_text(text, &config(Some("#"))).unwrap(),
+      "# greeting.mf2\n# Shown on the home page.\n.input {$name}\n{{Hello {$name}!}}\n"
+    );
+  }
+
+  #[test]
+  fn comment_prefix_inside_message() {
+    let text = "// comment\r\nHello\n// { $name }";
+    assert_eq!(
+      format_text(text, &config(Some("//"))).unwrap(),
+      "// comment\r\nHello\n// {$name}"
+    );
+  }
+
+  #[test]
+  fn one_message_per_line() {
+    let text =
+      "\nHello {  $a }\r\n\n# {$b  }\n.input {$c} {{{$c}}}\n{{ x }}\n{$d  }";
+    assert_eq!(
+      format_text(
+        text,
+        &config_with_separator(Some("#"), MessageSeparator::Line)
+      )
+      .unwrap(),
+      "\nHello {$a}\r\n\n# {$b  }\n.input {$c} {{{$c}}}\n{{ x }}\n{$d}"
+    );
+  }
+
+  #[test]
+  fn messages_separated_by_blank_lines() {
+    let text = "# a\n.input {$a}   {{{$a}}}\n\n  \n{ $x }\n\nHello\n{  $b }\n";
+    assert_eq!(
+      format_text(
+        text,
+        &config_with_separator(Some("#"), MessageSeparator::BlankLine)
+      )
+      .unwrap(),
+      "# a\n.input {$a}\n{{{$a}}}\n\n  \n{$x}\n\nHello\n{$b}\n"
+    );
+  }
+
+  #[test]
+  fn fatal_error_in_message() {
+    assert!(format_text("# comment\n.input", &config(Some("#"))).is_err());
+  }
+
+  #[test]
+  fn key_value_lines() {
+    let text = "# Messages\r\n\
+                greeting = \"Hello {  $name }!\"\r\n\
+                \n\
+                  escaped=\"Say \\\"{ |hi| }\\\" \\\\{ok\\\\} a\\\\b\",  \n\
+                tab = \"a\\tb {  $a }\"\n\
+                unicode = \"caf\\u00e9 {  $a }\"\n\
+                complex = \".input {$n :number}  {{{$n} items}}\"\n\
+                not a message = 'single {  $a }'\n\
+                = \"{  $no_key }\"\n\
+                trailing = \"{  $a }\" text\n\
+                last = \"{ $b }\"";
+    assert_eq!(
+      format_text(text, &config_with_key_value_separator("=")).unwrap(),
+      "# Messages\r\n\
+       greeting = \"Hello {$name}!\"\r\n\
+       \n\
+         escaped=\"Say \\\"{|hi|}\\\" \\\\{ok\\\\} a\\\\b\",  \n\
+       tab = \"a\\tb {  $a }\"\n\
+       unicode = \"caf\\u00e9 {  $a }\"\n\
+       complex = \".input {$n :number}\\n{{{$n} items}}\"\n\
+       not a message = 'single {  $a }'\n\
+       = \"{  $no_key }\"\n\
+       trailing = \"{  $a }\" text\n\
+       last = \"{$b}\""
+    );
+  }
+
+  #[test]
+  fn key_value_lines_with_other_separator() {
+    let text = "\"a.b\": \"{  $a }\",\n\"c\": \"{  $c }\"\n";
+    assert_eq!(
+      format_text(text, &config_with_key_value_separator(":")).unwrap(),
+      "\"a.b\": \"{$a}\",\n\"c\": \"{$c}\"\n"
+    );
+  }
+
+  #[test]
+  fn key_value_lines_with_fatal_errors() {
+    let text = "a = \"{ $a }\"\nb = \"{ \"\n";
+    let err = format_text(text, &config_with_key_value_separator("="))
+      .unwrap_err()
+      .to_string();
+    assert!(err.contains("message at 18..20: Placeholder is"), "{err}");
+  }
+
+  #[test]
+  fn escape_string_round_trips() {
+    for value in [r#"a \" b"#, r"a \\ b", r"a \n b", r"\\{", r"end \\"] {
+      assert_eq!(escape_string(&unescape_string(value).unwrap()), value);
+    }
+    // Escapes that can not be written back unchanged are not unescaped.
+    for value in [r"\{", r"a\tb", r"caf\u00e9"] {
+      assert_eq!(unescape_string(value), None);
+    }
+  }
+
+  #[test]
+  fn fatal_errors_in_multiple_messages() {
+    let text = "{ $a }\n{ \n{$b}\n.input\n";
+    let err =
+      format_text(text, &config_with_separator(None, MessageSeparator::Line))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("message at 7..9: Placeholder is"), "{err}");
+    assert!(
+      err.contains("message at 15..21: Input declaration"),
+      "{err}"
+    );
+    assert!(!err.contains("message at 0..6"), "{err}");
+  }
+
+  #[test]
+  fn lint_in_message() {
+    assert_eq!(
+      format_text("{ |a\\{b| }", &config(None)).unwrap(),
+      "{|a\\{b|}"
+    );
+    assert_eq!(
+      format_text("{ 1 @a=$b }", &config(None)).unwrap(),
+      "{1 @a=$b}"
+    );
+  }
+
+  #[test]
+  fn semantic_error_in_message() {
+    let text = ".local $a = {1}\n.local $a = {2}\n{{}}";
+    assert!(format_text(text, &config(None)).is_err());
+  }
+}
+
 #[cfg(target_arch = "wasm32")]
 generate_plugin_code!(Mf2PluginHandler, Mf2PluginHandler);
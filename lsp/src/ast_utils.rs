@@ -1,28 +1,185 @@
+use mf2_parser::ast;
 use mf2_parser::ast::AnyNode;
 use mf2_parser::ast::Message;
 use mf2_parser::Location;
+use mf2_parser::Span;
 use mf2_parser::Spanned as _;
 use mf2_parser::Visit as _;
 use mf2_parser::VisitAny;
+use yoke::Yokeable;
 
-struct FindNodeVisitor<'ast, 'text> {
-  loc: Location,
-  result: Option<AnyNode<'ast, 'text>>,
+/// An index of all nodes in a message, used to quickly find the nodes at a
+/// given location or in a given span.
+#[derive(Yokeable)]
+pub struct SpanIndex<'ast> {
+  /// All nodes, in the order they are visited in. This is source text order,
+  /// with parents before their children, so the nodes are sorted by start.
+  nodes: Vec<AnyNode<'ast, 'ast>>,
+  /// The index of the parent of each node in `nodes`.
+  parents: Vec<Option<usize>>,
 }
 
-impl<'ast, 'text> VisitAny<'ast, 'text> for FindNodeVisitor<'ast, 'text> {
-  fn before(&mut self, node: AnyNode<'ast, 'text>) {
-    if node.span().contains_loc(self.loc) {
-      self.result = Some(node);
-    }
+struct SpanIndexVisitor<'ast> {
+  index: SpanIndex<'ast>,
+  stack: Vec<usize>,
+}
+
+impl<'ast> VisitAny<'ast, 'ast> for SpanIndexVisitor<'ast> {
+  fn before(&mut self, node: AnyNode<'ast, 'ast>) {
+    self.stack.push(self.index.nodes.len());
+    self
+      .index
+      .parents
+      .push(self.stack.iter().rev().nth(1).copied());
+    self.index.nodes.push(node);
+  }
+
+  fn after(&mut self, _node: AnyNode<'ast, 'ast>) {
+    self.stack.pop();
+  }
+}
+
+impl<'ast> SpanIndex<'ast> {
+  pub fn new(ast: &'ast Message<'ast>) -> Self {
+    let mut visitor = SpanIndexVisitor {
+      index: SpanIndex {
+        nodes: vec![],
+        parents: vec![],
+      },
+      stack: vec![],
+    };
+    visitor.visit_message(ast);
+    visitor.index
   }
+
+  /// Find the innermost node that contains the given location.
+  pub fn node_at(&self, loc: Location) -> Option<AnyNode<'ast, 'ast>> {
+    self.nodes_at(loc).next()
+  }
+
+  /// Get all nodes that contain the given location, from the innermost node
+  /// to the outermost node.
+  pub fn nodes_at(
+    &self,
+    loc: Location,
+  ) -> impl Iterator<Item = AnyNode<'ast, 'ast>> + '_ {
+    // The nodes containing the location are the last node starting at or
+    // before the location and its ancestors, ignoring the nodes that end
+    // before the location.
+    let last = self
+      .nodes
+      .partition_point(|node| node.span().start <= loc)
+      .checked_sub(1);
+
+    std::iter::successors(last, |&i| self.parents[i])
+      .filter(move |&i| self.nodes[i].span().contains_loc(loc))
+      .map(|i| self.nodes[i].clone())
+  }
+
+  /// Get all nodes that are fully contained in the given span, in source text
+  /// order.
+  pub fn nodes_in(
+    &self,
+    span: Span,
+  ) -> impl Iterator<Item = AnyNode<'ast, 'ast>> + '_ {
+    let first = self
+      .nodes
+      .partition_point(|node| node.span().start < span.start);
+    self.nodes[first..]
+      .iter()
+      .take_while(move |node| node.span().start <= span.end)
+      .filter(move |node| node.span().end <= span.end)
+      .cloned()
+  }
+}
+
+/// Print a single expression, formatted the same way as it would be in a
+/// formatted message.
+pub fn print_expression(expression: &ast::Expression) -> String {
+  let pattern = ast::Pattern {
+    parts: vec![ast::PatternPart::Expression(expression.clone())],
+  };
+  mf2_printer::print(&Message::Simple(pattern), None)
 }
 
-pub fn find_node<'ast, 'text>(
-  ast: &'ast Message<'text>,
-  loc: Location,
-) -> Option<AnyNode<'ast, 'text>> {
-  let mut visitor = FindNodeVisitor { loc, result: None };
-  visitor.visit_message(ast);
-  visitor.result
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct FindNodeVisitor<'ast, 'text> {
+    loc: Location,
+    result: Option<AnyNode<'ast, 'text>>,
+  }
+
+  impl<'ast, 'text> VisitAny<'ast, 'text> for FindNodeVisitor<'ast, 'text> {
+    fn before(&mut self, node: AnyNode<'ast, 'text>) {
+      if node.span().contains_loc(self.loc) {
+        self.result = Some(node);
+      }
+    }
+  }
+
+  #[test]
+  fn node_at_matches_linear_search() {
+    let text = ".input {$a :fn opt=|x|}\n.local $b = {$a @attr}\n.match $a $b\n1 * {{Hello {$a} {#b}\\{{/b}}}\n* * {{}}";
+    let (ast, _, info) = mf2_parser::parse(text);
+    let index = SpanIndex::new(&ast);
+
+    let end = info.utf8_len(ast.span());
+    for i in 0..=end {
+      let loc = ast.span().start + &text[..i as usize];
+      let mut visitor = FindNodeVisitor { loc, result: None };
+      visitor.visit_message(&ast);
+      let expected = visitor.result;
+      let actual = index.node_at(loc);
+      match (expected, actual) {
+        (Some(expected), Some(actual)) => {
+          assert!(expected.same(&actual), "{expected:?} != {actual:?} at {i}")
+        }
+        (None, None) => {}
+        (expected, actual) => panic!("{expected:?} != {actual:?} at {i}"),
+      }
+    }
+  }
+
+  #[test]
+  fn nodes_at_location() {
+    let (ast, _, _) = mf2_parser::parse("a {$b :fn}");
+    let index = SpanIndex::new(&ast);
+    let loc = ast.span().start + "a {$";
+    let nodes = index.nodes_at(loc).map(|n| n.kind()).collect::<Vec<_>>();
+    assert_eq!(
+      nodes,
+      vec![
+        mf2_parser::ast::AnyNodeKind::Variable,
+        mf2_parser::ast::AnyNodeKind::VariableExpression,
+        mf2_parser::ast::AnyNodeKind::Pattern,
+      ]
+    );
+  }
+
+  #[test]
+  fn nodes_in_span() {
+    let (ast, _, _) = mf2_parser::parse("a {$b} {$c}");
+    let index = SpanIndex::new(&ast);
+    let start = ast.span().start;
+    let span = Span::new(start + "a "..start + "a {$b}");
+    let nodes = index.nodes_in(span).map(|n| n.kind()).collect::<Vec<_>>();
+    assert_eq!(
+      nodes,
+      vec![
+        mf2_parser::ast::AnyNodeKind::VariableExpression,
+        mf2_parser::ast::AnyNodeKind::Variable,
+      ]
+    );
+  }
+
+  #[test]
+  fn print_local_expression() {
+    let text = ".input {$a}\n.local $b = {  $a :number   @x}\n{{}}";
+    let (ast, mut diagnostics, _) = mf2_parser::parse(text);
+    let scope = mf2_parser::analyze_semantics(&ast, &mut diagnostics);
+    let expression = scope.get_local_expression("b").unwrap();
+    assert_eq!(print_expression(expression), "{$a :number @x}");
+  }
 }
@@ -1,45 +1,108 @@
+use std::sync::LazyLock;
+
 use mf2_parser::ast;
 use mf2_parser::ast::AnyNode;
 use mf2_parser::ast::Message;
+use mf2_parser::Diagnostic;
+use mf2_parser::FunctionRegistry;
 use mf2_parser::Location;
 use mf2_parser::Scope;
 use mf2_parser::Span;
 use mf2_parser::Spanned;
+use mf2_parser::VariableKind;
 use mf2_parser::Visit as _;
 use mf2_parser::VisitAny;
 
+use crate::ast_utils::print_expression;
+
 #[derive(Debug)]
 pub enum CompletionAction {
   Insert,
   Replace(Span),
 }
 
+#[derive(Debug)]
+pub enum CompletionKind {
+  Variable(VariableKind),
+  Function,
+  OptionKey,
+  Keyword,
+}
+
 #[derive(Debug)]
 pub struct Completion {
   pub text: String,
   pub action: CompletionAction,
+  pub kind: CompletionKind,
+  /// For local variables, the expression the variable is declared with.
+  pub expression: Option<String>,
 }
 
 #[derive(Debug)]
 enum AllowedCompletionType<'text> {
   None,
   Variable(Option<(Span, &'text str)>),
+  Function(Option<(Span, &'text str)>),
+  OptionKey(&'static [String], Option<(Span, &'text str)>),
+  /// The span of the (possibly partial) keyword after a `.` that starts a
+  /// statement.
+  Keyword(Span),
+}
+
+const STATEMENT_KEYWORDS: [&str; 3] = ["input", "local", "match"];
+
+/// The functions of the default function registry of the MessageFormat 2
+/// specification, together with the keys of the options they accept.
+static BUILTIN_FUNCTIONS: LazyLock<FunctionRegistry> =
+  LazyLock::new(FunctionRegistry::with_builtins);
+
+/// Get the option keys of the built-in function with the given identifier, or
+/// `None` if it is not a built-in function or it accepts no options.
+pub fn builtin_function_options(
+  id: &ast::Identifier,
+) -> Option<&'static [String]> {
+  BUILTIN_FUNCTIONS
+    .option_keys(id)
+    .filter(|options| !options.is_empty())
 }
 
 pub struct CompletionsProvider<'scope: 'text, 'text> {
   scope: &'scope Scope<'text>,
+  loc: Location,
   completion_type: AllowedCompletionType<'text>,
+  /// The variable declared by the declaration that contains the location, if
+  /// any. It can not be used in its own declaration.
+  declaring: Option<&'text str>,
 }
 
 impl<'scope, 'text> CompletionsProvider<'scope, 'text> {
-  pub fn new<'ast>(
-    ast: &'ast Message<'text>,
+  pub fn new(
+    ast: &'scope Message<'text>,
+    diagnostics: &[Diagnostic<'text>],
     loc: Location,
     scope: &'scope Scope<'text>,
   ) -> Self {
+    let declaring = match ast {
+      Message::Complex(complex) => complex
+        .declarations
+        .iter()
+        .find(|decl| {
+          let span = decl.span();
+          span.start < loc && loc <= span.end
+        })
+        .map(|decl| match decl {
+          ast::Declaration::InputDeclaration(decl) => {
+            decl.expression.variable.name
+          }
+          ast::Declaration::LocalDeclaration(decl) => decl.variable.name,
+        }),
+      Message::Simple(_) => None,
+    };
     Self {
       scope,
-      completion_type: get_completion_type(ast, loc),
+      loc,
+      completion_type: get_completion_type(ast, diagnostics, loc),
+      declaring,
     }
   }
 
@@ -51,37 +114,125 @@ impl<'scope, 'text> CompletionsProvider<'scope, 'text> {
     match self.completion_type {
       AllowedCompletionType::None => vec![],
       AllowedCompletionType::Variable(None) => self
-        .scope
-        .get_names()
-        .map(|n| Completion {
-          text: format!("${}", n),
-          action: CompletionAction::Insert,
-        })
+        .names()
+        .map(|n| self.completion(n, CompletionAction::Insert))
         .collect(),
       AllowedCompletionType::Variable(Some((span, name))) => {
         let include_self =
           name.len() > 1 && self.scope.get_spans(name).unwrap().len() > 1;
 
         let all_names = self
-          .scope
-          .get_names()
+          .names()
           .filter(|n| include_self || *n != name)
-          .map(|n| Completion {
-            text: format!("${}", n),
-            action: CompletionAction::Replace(span),
-          });
+          .map(|n| self.completion(n, CompletionAction::Replace(span)));
 
         all_names.collect()
       }
+      AllowedCompletionType::Function(None) => BUILTIN_FUNCTIONS
+        .function_names()
+        .into_iter()
+        .map(|name| {
+          keyword_completion(
+            name,
+            CompletionKind::Function,
+            CompletionAction::Insert,
+          )
+        })
+        .collect(),
+      AllowedCompletionType::Function(Some((span, _))) => BUILTIN_FUNCTIONS
+        .function_names()
+        .into_iter()
+        .map(|name| {
+          keyword_completion(
+            name,
+            CompletionKind::Function,
+            CompletionAction::Replace(span),
+          )
+        })
+        .collect(),
+      AllowedCompletionType::OptionKey(keys, None) => keys
+        .iter()
+        .map(|key| {
+          keyword_completion(
+            key,
+            CompletionKind::OptionKey,
+            CompletionAction::Insert,
+          )
+        })
+        .collect(),
+      AllowedCompletionType::OptionKey(keys, Some((span, _))) => keys
+        .iter()
+        .map(|key| {
+          keyword_completion(
+            key,
+            CompletionKind::OptionKey,
+            CompletionAction::Replace(span),
+          )
+        })
+        .collect(),
+      AllowedCompletionType::Keyword(span) => STATEMENT_KEYWORDS
+        .iter()
+        .map(|keyword| {
+          keyword_completion(
+            keyword,
+            CompletionKind::Keyword,
+            CompletionAction::Replace(span),
+          )
+        })
+        .collect(),
+    }
+  }
+
+  /// Names of the variables in scope, excluding those that are declared after
+  /// the completion location, or by the declaration that contains it. Using
+  /// those would be a usage before declaration.
+  fn names(&self) -> impl Iterator<Item = &'scope str> + '_ {
+    self.scope.get_names().filter(|name| {
+      Some(*name) != self.declaring
+        && self
+          .scope
+          .get_declaration_span(name)
+          .map_or(true, |span| span.start <= self.loc)
+    })
+  }
+
+  fn completion(&self, name: &str, action: CompletionAction) -> Completion {
+    let kind = self.scope.get_kind(name).unwrap_or(VariableKind::External);
+    let expression = match kind {
+      VariableKind::Local => {
+        self.scope.get_local_expression(name).map(print_expression)
+      }
+      VariableKind::Input | VariableKind::External => None,
+    };
+    Completion {
+      text: format!("${}", name),
+      action,
+      kind: CompletionKind::Variable(kind),
+      expression,
     }
   }
 }
 
+fn keyword_completion(
+  text: &str,
+  kind: CompletionKind,
+  action: CompletionAction,
+) -> Completion {
+  Completion {
+    text: text.to_string(),
+    action,
+    kind,
+    expression: None,
+  }
+}
+
 struct CompletionLocationVisitor<'ast, 'text> {
   loc: Location,
   parent_node: AnyNode<'ast, 'text>,
   current_node: AnyNode<'ast, 'text>,
   previous_node: Option<AnyNode<'ast, 'text>>,
+  /// The innermost annotation that contains the location.
+  annotation: Option<&'ast ast::Annotation<'text>>,
 }
 
 impl<'ast, 'text> VisitAny<'ast, 'text>
@@ -90,6 +241,18 @@ impl<'ast, 'text> VisitAny<'ast, 'text>
   fn before(&mut self, node: AnyNode<'ast, 'text>) {
     let span = node.span();
     if span.start < self.loc && self.loc <= span.end {
+      match node {
+        AnyNode::LiteralExpression(expr) => {
+          self.annotation = expr.annotation.as_ref();
+        }
+        AnyNode::VariableExpression(expr) => {
+          self.annotation = expr.annotation.as_ref();
+        }
+        AnyNode::AnnotationExpression(expr) => {
+          self.annotation = Some(&expr.annotation);
+        }
+        _ => {}
+      }
       self.parent_node = std::mem::replace(&mut self.current_node, node);
       assert!(!self.parent_node.same(&self.current_node));
       self.previous_node = None;
@@ -105,13 +268,29 @@ impl<'ast, 'text> VisitAny<'ast, 'text>
 
 fn get_completion_type<'text>(
   ast: &Message<'text>,
+  diagnostics: &[Diagnostic<'text>],
   loc: Location,
 ) -> AllowedCompletionType<'text> {
+  // Statements with an unrecognized keyword, like a partially typed `.i` or a
+  // bare `.`, are skipped by the parser and only show up as diagnostics.
+  for diagnostic in diagnostics {
+    if let Diagnostic::InvalidStatement { span, keyword } = diagnostic {
+      let keyword_start = span.start + '.';
+      let keyword_span = Span::new(keyword_start..keyword_start + *keyword);
+      if keyword_span.start <= loc && loc <= keyword_span.end {
+        // .|
+        // .i|
+        return AllowedCompletionType::Keyword(keyword_span);
+      }
+    }
+  }
+
   let mut visitor = CompletionLocationVisitor {
     loc,
     current_node: AnyNode::Message(ast),
     parent_node: AnyNode::Message(ast),
     previous_node: None,
+    annotation: None,
   };
   visitor.visit_message(ast);
 
@@ -119,6 +298,7 @@ fn get_completion_type<'text>(
     current_node,
     parent_node,
     previous_node,
+    annotation,
     ..
   } = visitor;
 
@@ -130,6 +310,26 @@ fn get_completion_type<'text>(
       // $f|
       AllowedCompletionType::Variable(Some((var.span(), var.name)))
     }
+    (X::Identifier(id), _, _)
+      if annotation
+        .is_some_and(|annotation| annotation.id.start == id.start) =>
+    {
+      // :f|
+      AllowedCompletionType::Function(Some((id.span(), id.name)))
+    }
+    _ if annotation.is_some_and(|annotation| annotation.id.start == loc) => {
+      let annotation = annotation.unwrap();
+      if annotation.id.span().is_empty() {
+        // :|
+        AllowedCompletionType::Function(None)
+      } else {
+        // :|f
+        AllowedCompletionType::Function(Some((
+          annotation.id.span(),
+          annotation.id.name,
+        )))
+      }
+    }
     (X::LiteralExpression(literal_expression), _, None)
       if literal_expression.literal.span().is_empty() =>
     {
@@ -157,21 +357,51 @@ fn get_completion_type<'text>(
       | X::AnnotationExpression(_)
       | X::LiteralExpression(_),
       _,
-      Some(X::FnOrMarkupOption(opt)),
-    ) => {
-      #[allow(clippy::collapsible_match)]
-      if let LiteralOrVariable::Literal(Literal::Text(text)) = &opt.value {
-        if text.span().is_empty() && text.span().start != opt.key.span().end {
-          // { $a :fn param= | }
-          return AllowedCompletionType::Variable(None);
+      Some(previous),
+    ) if annotation.is_some_and(|annotation| {
+      let span = annotation.span();
+      span.start <= previous.span().start && previous.span().end <= span.end
+    }) =>
+    {
+      let annotation = annotation.unwrap();
+      // The option right before the cursor, which is not necessarily the last
+      // option of the annotation.
+      if let X::FnOrMarkupOption(FnOrMarkupOption {
+        key,
+        value: LiteralOrVariable::Literal(Literal::Text(text)),
+      }) = previous
+      {
+        if text.span().is_empty() {
+          if text.span().start != key.span().end {
+            // { $a :fn param= | }
+            return AllowedCompletionType::Variable(None);
+          }
+          // { $a :fn param | }
+          return AllowedCompletionType::None;
         }
       }
-      AllowedCompletionType::None
+      match builtin_function_options(&annotation.id) {
+        // { $a :fn | }
+        // { $a :fn p1=a | }
+        Some(keys) => AllowedCompletionType::OptionKey(keys, None),
+        None => AllowedCompletionType::None,
+      }
     }
     (X::AnnotationExpression(_), _, None) => {
       // { | :fn }
       AllowedCompletionType::Variable(None)
     }
+    (X::Identifier(id), X::FnOrMarkupOption(option), _)
+      if id.start == option.key.start =>
+    {
+      // :fn p|
+      match annotation.and_then(|a| builtin_function_options(&a.id)) {
+        Some(keys) => {
+          AllowedCompletionType::OptionKey(keys, Some((id.span(), id.name)))
+        }
+        None => AllowedCompletionType::None,
+      }
+    }
     _ => AllowedCompletionType::None,
   }
 }
@@ -179,10 +409,12 @@ fn get_completion_type<'text>(
 #[cfg(test)]
 mod tests {
   use mf2_parser::parse;
+  use mf2_parser::parse_all;
   use mf2_parser::Location;
 
   use super::get_completion_type;
   use super::AllowedCompletionType;
+  use super::CompletionsProvider;
 
   macro_rules! assert_completion_type {
     ($source:expr, $expected:pat) => {
@@ -190,8 +422,8 @@ mod tests {
         $source.find('┋').expect("Cursor not found") as u32,
       );
       let message = $source.replace('┋', "");
-      let (ast, ..) = parse(&message);
-      let result = get_completion_type(&ast, loc);
+      let (ast, diagnostics, _) = parse(&message);
+      let result = get_completion_type(&ast, &diagnostics, loc);
 
       assert!(
         matches!(result, $expected),
@@ -232,5 +464,58 @@ mod tests {
     assert_completion_type!("{ $x :fn param=┋}", AllowedCompletionType::Variable(None));
     assert_completion_type!("{ ┋ :fn }", AllowedCompletionType::Variable(None));
     assert_completion_type!("{ $x┋ :fn }", AllowedCompletionType::Variable(Some((_, "x"))));
+    assert_completion_type!("{:┋}", AllowedCompletionType::Function(None));
+    assert_completion_type!("{ :┋ }", AllowedCompletionType::Function(None));
+    assert_completion_type!("{:num┋}", AllowedCompletionType::Function(Some((_, "num"))));
+    assert_completion_type!("{:┋num}", AllowedCompletionType::Function(Some((_, "num"))));
+    assert_completion_type!("{$x :num┋}", AllowedCompletionType::Function(Some((_, "num"))));
+    assert_completion_type!("{$x :┋}", AllowedCompletionType::Function(None));
+    assert_completion_type!("{:fn p┋}", AllowedCompletionType::None);
+    assert_completion_type!("{$x :number ┋}", AllowedCompletionType::OptionKey(_, None));
+    assert_completion_type!("{$x :number style=percent ┋}", AllowedCompletionType::OptionKey(_, None));
+    assert_completion_type!("{$x :number st┋}", AllowedCompletionType::OptionKey(_, Some((_, "st"))));
+    assert_completion_type!("{$x :number st┋=percent}", AllowedCompletionType::OptionKey(_, Some((_, "st"))));
+    assert_completion_type!("{$x :number style=┋}", AllowedCompletionType::Variable(None));
+    assert_completion_type!("{$x :number style=p┋}", AllowedCompletionType::None);
+    assert_completion_type!("{$x :number style=percent ┋ signDisplay=auto}", AllowedCompletionType::OptionKey(_, None));
+    assert_completion_type!("{$x :number style ┋ signDisplay=auto}", AllowedCompletionType::None);
+    assert_completion_type!("{$x :number style ┋}", AllowedCompletionType::None);
+    assert_completion_type!("{$x :string ┋}", AllowedCompletionType::None);
+    assert_completion_type!("{$x :ns:number ┋}", AllowedCompletionType::None);
+    assert_completion_type!("{#b p┋}", AllowedCompletionType::None);
+    assert_completion_type!("{#┋}", AllowedCompletionType::None);
+    assert_completion_type!("{#b┋}", AllowedCompletionType::None);
+    assert_completion_type!(".┋", AllowedCompletionType::Keyword(_));
+    assert_completion_type!(".i┋", AllowedCompletionType::Keyword(_));
+    assert_completion_type!(".┋i", AllowedCompletionType::Keyword(_));
+    assert_completion_type!(".input {$x}\n.l┋\n{{}}", AllowedCompletionType::Keyword(_));
+    assert_completion_type!(".inp┋ {$x}\n{{}}", AllowedCompletionType::Keyword(_));
+    assert_completion_type!(".foo {$x┋}\n{{}}", AllowedCompletionType::None);
+    assert_completion_type!("┋.i", AllowedCompletionType::None);
+    assert_completion_type!("{|$f┋|}", AllowedCompletionType::None);
+    assert_completion_type!("|$f┋|", AllowedCompletionType::None);
+    assert_completion_type!("hello $┋", AllowedCompletionType::None);
+    assert_completion_type!("{{hello $┋}}", AllowedCompletionType::None);
+  }
+
+  #[test]
+  fn excludes_variables_declared_later() {
+    let source = ".local $a = {1}\n.local $b = {$┋}\n.local $c = {2}\n.input {$d :number}\n{{{$e}}}";
+    let loc = Location::new_for_test(source.find('┋').unwrap() as u32);
+    let message = source.replace('┋', "");
+    let (ast, diagnostics, _, scope) = parse_all(&message);
+    let provider = CompletionsProvider::new(&ast, &diagnostics, loc, &scope);
+
+    let names = provider
+      .get_completions()
+      .into_iter()
+      .map(|completion| completion.text)
+      .collect::<Vec<_>>();
+
+    assert!(names.contains(&"$a".to_string()));
+    assert!(names.contains(&"$e".to_string()));
+    assert!(!names.contains(&"$b".to_string()));
+    assert!(!names.contains(&"$c".to_string()));
+    assert!(!names.contains(&"$d".to_string()));
   }
 }
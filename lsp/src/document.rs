@@ -1,8 +1,11 @@
+use std::rc::Rc;
+
 use lsp_types::Range;
 use lsp_types::Uri;
 use mf2_parser::ast;
 use mf2_parser::ast::Message;
 use mf2_parser::Diagnostic;
+use mf2_parser::FunctionRegistry;
 use mf2_parser::LineColUtf16;
 use mf2_parser::Location;
 use mf2_parser::Scope;
@@ -11,12 +14,14 @@ use mf2_parser::Span;
 use yoke::Yoke;
 use yoke::Yokeable;
 
-use crate::ast_utils::find_node;
+use crate::ast_utils::SpanIndex;
 
 pub struct Document {
   pub uri: Uri,
   pub version: i32,
-  pub parsed: Yoke<ParsedDocument<'static>, Box<str>>,
+  pub parsed: Rc<Yoke<ParsedDocument<'static>, Box<str>>>,
+  span_index:
+    Yoke<SpanIndex<'static>, Rc<Yoke<ParsedDocument<'static>, Box<str>>>>,
 }
 
 #[derive(Yokeable)]
@@ -30,8 +35,23 @@ pub struct ParsedDocument<'text> {
 impl Document {
   pub fn new(uri: Uri, version: i32, text: Box<str>) -> Document {
     let parsed = Yoke::attach_to_cart(text, |text| {
-      let (ast, mut diagnostics, info) = mf2_parser::parse(text);
-      let scope = mf2_parser::analyze_semantics(&ast, &mut diagnostics);
+      let (ast, mut diagnostics, info, scope) = mf2_parser::parse_all(text);
+      let registry = FunctionRegistry::with_builtins();
+      mf2_parser::validate_annotations(&ast, &registry, &mut diagnostics);
+      if let Message::Complex(ast::ComplexMessage {
+        declarations,
+        body: ast::ComplexMessageBody::Matcher(matcher),
+        ..
+      }) = &ast
+      {
+        mf2_parser::validate_matcher(
+          matcher,
+          declarations,
+          &registry,
+          &mut diagnostics,
+        );
+      }
+      mf2_parser::sort_by_span(&mut diagnostics);
 
       ParsedDocument {
         ast,
@@ -40,10 +60,18 @@ impl Document {
         diagnostics,
       }
     });
+    let parsed = Rc::new(parsed);
+    let span_index = Yoke::attach_to_cart(
+      parsed.clone(),
+      |parsed: &Yoke<ParsedDocument<'static>, Box<str>>| {
+        SpanIndex::new(&parsed.get().ast)
+      },
+    );
     Document {
       uri,
       version,
       parsed,
+      span_index,
     }
   }
 
@@ -96,10 +124,55 @@ impl Document {
     &self.parsed.get().diagnostics
   }
 
+  /// Find the innermost node that contains the given location.
+  pub fn node_at(&self, loc: Location) -> Option<ast::AnyNode> {
+    self.span_index.get().node_at(loc)
+  }
+
+  /// Get all nodes that contain the given location, from the innermost node to
+  /// the outermost node.
+  pub fn nodes_at(
+    &self,
+    loc: Location,
+  ) -> impl Iterator<Item = ast::AnyNode<'_, '_>> + '_ {
+    self.span_index.get().nodes_at(loc)
+  }
+
+  /// Get all nodes that are fully contained in the given span, in source text
+  /// order.
+  pub fn nodes_in(
+    &self,
+    span: Span,
+  ) -> impl Iterator<Item = ast::AnyNode<'_, '_>> + '_ {
+    self.span_index.get().nodes_in(span)
+  }
+
   pub fn find_variable_at(&self, loc: Location) -> Option<&str> {
-    match find_node(self.ast(), loc) {
+    match self.node_at(loc) {
       Some(ast::AnyNode::Variable(node)) => Some(node.name),
       _ => None,
     }
   }
+
+  /// Find all references to the variable at the given location, in source text
+  /// order. Each reference is returned together with whether it is the
+  /// declaration of the variable.
+  pub fn find_references_at(&self, loc: Location) -> Option<Vec<(Span, bool)>> {
+    let name = self.find_variable_at(loc)?;
+    let scope = self.scope();
+    let declaration = scope.get_declaration_span(name);
+
+    let mut references = scope
+      .get_spans(name)
+      .into_iter()
+      .flatten()
+      .map(|span| {
+        let is_declaration =
+          declaration.is_some_and(|decl| decl.start == span.start);
+        (*span, is_declaration)
+      })
+      .collect::<Vec<_>>();
+    references.sort_by_key(|(span, _)| span.start);
+    Some(references)
+  }
 }
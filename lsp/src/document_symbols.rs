@@ -0,0 +1,134 @@
+use lsp_types::DocumentSymbol;
+use lsp_types::SymbolKind;
+use mf2_parser::ast;
+use mf2_parser::Span;
+use mf2_parser::Spanned as _;
+use mf2_parser::Visit;
+use mf2_parser::Visitable as _;
+
+use crate::document::Document;
+
+/// Collects the outline of a complex message: a symbol for each declaration,
+/// followed by a symbol for the matcher with a child symbol for each variant.
+pub struct DocumentSymbolVisitor<'a> {
+  pub document: &'a Document,
+  pub symbols: Vec<DocumentSymbol>,
+}
+
+impl DocumentSymbolVisitor<'_> {
+  fn symbol(
+    &self,
+    name: String,
+    detail: Option<String>,
+    kind: SymbolKind,
+    span: Span,
+    selection_span: Span,
+    children: Option<Vec<DocumentSymbol>>,
+  ) -> DocumentSymbol {
+    #[allow(deprecated)]
+    DocumentSymbol {
+      name,
+      detail,
+      kind,
+      tags: None,
+      deprecated: None,
+      range: self.document.span_to_range(span),
+      selection_range: self.document.span_to_range(selection_span),
+      children,
+    }
+  }
+
+  fn declaration(
+    &mut self,
+    keyword: &str,
+    variable: &ast::Variable,
+    span: Span,
+  ) {
+    let symbol = self.symbol(
+      format!("${}", variable.name),
+      Some(keyword.to_string()),
+      SymbolKind::VARIABLE,
+      span,
+      variable.span(),
+      None,
+    );
+    self.symbols.push(symbol);
+  }
+
+  fn variant(&self, variant: &ast::Variant) -> DocumentSymbol {
+    let (name, selection_span) =
+      match (variant.keys.first(), variant.keys.last()) {
+        (Some(first), Some(last)) => {
+          let span = Span::new(first.span().start..last.span().end);
+          let keys = variant
+            .keys
+            .iter()
+            .map(|key| self.document.info().text(key.span()))
+            .collect::<Vec<_>>();
+          (keys.join(" "), span)
+        }
+        // Clients do not accept symbols with empty names.
+        _ => ("(no keys)".to_string(), variant.span()),
+      };
+    self.symbol(
+      name,
+      None,
+      SymbolKind::ENUM_MEMBER,
+      variant.span(),
+      selection_span,
+      None,
+    )
+  }
+}
+
+impl<'ast, 'text> Visit<'ast, 'text> for DocumentSymbolVisitor<'_> {
+  fn visit_input_declaration(
+    &mut self,
+    decl: &'ast ast::InputDeclaration<'text>,
+  ) {
+    self.declaration(".input", &decl.expression.variable, decl.span());
+  }
+
+  fn visit_local_declaration(
+    &mut self,
+    decl: &'ast ast::LocalDeclaration<'text>,
+  ) {
+    self.declaration(".local", &decl.variable, decl.span());
+  }
+
+  fn visit_matcher(&mut self, matcher: &'ast ast::Matcher<'text>) {
+    let selectors = matcher
+      .selectors
+      .iter()
+      .map(|selector| format!("${}", selector.name))
+      .collect::<Vec<_>>();
+    let variants = matcher
+      .variants
+      .iter()
+      .map(|variant| self.variant(variant))
+      .collect();
+    let keyword = Span::new(matcher.start..matcher.start + ".match");
+    let symbol = self.symbol(
+      ".match".to_string(),
+      (!selectors.is_empty()).then(|| selectors.join(" ")),
+      SymbolKind::OBJECT,
+      matcher.span(),
+      keyword,
+      Some(variants),
+    );
+    self.symbols.push(symbol);
+  }
+
+  fn visit_pattern(&mut self, _pattern: &'ast ast::Pattern<'text>) {
+    // Patterns contain no symbols, so there is no need to visit them.
+  }
+}
+
+pub fn document_symbols(document: &Document) -> Vec<DocumentSymbol> {
+  let mut visitor = DocumentSymbolVisitor {
+    document,
+    symbols: vec![],
+  };
+  document.ast().apply_visitor(&mut visitor);
+  visitor.symbols
+}
@@ -0,0 +1,53 @@
+use lsp_types::FoldingRange;
+use mf2_parser::ast;
+use mf2_parser::Span;
+use mf2_parser::Spanned as _;
+use mf2_parser::Visit;
+use mf2_parser::Visitable as _;
+
+use crate::document::Document;
+
+/// Collects a folding range for each matcher and each quoted pattern that spans
+/// multiple lines.
+pub struct FoldingRangeVisitor<'a> {
+  pub document: &'a Document,
+  pub ranges: Vec<FoldingRange>,
+}
+
+impl FoldingRangeVisitor<'_> {
+  fn report_range(&mut self, span: Span) {
+    let info = self.document.info();
+    let start_line = info.utf8_line_col(span.start).line;
+    let end_line = info.utf8_line_col(span.end).line;
+    if end_line > start_line {
+      self.ranges.push(FoldingRange {
+        start_line,
+        start_character: None,
+        end_line,
+        end_character: None,
+        kind: None,
+        collapsed_text: None,
+      });
+    }
+  }
+}
+
+impl<'ast, 'text> Visit<'ast, 'text> for FoldingRangeVisitor<'_> {
+  fn visit_matcher(&mut self, matcher: &'ast ast::Matcher<'text>) {
+    self.report_range(matcher.span());
+    matcher.apply_visitor_to_children(self);
+  }
+
+  fn visit_quoted_pattern(&mut self, pattern: &'ast ast::QuotedPattern<'text>) {
+    self.report_range(pattern.span());
+  }
+}
+
+pub fn folding_ranges(document: &Document) -> Vec<FoldingRange> {
+  let mut visitor = FoldingRangeVisitor {
+    document,
+    ranges: vec![],
+  };
+  document.ast().apply_visitor(&mut visitor);
+  visitor.ranges
+}
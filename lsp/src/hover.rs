@@ -0,0 +1,198 @@
+use mf2_parser::ast;
+use mf2_parser::ast::AnyNode;
+use mf2_parser::ast::AnyNodeKind;
+use mf2_parser::Location;
+use mf2_parser::Span;
+use mf2_parser::Spanned as _;
+use mf2_parser::VariableKind;
+
+use crate::ast_utils::print_expression;
+use crate::document::Document;
+
+/// Describe the innermost node at the given location as Markdown. Returns the
+/// span of the node together with the description.
+pub fn hover(document: &Document, loc: Location) -> Option<(Span, String)> {
+  let mut nodes = document.nodes_at(loc);
+  let node = nodes.next()?;
+  let parent = nodes.next();
+
+  let mut sections = vec![];
+  match &node {
+    AnyNode::Variable(variable) => {
+      sections.extend(describe_variable(document, variable));
+    }
+    AnyNode::Number(number) => {
+      sections.push(match number.as_f64().filter(|value| value.is_finite()) {
+        Some(value) => {
+          format!("Number literal with the value `{}`.", format_number(value))
+        }
+        None => "Number literal.".to_string(),
+      });
+      let span = number.span();
+      sections.extend(
+        document
+          .diagnostics()
+          .iter()
+          .filter(|diag| {
+            diag.span().contains(&span) || span.contains(&diag.span())
+          })
+          .map(|diag| diag.message()),
+      );
+    }
+    AnyNode::Identifier(id) => {
+      let text = document.info().text(id.span());
+      sections.push(match parent.as_ref().map(|parent| parent.kind()) {
+        Some(
+          AnyNodeKind::LiteralExpression
+          | AnyNodeKind::VariableExpression
+          | AnyNodeKind::AnnotationExpression,
+        ) => format!("Function `:{text}`."),
+        Some(AnyNodeKind::FnOrMarkupOption) => format!("Option `{text}`."),
+        Some(AnyNodeKind::Attribute) => format!("Attribute `@{text}`."),
+        Some(AnyNodeKind::Markup) => format!("Markup tag `{text}`."),
+        _ => format!("Identifier `{text}`."),
+      });
+    }
+    AnyNode::Text(text) => {
+      sections.push(match parent.as_ref().map(|parent| parent.kind()) {
+        Some(AnyNodeKind::Pattern) => "Text.".to_string(),
+        Some(AnyNodeKind::Quoted | AnyNodeKind::QuotedPart) => {
+          "Quoted literal.".to_string()
+        }
+        _ => format!("Unquoted literal `{}`.", text.content),
+      });
+    }
+    node => sections.push(format!("{}.", describe_kind(node.kind()))),
+  }
+
+  // Variables already show their declaration, which is more useful than the
+  // expression they are used in.
+  if !matches!(node, AnyNode::Variable(_)) {
+    let expression = std::iter::once(node.clone())
+      .chain(parent)
+      .chain(nodes)
+      .find_map(as_expression);
+    if let Some(expression) = expression {
+      sections.push(code_block(&print_expression(&expression)));
+    }
+  }
+
+  Some((node.span(), sections.join("\n\n")))
+}
+
+fn describe_variable(
+  document: &Document,
+  variable: &ast::Variable,
+) -> Vec<String> {
+  let scope = document.scope();
+  let name = variable.name;
+  match scope.get_kind(name).unwrap_or(VariableKind::External) {
+    VariableKind::Local => {
+      let mut sections =
+        vec![format!("Local variable `${name}`, declared as:")];
+      if let Some(expression) = scope.get_local_expression(name) {
+        sections.push(code_block(&format!(
+          ".local ${name} = {}",
+          print_expression(expression)
+        )));
+      }
+      sections
+    }
+    VariableKind::Input => {
+      let mut sections =
+        vec![format!("Input variable `${name}`, declared as:")];
+      let declaration = scope.get_declaration_span(name).and_then(|span| {
+        document.nodes_at(span.start).find_map(|node| match node {
+          AnyNode::InputDeclaration(declaration) => Some(declaration),
+          _ => None,
+        })
+      });
+      if let Some(declaration) = declaration {
+        let expression =
+          ast::Expression::VariableExpression(declaration.expression.clone());
+        sections.push(code_block(&format!(
+          ".input {}",
+          print_expression(&expression)
+        )));
+      }
+      sections
+    }
+    VariableKind::External => vec![format!(
+      "External variable `${name}`. It is not declared in this message, so \
+       it is an implicit input."
+    )],
+  }
+}
+
+fn as_expression<'text>(
+  node: AnyNode<'_, 'text>,
+) -> Option<ast::Expression<'text>> {
+  match node {
+    AnyNode::LiteralExpression(expression) => {
+      Some(ast::Expression::LiteralExpression(expression.clone()))
+    }
+    AnyNode::VariableExpression(expression) => {
+      Some(ast::Expression::VariableExpression(expression.clone()))
+    }
+    AnyNode::AnnotationExpression(expression) => {
+      Some(ast::Expression::AnnotationExpression(expression.clone()))
+    }
+    AnyNode::Expression(expression) => Some(expression.clone()),
+    _ => None,
+  }
+}
+
+/// Wrap the code in a Markdown code block. The fence is longer than any run of
+/// backticks in the code, so that the code can not end the block early.
+fn code_block(code: &str) -> String {
+  let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+  let fence = "`".repeat(longest_run.max(2) + 1);
+  format!("{fence}mf2\n{code}\n{fence}")
+}
+
+/// Format a number for display. Like in JavaScript, numbers with an absolute
+/// value of at least 1e21 or below 1e-6 are shown in scientific notation, so
+/// that `1e300` is not written out with hundreds of digits.
+fn format_number(value: f64) -> String {
+  let abs = value.abs();
+  if abs >= 1e21 || (abs != 0.0 && abs < 1e-6) {
+    format!("{value:e}")
+  } else {
+    format!("{value}")
+  }
+}
+
+fn describe_kind(kind: AnyNodeKind) -> &'static str {
+  match kind {
+    AnyNodeKind::Message => "Message",
+    AnyNodeKind::Pattern => "Pattern",
+    AnyNodeKind::PatternPart => "Pattern part",
+    AnyNodeKind::Text => "Text",
+    AnyNodeKind::Escape => "Escape sequence",
+    AnyNodeKind::Expression => "Expression",
+    AnyNodeKind::LiteralExpression => "Literal expression",
+    AnyNodeKind::VariableExpression => "Variable expression",
+    AnyNodeKind::Variable => "Variable",
+    AnyNodeKind::AnnotationExpression => "Function expression",
+    AnyNodeKind::Annotation => "Function annotation",
+    AnyNodeKind::FnOrMarkupOption => "Option",
+    AnyNodeKind::Attribute => "Attribute",
+    AnyNodeKind::LiteralOrVariable => "Option value",
+    AnyNodeKind::Quoted => "Quoted literal",
+    AnyNodeKind::QuotedPart => "Quoted literal part",
+    AnyNodeKind::Literal => "Literal",
+    AnyNodeKind::Number => "Number literal",
+    AnyNodeKind::Markup => "Markup",
+    AnyNodeKind::Identifier => "Identifier",
+    AnyNodeKind::ComplexMessage => "Complex message",
+    AnyNodeKind::Declaration => "Declaration",
+    AnyNodeKind::InputDeclaration => "Input declaration",
+    AnyNodeKind::LocalDeclaration => "Local declaration",
+    AnyNodeKind::ComplexMessageBody => "Complex message body",
+    AnyNodeKind::QuotedPattern => "Quoted pattern",
+    AnyNodeKind::Matcher => "Matcher",
+    AnyNodeKind::Variant => "Variant",
+    AnyNodeKind::Key => "Variant key",
+    AnyNodeKind::Star => "Catch-all key",
+  }
+}
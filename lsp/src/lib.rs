@@ -1,9 +1,13 @@
 mod ast_utils;
 mod completions;
 mod document;
+mod document_symbols;
+mod folding_ranges;
+mod hover;
 mod protocol;
 mod semantic_tokens;
 mod server;
+mod signature_help;
 
 pub use crate::protocol::ConnectionManager;
 pub use crate::server::Server;
@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::ops::ControlFlow;
 
 use lsp_server::Connection;
@@ -8,27 +9,41 @@ use lsp_types::notification::DidChangeTextDocument;
 use lsp_types::notification::DidCloseTextDocument;
 use lsp_types::notification::DidOpenTextDocument;
 use lsp_types::notification::Initialized;
+use lsp_types::notification::Progress;
 use lsp_types::notification::PublishDiagnostics;
 use lsp_types::request::CodeActionRequest;
 use lsp_types::request::Completion as CompletionRequest;
+use lsp_types::request::DocumentHighlightRequest;
+use lsp_types::request::DocumentLinkRequest;
+use lsp_types::request::DocumentSymbolRequest;
+use lsp_types::request::ExecuteCommand;
+use lsp_types::request::FoldingRangeRequest;
 use lsp_types::request::Formatting as FormattingRequest;
 use lsp_types::request::GotoDeclaration;
 use lsp_types::request::GotoDefinition;
 use lsp_types::request::HoverRequest;
 use lsp_types::request::Initialize;
+use lsp_types::request::InlayHintRequest;
 use lsp_types::request::PrepareRenameRequest;
+use lsp_types::request::References as ReferencesRequest;
 use lsp_types::request::Rename as RenameRequest;
 use lsp_types::request::SemanticTokensFullRequest;
 use lsp_types::request::SemanticTokensRangeRequest;
+use lsp_types::request::SignatureHelpRequest;
+use lsp_types::request::WorkDoneProgressCreate;
 use yoke::Yokeable;
 
 pub struct LanguageClient<'a> {
   connection: &'a Connection,
+  next_request_id: Cell<i32>,
 }
 
 impl<'a> LanguageClient<'a> {
   pub fn new(connection: &'a Connection) -> Self {
-    Self { connection }
+    Self {
+      connection,
+      next_request_id: Cell::new(1),
+    }
   }
 }
 
@@ -36,9 +51,42 @@ macro_rules! language_client {
   (
     notifications: {
       $($name:ident: $typ:path),* $(,)?
+    } $(,)?
+    requests: {
+      $($request_name:ident: $request_typ:path),* $(,)?
     }
   ) => {
     impl LanguageClient<'_> {
+    $(
+      /// Send the request to the client. The response is not waited for: it
+      /// is only logged if it is an error.
+      pub fn $request_name(
+        &self,
+        params: <$request_typ as lsp_types::request::Request>::Params,
+      ) {
+        let id = self.next_request_id.get();
+        self.next_request_id.set(id + 1);
+        if let Err(err) =
+          self
+            .connection
+            .sender
+            .send(lsp_server::Message::Request(
+              lsp_server::Request {
+                id: id.into(),
+                method: <$request_typ as lsp_types::request::Request>::METHOD
+                  .to_string(),
+                params: serde_json::to_value(params).unwrap(),
+              },
+            ))
+        {
+          eprintln!(
+            "Error sending {} request: {:?}",
+            <$request_typ as lsp_types::request::Request>::METHOD,
+            err
+          );
+        }
+      }
+    )*
     $(
       pub fn $name(
         &self,
@@ -260,7 +308,11 @@ impl<'a, LS: LanguageServer> ConnectionManager<'a, LS> {
         let resp = self.server.handle_request(req);
         self.connection.sender.send(resp.into())?;
       }
-      lsp_server::Message::Response(_) => todo!(),
+      lsp_server::Message::Response(resp) => {
+        if let Some(err) = resp.error {
+          eprintln!("Error response to request {}: {:?}", resp.id, err);
+        }
+      }
     }
     Ok(ControlFlow::Continue(()))
   }
@@ -290,6 +342,8 @@ language_server! {
     hover: HoverRequest,
     go_to_declaration: GotoDeclaration,
     go_to_definition: GotoDefinition,
+    references: ReferencesRequest,
+    document_highlight: DocumentHighlightRequest,
     code_action: CodeActionRequest,
     rename: RenameRequest,
     prepare_rename: PrepareRenameRequest,
@@ -297,11 +351,21 @@ language_server! {
     semantic_tokens_full: SemanticTokensFullRequest,
     semantic_tokens_range: SemanticTokensRangeRequest,
     formatting: FormattingRequest,
+    execute_command: ExecuteCommand,
+    document_link: DocumentLinkRequest,
+    document_symbol: DocumentSymbolRequest,
+    inlay_hint: InlayHintRequest,
+    folding_range: FoldingRangeRequest,
+    signature_help: SignatureHelpRequest,
   }
 }
 
 language_client! {
   notifications: {
     publish_diagnostics: PublishDiagnostics,
+    progress: Progress,
+  },
+  requests: {
+    create_work_done_progress: WorkDoneProgressCreate,
   }
 }
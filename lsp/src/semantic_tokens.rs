@@ -3,6 +3,8 @@ use lsp_types::SemanticToken;
 use lsp_types::SemanticTokenType;
 use lsp_types::SemanticTokensLegend;
 use mf2_parser::ast;
+use mf2_parser::ast::AnyNode;
+use mf2_parser::ast::AnyNodeKind;
 use mf2_parser::Span;
 use mf2_parser::Spanned;
 use mf2_parser::Visit;
@@ -19,18 +21,124 @@ pub fn legend() -> SemanticTokensLegend {
       SemanticTokenType::KEYWORD,
       SemanticTokenType::STRING,
       SemanticTokenType::NUMBER,
+      // Not a standard token type. Plain pattern text has its own type, so
+      // that clients (like spell checkers) can tell prose apart from literals.
+      SemanticTokenType::new("text"),
     ],
     token_modifiers: vec![],
   }
 }
 
+/// A part of the message that tokens are reported for by
+/// [SemanticTokenVisitor::visit_range].
+enum RangeItem<'ast, 'text> {
+  Node(AnyNode<'ast, 'text>),
+  MatcherKeyword(&'ast ast::Matcher<'text>),
+  Keys(&'ast ast::Variant<'text>),
+}
+
+/// Whether tokens of the node are reported by visiting its children, instead
+/// of by visiting the node itself.
+fn is_container(node: &AnyNode) -> bool {
+  matches!(
+    node.kind(),
+    AnyNodeKind::Message
+      | AnyNodeKind::Pattern
+      | AnyNodeKind::ComplexMessage
+      | AnyNodeKind::QuotedPattern
+      | AnyNodeKind::Matcher
+      | AnyNodeKind::Variant
+  )
+}
+
 pub struct SemanticTokenVisitor<'a> {
   pub document: &'a Document,
   pub tokens: Vec<SemanticToken>,
   pub last_start: Position,
 }
 
-impl SemanticTokenVisitor<'_> {
+impl<'a> SemanticTokenVisitor<'a> {
+  /// Report the tokens of all parts of the message that intersect the given
+  /// span. Parts that are only partially in the span, like an expression that
+  /// starts before it, are reported in full.
+  pub fn visit_range(&mut self, span: Span) {
+    let document = self.document;
+    let intersects =
+      |other: Span| other.start < span.end && other.end > span.start;
+
+    // Extend the span to the start and end of the parts it partially covers,
+    // so that they are included in the nodes in the span. Parts that start at
+    // the end of the span are not covered by it.
+    let mut expanded = span;
+    for loc in [span.start, span.end] {
+      let outermost = document
+        .nodes_at(loc)
+        .take_while(|node| !is_container(node))
+        .filter(|node| node.span().start < span.end)
+        .last();
+      if let Some(node) = outermost {
+        expanded = expanded.merge(&node.span());
+      }
+    }
+
+    // The `.match` keyword and the variant keys are not nodes in the span
+    // index, so they are found through the matcher. They are added first, so
+    // that they are visited before the nodes that start at the same location.
+    let mut items = vec![];
+    if let ast::Message::Complex(ast::ComplexMessage {
+      body: ast::ComplexMessageBody::Matcher(matcher),
+      ..
+    }) = document.ast()
+    {
+      let keyword = matcher_keyword_span(matcher);
+      if intersects(keyword) {
+        items.push((keyword, RangeItem::MatcherKeyword(matcher)));
+      }
+      let first = matcher
+        .variants
+        .partition_point(|variant| variant.span().end <= span.start);
+      for variant in matcher.variants[first..]
+        .iter()
+        .take_while(|variant| variant.span().start < span.end)
+      {
+        if let (Some(first), Some(last)) =
+          (variant.keys.first(), variant.keys.last())
+        {
+          let keys = first.span().merge(&last.span());
+          items.push((keys, RangeItem::Keys(variant)));
+        }
+      }
+    }
+    items.extend(
+      document
+        .nodes_in(expanded)
+        .filter(|node| !is_container(node))
+        .map(|node| (node.span(), RangeItem::Node(node))),
+    );
+    items.sort_by_key(|(span, _)| span.start);
+
+    // Visiting a node also visits its children, so nodes inside of the last
+    // visited part are skipped.
+    let mut visited: Option<Span> = None;
+    for (item_span, item) in items {
+      if visited.is_some_and(|visited| visited.contains(&item_span)) {
+        continue;
+      }
+      visited = Some(item_span);
+      match item {
+        RangeItem::Node(node) => node.apply_visitor(self),
+        RangeItem::MatcherKeyword(matcher) => {
+          self.report_token(matcher_keyword_span(matcher), 3 /* keyword */)
+        }
+        RangeItem::Keys(variant) => {
+          for key in &variant.keys {
+            key.apply_visitor(self);
+          }
+        }
+      }
+    }
+  }
+
   fn report_token(&mut self, span: Span, token_type: u32) {
     let mut start = self.document.loc_to_pos(span.start);
     let end = self.document.loc_to_pos(span.end);
@@ -57,9 +165,12 @@ impl SemanticTokenVisitor<'_> {
         token_modifiers_bitset: 0,
       };
 
-      self.tokens.push(token);
-
-      self.last_start = start;
+      // A span that ends with a line break would otherwise produce an empty
+      // token at the start of the next line.
+      if token.length > 0 {
+        self.tokens.push(token);
+        self.last_start = start;
+      }
 
       start.line += 1;
       start.character = 0;
@@ -78,6 +189,12 @@ impl<'ast, 'text> Visit<'ast, 'text> for SemanticTokenVisitor<'ast> {
     var.apply_visitor_to_children(self);
   }
 
+  fn visit_text(&mut self, text: &'ast ast::Text<'text>) {
+    if !text.content.is_empty() {
+      self.report_token(text.span(), 6 /* text */);
+    }
+  }
+
   fn visit_literal(&mut self, literal: &'ast ast::Literal<'text>) {
     match literal {
       ast::Literal::Text(s) => {
@@ -93,10 +210,7 @@ impl<'ast, 'text> Visit<'ast, 'text> for SemanticTokenVisitor<'ast> {
   }
 
   fn visit_matcher(&mut self, matcher: &'ast ast::Matcher<'text>) {
-    self.report_token(
-      Span::new(matcher.start..matcher.start + ".match"),
-      3, /* keyword */
-    );
+    self.report_token(matcher_keyword_span(matcher), 3 /* keyword */);
     matcher.apply_visitor_to_children(self);
   }
 
@@ -108,3 +222,102 @@ impl<'ast, 'text> Visit<'ast, 'text> for SemanticTokenVisitor<'ast> {
     opt.apply_visitor_to_children(self);
   }
 }
+
+fn matcher_keyword_span(matcher: &ast::Matcher) -> Span {
+  Span::new(matcher.start..matcher.start + ".match")
+}
+
+#[cfg(test)]
+mod tests {
+  use lsp_types::Position;
+  use lsp_types::Range;
+  use lsp_types::SemanticToken;
+  use mf2_parser::Spanned as _;
+  use mf2_parser::Visitable as _;
+
+  use super::SemanticTokenVisitor;
+  use crate::document::Document;
+
+  /// Convert the relative positions of the tokens to (line, character, length,
+  /// type) tuples.
+  fn absolute(tokens: &[SemanticToken]) -> Vec<(u32, u32, u32, u32)> {
+    let mut pos = Position::new(0, 0);
+    tokens
+      .iter()
+      .map(|token| {
+        if token.delta_line > 0 {
+          pos = Position::new(pos.line + token.delta_line, token.delta_start);
+        } else {
+          pos.character += token.delta_start;
+        }
+        (pos.line, pos.character, token.length, token.token_type)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn range_tokens_match_full_tokens() {
+    let text = ".input {$a :number}\n.local $b = {|x| @y}\n.match $a $b\n1 |q| {{One {$a :x c=1} {#b}}}\n* * {{é {$b}}}";
+    let document =
+      Document::new("file:///a.mf2".parse().unwrap(), 1, text.into());
+    let tokens = |range: Option<Range>| {
+      let mut visitor = SemanticTokenVisitor {
+        document: &document,
+        tokens: Vec::new(),
+        last_start: Position::new(0, 0),
+      };
+      match range {
+        Some(range) => visitor.visit_range(document.range_to_span(range)),
+        None => document.ast().apply_visitor(&mut visitor),
+      }
+      absolute(&visitor.tokens)
+    };
+    let full = tokens(None);
+
+    let positions = text
+      .char_indices()
+      .map(|(i, _)| i)
+      .chain([text.len()])
+      .map(|i| document.loc_to_pos(document.ast().span().start + &text[..i]))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      tokens(Some(Range::new(
+        positions[0],
+        positions[positions.len() - 1]
+      ))),
+      full
+    );
+
+    let first_line = Range::new(Position::new(0, 0), Position::new(1, 0));
+    let first_line_tokens = full.iter().filter(|token| token.0 == 0);
+    assert_eq!(
+      tokens(Some(first_line)),
+      first_line_tokens.cloned().collect::<Vec<_>>()
+    );
+
+    for (i, &start) in positions.iter().enumerate() {
+      for &end in &positions[i..] {
+        let range = tokens(Some(Range::new(start, end)));
+        for token in &full {
+          let (line, character, length, _) = *token;
+          let token_start = Position::new(line, character);
+          let token_end = Position::new(line, character + length);
+          if token_start < end && token_end > start {
+            assert!(
+              range.contains(token),
+              "{token:?} missing in {start:?}..{end:?}"
+            );
+          }
+        }
+        assert!(
+          range.windows(2).all(|w| w[0] < w[1]),
+          "unsorted in {start:?}..{end:?}"
+        );
+        assert!(
+          range.iter().all(|token| full.contains(token)),
+          "extra token in {start:?}..{end:?}"
+        );
+      }
+    }
+  }
+}
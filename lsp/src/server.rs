@@ -3,6 +3,11 @@ use lsp_types::DidChangeTextDocumentParams;
 use lsp_types::DidCloseTextDocumentParams;
 use lsp_types::DidOpenTextDocumentParams;
 use lsp_types::DocumentFormattingParams;
+use lsp_types::DocumentLink;
+use lsp_types::DocumentLinkParams;
+use lsp_types::DocumentSymbolParams;
+use lsp_types::DocumentSymbolResponse;
+use lsp_types::ExecuteCommandParams;
 use lsp_types::InitializeParams;
 use lsp_types::InitializeResult;
 use lsp_types::InitializedParams;
@@ -15,31 +20,58 @@ use lsp_types::SemanticTokensRangeResult;
 use lsp_types::SemanticTokensResult;
 use lsp_types::ServerCapabilities;
 use lsp_types::ServerInfo;
+use lsp_types::TextDocumentContentChangeEvent;
 use lsp_types::TextDocumentSyncCapability;
 use lsp_types::TextDocumentSyncKind;
 use lsp_types::TextEdit;
 use lsp_types::Uri;
+use mf2_parser::ast;
 use mf2_parser::ast::AnyNode;
 use mf2_parser::is_valid_name;
+use mf2_parser::LineColUtf16;
+use mf2_parser::Location;
+use mf2_parser::Severity;
+use mf2_parser::SourceTextInfo;
+use mf2_parser::Span;
 use mf2_parser::Spanned as _;
+use mf2_parser::VariableKind;
 use mf2_parser::Visitable;
 
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
-use crate::ast_utils::find_node;
 use crate::completions::CompletionAction;
+use crate::completions::CompletionKind;
 use crate::completions::CompletionsProvider;
 use crate::document::Document;
+use crate::document_symbols::document_symbols;
+use crate::folding_ranges::folding_ranges;
+use crate::hover::hover;
 use crate::protocol::LanguageClient;
 use crate::protocol::LanguageServer;
 use crate::semantic_tokens;
 use crate::semantic_tokens::SemanticTokenVisitor;
+use crate::signature_help::signature_help;
+
+/// Command that applies the fixes of all diagnostics in a document that have
+/// exactly one fix. The only argument is the URI of the document.
+const FIX_ALL_COMMAND: &str = "mf2.fixAll";
+
+/// When a document larger than this many bytes is opened, work done progress
+/// is reported to the client while the document is analyzed.
+const PROGRESS_THRESHOLD_BYTES: usize = 64 * 1024;
 
 pub struct Server<'a> {
   client: LanguageClient<'a>,
   initialize_params: Option<InitializeParams>,
   documents: HashMap<Uri, Document>,
+  /// Whether diagnostics are pushed to the client when a document changes.
+  /// Can be disabled with the `publishDiagnostics` initialization option.
+  publish_diagnostics: bool,
+  /// Documents (or requested ranges) larger than this many bytes get no
+  /// semantic tokens, so that the client falls back to its own highlighting.
+  /// Can be set with the `semanticTokensMaxBytes` initialization option.
+  semantic_tokens_max_bytes: Option<usize>,
 }
 
 impl Server<'_> {
@@ -58,11 +90,57 @@ impl Server<'_> {
       client: LanguageClient::new(connection),
       initialize_params: None,
       documents: HashMap::new(),
+      publish_diagnostics: true,
+      semantic_tokens_max_bytes: None,
     }
   }
 
-  fn on_document_change(&mut self, uri: Uri, version: i32, text: String) {
+  fn supports_work_done_progress(&self) -> bool {
+    self
+      .initialize_params
+      .as_ref()
+      .and_then(|params| params.capabilities.window.as_ref())
+      .and_then(|window| window.work_done_progress)
+      .unwrap_or(false)
+  }
+
+  fn on_document_change(
+    &mut self,
+    uri: Uri,
+    version: i32,
+    text: String,
+    is_open: bool,
+  ) {
+    // Only the initial analysis of a large document is reported, as changes
+    // are analyzed while the user is typing, where progress would be noise.
+    let progress_token = (is_open
+      && text.len() > PROGRESS_THRESHOLD_BYTES
+      && self.supports_work_done_progress())
+    .then(|| {
+      lsp_types::ProgressToken::String(format!(
+        "mf2lsp/analyze/{}/{}",
+        uri.as_str(),
+        version
+      ))
+    });
+    if let Some(token) = &progress_token {
+      self.client.create_work_done_progress(
+        lsp_types::WorkDoneProgressCreateParams {
+          token: token.clone(),
+        },
+      );
+      self.client.progress(work_done_progress(
+        token,
+        lsp_types::WorkDoneProgress::Begin(lsp_types::WorkDoneProgressBegin {
+          title: "Analyzing message…".to_string(),
+          message: Some("Parsing".to_string()),
+          ..Default::default()
+        }),
+      ));
+    }
+
     let document = Document::new(uri.clone(), version, text.into_boxed_str());
+
     let entry = self.documents.entry(uri.clone());
     let document = match entry {
       Entry::Occupied(mut entry) => {
@@ -73,18 +151,49 @@ impl Server<'_> {
       Entry::Vacant(entry) => entry.insert(document),
     };
 
-    let parsed = document.parsed.get();
+    if self.publish_diagnostics {
+      if let Some(token) = &progress_token {
+        self.client.progress(work_done_progress(
+          token,
+          lsp_types::WorkDoneProgress::Report(
+            lsp_types::WorkDoneProgressReport {
+              message: Some("Publishing diagnostics".to_string()),
+              ..Default::default()
+            },
+          ),
+        ));
+      }
 
-    let diagnostics = &parsed.diagnostics;
+      let diagnostics = &document.parsed.get().diagnostics;
 
-    self.client.publish_diagnostics(PublishDiagnosticsParams {
-      uri,
-      version: Some(document.version),
-      diagnostics: diagnostics
-        .iter()
-        .map(|diag| diagnostic_to_lsp(diag, document))
-        .collect(),
-    });
+      self.client.publish_diagnostics(PublishDiagnosticsParams {
+        uri,
+        version: Some(document.version),
+        diagnostics: diagnostics
+          .iter()
+          .map(|diag| diagnostic_to_lsp(diag, document))
+          .collect(),
+      });
+    }
+
+    if let Some(token) = &progress_token {
+      self.client.progress(work_done_progress(
+        token,
+        lsp_types::WorkDoneProgress::End(
+          lsp_types::WorkDoneProgressEnd::default(),
+        ),
+      ));
+    }
+  }
+}
+
+fn work_done_progress(
+  token: &lsp_types::ProgressToken,
+  progress: lsp_types::WorkDoneProgress,
+) -> lsp_types::ProgressParams {
+  lsp_types::ProgressParams {
+    token: token.clone(),
+    value: lsp_types::ProgressParamsValue::WorkDone(progress),
   }
 }
 
@@ -93,17 +202,36 @@ impl LanguageServer for Server<'_> {
     &mut self,
     params: InitializeParams,
   ) -> Result<InitializeResult, anyhow::Error> {
+    if let Some(publish_diagnostics) = params
+      .initialization_options
+      .as_ref()
+      .and_then(|options| options.get("publishDiagnostics"))
+      .and_then(|value| value.as_bool())
+    {
+      self.publish_diagnostics = publish_diagnostics;
+    }
+    if let Some(max_bytes) = params
+      .initialization_options
+      .as_ref()
+      .and_then(|options| options.get("semanticTokensMaxBytes"))
+      .and_then(|value| value.as_u64())
+    {
+      self.semantic_tokens_max_bytes = Some(max_bytes as usize);
+    }
     self.initialize_params = Some(params);
 
     let capabilities = ServerCapabilities {
       text_document_sync: Some(TextDocumentSyncCapability::Kind(
-        TextDocumentSyncKind::FULL,
+        TextDocumentSyncKind::INCREMENTAL,
       )),
       hover_provider: Some(lsp_types::HoverProviderCapability::Simple(true)),
       code_action_provider: Some(
         lsp_types::CodeActionProviderCapability::Options(
           lsp_types::CodeActionOptions {
-            code_action_kinds: Some(vec![lsp_types::CodeActionKind::QUICKFIX]),
+            code_action_kinds: Some(vec![
+              lsp_types::CodeActionKind::QUICKFIX,
+              lsp_types::CodeActionKind::REFACTOR,
+            ]),
             ..lsp_types::CodeActionOptions::default()
           },
         ),
@@ -124,11 +252,17 @@ impl LanguageServer for Server<'_> {
             lsp_types::WorkDoneProgressOptions::default(),
         },
       )),
+      references_provider: Some(lsp_types::OneOf::Left(true)),
+      document_highlight_provider: Some(lsp_types::OneOf::Left(true)),
       completion_provider: Some(lsp_types::CompletionOptions {
         all_commit_characters: None,
         completion_item: None,
         resolve_provider: Some(false),
-        trigger_characters: Some(vec!["$".to_string()]),
+        trigger_characters: Some(vec![
+          "$".to_string(),
+          ":".to_string(),
+          ".".to_string(),
+        ]),
         work_done_progress_options: lsp_types::WorkDoneProgressOptions::default(
         ),
       }),
@@ -143,6 +277,27 @@ impl LanguageServer for Server<'_> {
         ),
       ),
       document_formatting_provider: Some(lsp_types::OneOf::Left(true)),
+      execute_command_provider: Some(lsp_types::ExecuteCommandOptions {
+        commands: vec![FIX_ALL_COMMAND.to_string()],
+        work_done_progress_options: lsp_types::WorkDoneProgressOptions::default(
+        ),
+      }),
+      document_link_provider: Some(lsp_types::DocumentLinkOptions {
+        resolve_provider: Some(false),
+        work_done_progress_options: lsp_types::WorkDoneProgressOptions::default(
+        ),
+      }),
+      document_symbol_provider: Some(lsp_types::OneOf::Left(true)),
+      inlay_hint_provider: Some(lsp_types::OneOf::Left(true)),
+      signature_help_provider: Some(lsp_types::SignatureHelpOptions {
+        trigger_characters: Some(vec![" ".to_string()]),
+        retrigger_characters: None,
+        work_done_progress_options: lsp_types::WorkDoneProgressOptions::default(
+        ),
+      }),
+      folding_range_provider: Some(
+        lsp_types::FoldingRangeProviderCapability::Simple(true),
+      ),
       ..ServerCapabilities::default()
     };
 
@@ -175,19 +330,18 @@ impl LanguageServer for Server<'_> {
       params.text_document.uri.clone(),
       params.text_document.version,
       params.text_document.text,
+      true,
     );
   }
 
-  fn on_change_text_document(
-    &mut self,
-    mut params: DidChangeTextDocumentParams,
-  ) {
-    assert_eq!(params.content_changes.len(), 1);
-    self.on_document_change(
-      params.text_document.uri.clone(),
-      params.text_document.version,
-      params.content_changes.remove(0).text,
+  fn on_change_text_document(&mut self, params: DidChangeTextDocumentParams) {
+    let uri = params.text_document.uri;
+    let document = self.documents.get(&uri);
+    let text = apply_content_changes(
+      document.map(|document| document.info()),
+      params.content_changes,
     );
+    self.on_document_change(uri, params.text_document.version, text, false);
   }
 
   fn on_close_text_document(&mut self, params: DidCloseTextDocumentParams) {
@@ -205,8 +359,8 @@ impl LanguageServer for Server<'_> {
       return Ok(None);
     };
 
-    let Some(node) = find_node(
-      document.ast(),
+    let Some((span, value)) = hover(
+      document,
       document.pos_to_loc(params.text_document_position_params.position),
     ) else {
       return Ok(None);
@@ -214,10 +368,10 @@ impl LanguageServer for Server<'_> {
 
     Ok(Some(lsp_types::Hover {
       contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
-        kind: lsp_types::MarkupKind::PlainText,
-        value: format!("{:?}", node),
+        kind: lsp_types::MarkupKind::Markdown,
+        value,
       }),
-      range: Some(document.span_to_range(node.span())),
+      range: Some(document.span_to_range(span)),
     }))
   }
 
@@ -255,6 +409,75 @@ impl LanguageServer for Server<'_> {
     self.go_to_declaration(params)
   }
 
+  fn references(
+    &mut self,
+    params: lsp_types::ReferenceParams,
+  ) -> Result<Option<Vec<lsp_types::Location>>, anyhow::Error> {
+    let lsp_types::TextDocumentPositionParams {
+      text_document,
+      position,
+    } = params.text_document_position;
+
+    let document = self
+      .documents
+      .get(&text_document.uri)
+      .ok_or(anyhow::anyhow!("Document not found."))?;
+
+    let Some(references) =
+      document.find_references_at(document.pos_to_loc(position))
+    else {
+      return Ok(None);
+    };
+
+    Ok(Some(
+      references
+        .into_iter()
+        .filter(|(_, is_declaration)| {
+          params.context.include_declaration || !is_declaration
+        })
+        .map(|(span, _)| lsp_types::Location {
+          uri: text_document.uri.clone(),
+          range: document.span_to_range(span),
+        })
+        .collect(),
+    ))
+  }
+
+  fn document_highlight(
+    &mut self,
+    params: lsp_types::DocumentHighlightParams,
+  ) -> Result<Option<Vec<lsp_types::DocumentHighlight>>, anyhow::Error> {
+    let lsp_types::TextDocumentPositionParams {
+      text_document,
+      position,
+    } = params.text_document_position_params;
+
+    let document = self
+      .documents
+      .get(&text_document.uri)
+      .ok_or(anyhow::anyhow!("Document not found."))?;
+
+    let Some(references) =
+      document.find_references_at(document.pos_to_loc(position))
+    else {
+      return Ok(None);
+    };
+
+    Ok(Some(
+      references
+        .into_iter()
+        .map(|(span, is_declaration)| lsp_types::DocumentHighlight {
+          range: document.span_to_range(span),
+          kind: Some(if is_declaration {
+            lsp_types::DocumentHighlightKind::WRITE
+          } else {
+            lsp_types::DocumentHighlightKind::READ
+          }),
+        })
+        .collect(),
+    ))
+  }
+
   fn code_action(
     &mut self,
     params: lsp_types::CodeActionParams,
@@ -266,7 +489,7 @@ impl LanguageServer for Server<'_> {
 
     let span = document.range_to_span(params.range);
 
-    let diagnostics = document
+    let mut actions = document
       .parsed
       .get()
       .diagnostics
@@ -275,7 +498,10 @@ impl LanguageServer for Server<'_> {
       .flat_map(|d| fixes_for_diagnostic(d, document))
       .collect::<Vec<_>>();
 
-    Ok(Some(diagnostics))
+    actions.extend(add_selector_action(document, span));
+    actions.extend(remove_attributes_action(document, span));
+
+    Ok(Some(actions))
   }
 
   fn rename(
@@ -334,7 +560,7 @@ impl LanguageServer for Server<'_> {
     };
 
     let Some(AnyNode::Variable(node)) =
-      find_node(document.ast(), document.pos_to_loc(params.position))
+      document.node_at(document.pos_to_loc(params.position))
     else {
       return Ok(None);
     };
@@ -360,6 +586,7 @@ impl LanguageServer for Server<'_> {
 
     let provider = CompletionsProvider::new(
       document.ast(),
+      document.diagnostics(),
       document.pos_to_loc(position),
       document.scope(),
     );
@@ -372,23 +599,46 @@ impl LanguageServer for Server<'_> {
       provider
         .get_completions()
         .into_iter()
-        .map(|completion| match completion.action {
-          CompletionAction::Insert => lsp_types::CompletionItem {
-            label: completion.text,
-            kind: Some(lsp_types::CompletionItemKind::VARIABLE),
-            ..lsp_types::CompletionItem::default()
-          },
-          CompletionAction::Replace(span) => lsp_types::CompletionItem {
-            label: completion.text.clone(),
-            kind: Some(lsp_types::CompletionItemKind::VARIABLE),
-            text_edit: Some(lsp_types::CompletionTextEdit::Edit(
-              lsp_types::TextEdit {
+        .map(|completion| {
+          let (kind, detail) = match completion.kind {
+            CompletionKind::Variable(VariableKind::Input) => {
+              (lsp_types::CompletionItemKind::FIELD, "input")
+            }
+            CompletionKind::Variable(VariableKind::Local) => {
+              (lsp_types::CompletionItemKind::VARIABLE, "local")
+            }
+            CompletionKind::Variable(VariableKind::External) => {
+              (lsp_types::CompletionItemKind::FIELD, "external")
+            }
+            CompletionKind::Function => {
+              (lsp_types::CompletionItemKind::FUNCTION, "function")
+            }
+            CompletionKind::OptionKey => {
+              (lsp_types::CompletionItemKind::PROPERTY, "option")
+            }
+            CompletionKind::Keyword => {
+              (lsp_types::CompletionItemKind::KEYWORD, "keyword")
+            }
+          };
+          let text_edit = match completion.action {
+            CompletionAction::Insert => None,
+            CompletionAction::Replace(span) => {
+              Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
                 range: document.span_to_range(span),
-                new_text: completion.text,
-              },
-            )),
+                new_text: completion.text.clone(),
+              }))
+            }
+          };
+          lsp_types::CompletionItem {
+            label: completion.text,
+            kind: Some(kind),
+            detail: Some(detail.to_string()),
+            documentation: completion
+              .expression
+              .map(lsp_types::Documentation::String),
+            text_edit,
             ..lsp_types::CompletionItem::default()
-          },
+          }
         })
         .collect(),
     )))
@@ -403,6 +653,13 @@ impl LanguageServer for Server<'_> {
       return Ok(None);
     };
 
+    if self
+      .semantic_tokens_max_bytes
+      .is_some_and(|max_bytes| document.info().full_text().len() > max_bytes)
+    {
+      return Ok(None);
+    }
+
     let mut visitor = SemanticTokenVisitor {
       document,
       tokens: Vec::new(),
@@ -428,7 +685,14 @@ impl LanguageServer for Server<'_> {
       return Ok(None);
     };
 
-    // TODO: only compute tokens for the range
+    // Only the requested range is limited, so that clients can still get the
+    // tokens of the visible part of a large document.
+    let span = document.range_to_span(params.range);
+    if self.semantic_tokens_max_bytes.is_some_and(|max_bytes| {
+      document.info().utf8_len(span) as usize > max_bytes
+    }) {
+      return Ok(None);
+    }
 
     let mut visitor = SemanticTokenVisitor {
       document,
@@ -438,7 +702,7 @@ impl LanguageServer for Server<'_> {
         character: 0,
       },
     };
-    document.parsed.get().ast.apply_visitor(&mut visitor);
+    visitor.visit_range(span);
 
     Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
       result_id: None,
@@ -462,12 +726,227 @@ impl LanguageServer for Server<'_> {
     }
 
     let formatted = mf2_printer::print(document.ast(), Some(document.info()));
+    if formatted == document.info().full_text() {
+      return Ok(Some(vec![]));
+    }
 
+    // The whole document is replaced, including any whitespace around the
+    // message, because the printer normalizes it too.
     Ok(Some(vec![lsp_types::TextEdit {
-      range: document.span_to_range(document.ast().span()),
+      range: document.span_to_range(document.info().span()),
       new_text: formatted,
     }]))
   }
+
+  fn execute_command(
+    &mut self,
+    params: ExecuteCommandParams,
+  ) -> Result<Option<serde_json::Value>, anyhow::Error> {
+    match params.command.as_str() {
+      FIX_ALL_COMMAND => {
+        let uri: Uri = params
+          .arguments
+          .into_iter()
+          .next()
+          .map(serde_json::from_value)
+          .transpose()?
+          .ok_or(anyhow::anyhow!("Missing document URI argument."))?;
+
+        let document = self
+          .documents
+          .get(&uri)
+          .ok_or(anyhow::anyhow!("Document not found."))?;
+
+        let edit = lsp_types::WorkspaceEdit {
+          changes: Some([(uri, fix_all_edits(document))].into()),
+          document_changes: None,
+          change_annotations: None,
+        };
+
+        Ok(Some(serde_json::to_value(edit)?))
+      }
+      command => Err(anyhow::anyhow!("Unknown command: {command}")),
+    }
+  }
+
+  fn document_link(
+    &mut self,
+    params: DocumentLinkParams,
+  ) -> Result<Option<Vec<DocumentLink>>, anyhow::Error> {
+    let uri = params.text_document.uri;
+    let maybe_document = self.documents.get(&uri);
+    let Some(document) = maybe_document else {
+      return Ok(None);
+    };
+
+    // Links point to a line and column in the same document, using the
+    // `#L<line>,<column>` fragment (1-based) that editors understand.
+    let base = uri.as_str().split('#').next().unwrap_or_default();
+
+    let scope = document.scope();
+    let mut links = vec![];
+    for name in scope.get_names() {
+      let Some(declaration) = scope.get_declaration_span(name) else {
+        continue;
+      };
+      let start = document.loc_to_pos(declaration.start);
+      let target: Uri =
+        format!("{base}#L{},{}", start.line + 1, start.character + 1)
+          .parse()?;
+
+      for span in scope.get_spans(name).into_iter().flatten() {
+        if span.start == declaration.start {
+          continue;
+        }
+        links.push(DocumentLink {
+          range: document.span_to_range(*span),
+          target: Some(target.clone()),
+          tooltip: Some(format!("Go to declaration of ${name}")),
+          data: None,
+        });
+      }
+    }
+    links
+      .sort_by_key(|link| (link.range.start.line, link.range.start.character));
+
+    Ok(Some(links))
+  }
+
+  fn document_symbol(
+    &mut self,
+    params: DocumentSymbolParams,
+  ) -> Result<Option<DocumentSymbolResponse>, anyhow::Error> {
+    let maybe_document = self.documents.get(&params.text_document.uri);
+    let Some(document) = maybe_document else {
+      return Ok(None);
+    };
+
+    Ok(Some(DocumentSymbolResponse::Nested(document_symbols(
+      document,
+    ))))
+  }
+
+  fn inlay_hint(
+    &mut self,
+    params: lsp_types::InlayHintParams,
+  ) -> Result<Option<Vec<lsp_types::InlayHint>>, anyhow::Error> {
+    let maybe_document = self.documents.get(&params.text_document.uri);
+    let Some(document) = maybe_document else {
+      return Ok(None);
+    };
+
+    let ast::Message::Complex(ast::ComplexMessage {
+      body: ast::ComplexMessageBody::Matcher(matcher),
+      ..
+    }) = document.ast()
+    else {
+      return Ok(Some(vec![]));
+    };
+
+    // Each key is labeled with the selector it is matched against. Variants
+    // with the wrong number of keys already have a diagnostic, and labeling
+    // their keys would suggest an alignment that does not exist.
+    let range = document.range_to_span(params.range);
+    let hints = matcher
+      .variants
+      .iter()
+      .filter(|variant| variant.keys.len() == matcher.selectors.len())
+      .flat_map(|variant| variant.keys.iter().zip(&matcher.selectors))
+      .filter(|(key, _)| {
+        let start = key.span().start;
+        range.start <= start && start <= range.end
+      })
+      .map(|(key, selector)| lsp_types::InlayHint {
+        position: document.loc_to_pos(key.span().start),
+        label: lsp_types::InlayHintLabel::String(format!(
+          "${}:",
+          selector.name
+        )),
+        kind: Some(lsp_types::InlayHintKind::PARAMETER),
+        text_edits: None,
+        tooltip: None,
+        padding_left: None,
+        padding_right: Some(true),
+        data: None,
+      })
+      .collect();
+
+    Ok(Some(hints))
+  }
+
+  fn folding_range(
+    &mut self,
+    params: lsp_types::FoldingRangeParams,
+  ) -> Result<Option<Vec<lsp_types::FoldingRange>>, anyhow::Error> {
+    let maybe_document = self.documents.get(&params.text_document.uri);
+    let Some(document) = maybe_document else {
+      return Ok(None);
+    };
+
+    Ok(Some(folding_ranges(document)))
+  }
+
+  fn signature_help(
+    &mut self,
+    params: lsp_types::SignatureHelpParams,
+  ) -> Result<Option<lsp_types::SignatureHelp>, anyhow::Error> {
+    let lsp_types::TextDocumentPositionParams {
+      text_document,
+      position,
+    } = params.text_document_position_params;
+
+    let maybe_document = self.documents.get(&text_document.uri);
+    let Some(document) = maybe_document else {
+      return Ok(None);
+    };
+
+    Ok(signature_help(document, document.pos_to_loc(position)))
+  }
+}
+
+/// Apply the changes of a `didChange` notification in order to the text of a
+/// document, described by its source text info. Ranged changes are relative to
+/// the text after all previous changes were applied, and changes without a
+/// range replace the whole text.
+fn apply_content_changes(
+  info: Option<&SourceTextInfo>,
+  changes: Vec<TextDocumentContentChangeEvent>,
+) -> String {
+  let mut text = info.map(|info| info.full_text()).unwrap_or("").to_string();
+  // The info of the original text can be reused until the first change is
+  // applied, after which it is recomputed for each ranged change.
+  let mut changed = false;
+  for change in changes {
+    let Some(range) = change.range else {
+      text = change.text;
+      changed = true;
+      continue;
+    };
+
+    let byte_range = {
+      let updated_info;
+      let info = match info {
+        Some(info) if !changed => info,
+        _ => {
+          updated_info = SourceTextInfo::new(&text);
+          &updated_info
+        }
+      };
+      let offset = |position: lsp_types::Position| {
+        let loc = info.utf16_loc(LineColUtf16 {
+          line: position.line,
+          col: position.character,
+        });
+        info.text(Span::new(info.span().start..loc)).len()
+      };
+      let (start, end) = (offset(range.start), offset(range.end));
+      start.min(end)..end.max(start)
+    };
+
+    text.replace_range(byte_range, &change.text);
+    changed = true;
+  }
+  text
 }
 
 fn diagnostic_to_lsp(
@@ -476,13 +955,31 @@ fn diagnostic_to_lsp(
 ) -> lsp_types::Diagnostic {
   lsp_types::Diagnostic {
     range: doc.span_to_range(diag.span()),
-    severity: Some(lsp_types::DiagnosticSeverity::ERROR),
-    code: None,
+    severity: Some(match diag.severity() {
+      Severity::Error => lsp_types::DiagnosticSeverity::ERROR,
+      Severity::Warning => lsp_types::DiagnosticSeverity::WARNING,
+      Severity::Info => lsp_types::DiagnosticSeverity::INFORMATION,
+    }),
+    code: Some(lsp_types::NumberOrString::String(diag.code().to_string())),
     code_description: None,
     source: Some("mf2".to_string()),
     message: diag.message(),
-    related_information: None,
-    tags: None,
+    related_information: Some(
+      diag
+        .related_spans()
+        .into_iter()
+        .map(|(span, label)| lsp_types::DiagnosticRelatedInformation {
+          location: lsp_types::Location {
+            uri: doc.uri.clone(),
+            range: doc.span_to_range(span),
+          },
+          message: label,
+        })
+        .collect::<Vec<_>>(),
+    )
+    .filter(|related| !related.is_empty()),
+    tags: matches!(diag, mf2_parser::Diagnostic::UnusedDeclaration { .. })
+      .then(|| vec![lsp_types::DiagnosticTag::UNNECESSARY]),
     data: None,
   }
 }
@@ -526,3 +1023,177 @@ fn fixes_for_diagnostic(
     })
     .collect()
 }
+
+/// A refactor that adds a selector to the matcher containing the span, and a
+/// `*` key for the new selector to every variant, so that all variants keep
+/// having as many keys as there are selectors.
+///
+/// The new selector is the first declared variable that is not a selector yet.
+/// The action is not offered if every declared variable is already a selector,
+/// as a placeholder variable would not be declared.
+fn add_selector_action(
+  document: &Document,
+  span: Span,
+) -> Option<lsp_types::CodeActionOrCommand> {
+  let ast::Message::Complex(complex) = document.ast() else {
+    return None;
+  };
+  let ast::ComplexMessageBody::Matcher(matcher) = &complex.body else {
+    return None;
+  };
+  if !matcher.span().contains(&span) {
+    return None;
+  }
+
+  let name = complex
+    .declarations
+    .iter()
+    .map(|decl| match decl {
+      ast::Declaration::InputDeclaration(decl) => decl.expression.variable.name,
+      ast::Declaration::LocalDeclaration(decl) => decl.variable.name,
+    })
+    .find(|name| !matcher.selectors.iter().any(|sel| sel.name == *name))?;
+
+  let insert = |loc: Location, new_text: String| lsp_types::TextEdit {
+    range: document.span_to_range(Span::new(loc..loc)),
+    new_text,
+  };
+
+  let mut edits = vec![insert(
+    matcher.selectors_end_location(),
+    format!(" ${name}"),
+  )];
+  for variant in &matcher.variants {
+    let keys = variant.keys_region();
+    edits.push(if variant.keys.is_empty() {
+      insert(keys.start, "* ".to_owned())
+    } else {
+      insert(keys.end, " *".to_owned())
+    });
+  }
+
+  Some(lsp_types::CodeActionOrCommand::CodeAction(
+    lsp_types::CodeAction {
+      title: "Add selector".to_owned(),
+      kind: Some(lsp_types::CodeActionKind::REFACTOR),
+      edit: Some(lsp_types::WorkspaceEdit {
+        changes: Some(std::iter::once((document.uri.clone(), edits)).collect()),
+        change_annotations: None,
+        document_changes: None,
+      }),
+      command: None,
+      diagnostics: None,
+      is_preferred: None,
+      data: None,
+      disabled: None,
+    },
+  ))
+}
+
+/// A refactor that removes all attributes from the expression or markup at the
+/// start of the span, including the whitespace before each attribute.
+fn remove_attributes_action(
+  document: &Document,
+  span: Span,
+) -> Option<lsp_types::CodeActionOrCommand> {
+  // Each attribute is removed together with everything between it and the
+  // end of the part before it, which is where the attribute list starts.
+  let (attributes_start, attributes) =
+    document.nodes_at(span.start).find_map(|node| match node {
+      AnyNode::LiteralExpression(expr) => Some((
+        expr
+          .annotation
+          .as_ref()
+          .map_or(expr.literal.span().end, |ann| ann.span().end),
+        &expr.attributes,
+      )),
+      AnyNode::VariableExpression(expr) => Some((
+        expr
+          .annotation
+          .as_ref()
+          .map_or(expr.variable.span().end, |ann| ann.span().end),
+        &expr.attributes,
+      )),
+      AnyNode::AnnotationExpression(expr) => {
+        Some((expr.annotation.span().end, &expr.attributes))
+      }
+      AnyNode::Markup(markup) => Some((
+        markup
+          .options
+          .last()
+          .map_or(markup.id.span().end, |opt| opt.span().end),
+        &markup.attributes,
+      )),
+      _ => None,
+    })?;
+  if attributes.is_empty() {
+    return None;
+  }
+
+  let mut start = attributes_start;
+  let mut edits = vec![];
+  for attribute in attributes {
+    let end = attribute.span().end;
+    edits.push(lsp_types::TextEdit {
+      range: document.span_to_range(Span::new(start..end)),
+      new_text: "".to_owned(),
+    });
+    start = end;
+  }
+
+  Some(lsp_types::CodeActionOrCommand::CodeAction(
+    lsp_types::CodeAction {
+      title: "Remove attributes".to_owned(),
+      kind: Some(lsp_types::CodeActionKind::REFACTOR),
+      edit: Some(lsp_types::WorkspaceEdit {
+        changes: Some(std::iter::once((document.uri.clone(), edits)).collect()),
+        change_annotations: None,
+        document_changes: None,
+      }),
+      command: None,
+      diagnostics: None,
+      is_preferred: None,
+      data: None,
+      disabled: None,
+    },
+  ))
+}
+
+/// Collects the edits of all diagnostics in the document that have exactly one
+/// fix. If the edits of a fix conflict with the edits of a fix of an earlier
+/// diagnostic, the later fix is skipped.
+fn fix_all_edits(document: &Document) -> Vec<lsp_types::TextEdit> {
+  let mut applied_spans: Vec<Span> = vec![];
+  let mut edits = vec![];
+
+  for diagnostic in document.diagnostics() {
+    let mut fixes = diagnostic.fixes(document.info());
+    if fixes.len() != 1 {
+      continue;
+    }
+    let fix = fixes.remove(0);
+
+    let has_conflict = fix.edits.iter().any(|edit| {
+      applied_spans
+        .iter()
+        .any(|applied| edits_conflict(*applied, edit.span))
+    });
+    if has_conflict {
+      continue;
+    }
+
+    applied_spans.extend(fix.edits.iter().map(|edit| edit.span));
+    edits.extend(fix.edits.into_iter().map(|edit| lsp_types::TextEdit {
+      range: document.span_to_range(edit.span),
+      new_text: edit.new_text,
+    }));
+  }
+
+  edits
+}
+
+/// Two edits conflict if the spans they replace overlap, or if they start at
+/// the same location (in which case the order of the edits is ambiguous).
+fn edits_conflict(a: Span, b: Span) -> bool {
+  a.start == b.start || (a.start < b.end && b.start < a.end)
+}
@@ -0,0 +1,91 @@
+use lsp_types::ParameterInformation;
+use lsp_types::ParameterLabel;
+use lsp_types::SignatureHelp;
+use lsp_types::SignatureInformation;
+use mf2_parser::ast;
+use mf2_parser::ast::AnyNode;
+use mf2_parser::Location;
+use mf2_parser::Spanned as _;
+
+use crate::completions::builtin_function_options;
+use crate::document::Document;
+
+/// Get the signature of the built-in function whose options the given location
+/// is in, with the option that is currently being edited as active parameter.
+pub fn signature_help(
+  document: &Document,
+  loc: Location,
+) -> Option<SignatureHelp> {
+  let (span, annotation, attributes) =
+    document.nodes_at(loc).find_map(|node| match node {
+      AnyNode::LiteralExpression(expr) => {
+        Some((expr.span, expr.annotation.as_ref(), &expr.attributes))
+      }
+      AnyNode::VariableExpression(expr) => {
+        Some((expr.span, expr.annotation.as_ref(), &expr.attributes))
+      }
+      AnyNode::AnnotationExpression(expr) => {
+        Some((expr.span, Some(&expr.annotation), &expr.attributes))
+      }
+      _ => None,
+    })?;
+  let annotation = annotation?;
+
+  // The options are between the function name and the attributes, and the
+  // location must not be after the closing brace.
+  let closed = document.info().text(span).ends_with('}');
+  if loc <= annotation.id.span().end
+    || (closed && loc == span.end)
+    || attributes.first().is_some_and(|attr| attr.span.start < loc)
+  {
+    return None;
+  }
+
+  let keys = builtin_function_options(&annotation.id)?;
+
+  let mut label = format!(":{}", annotation.id.name);
+  let parameters = keys
+    .iter()
+    .map(|key| {
+      label.push(' ');
+      let start = label.len() as u32;
+      label.push_str(key);
+      ParameterInformation {
+        label: ParameterLabel::LabelOffsets([start, label.len() as u32]),
+        documentation: None,
+      }
+    })
+    .collect();
+
+  Some(SignatureHelp {
+    signatures: vec![SignatureInformation {
+      label,
+      documentation: None,
+      parameters: Some(parameters),
+      active_parameter: None,
+    }],
+    active_signature: Some(0),
+    active_parameter: active_option(annotation, keys, loc),
+  })
+}
+
+/// The option being edited is the last option that starts at or before the
+/// location. Returns its index in the known option keys of the function.
+fn active_option(
+  annotation: &ast::Annotation,
+  keys: &[String],
+  loc: Location,
+) -> Option<u32> {
+  let preceding = annotation
+    .options
+    .iter()
+    .take_while(|option| option.span().start <= loc)
+    .count();
+  let option = annotation.options.get(preceding.checked_sub(1)?)?;
+  if option.span().end < loc {
+    // The cursor is in the space after the option.
+    return None;
+  }
+  let index = keys.iter().position(|key| *key == option.key.name)?;
+  Some(index as u32)
+}
@@ -37,7 +37,8 @@ Deno.test("diagnostics", async () => {
           end: { character: 16, line: 0 },
           start: { character: 14, line: 0 },
         },
-        severity: 1,
+        severity: 2,
+        code: "escape-invalid-character",
         source: "mf2",
       },
     ],
@@ -46,6 +47,53 @@ Deno.test("diagnostics", async () => {
   });
 });
 
+Deno.test("diagnostics for large document with progress", async () => {
+  await using lsp = new AutoLSPTest();
+  await lsp.initialize(undefined, { window: { workDoneProgress: true } });
+
+  const progress = lsp.collectNotifications("$/progress");
+  const diagnosticPromise = lsp.waitNotify("textDocument/publishDiagnostics");
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: "Hello, {$name}! ".repeat(5000),
+      },
+    },
+  );
+
+  const diagnostic = await diagnosticPromise;
+  assertEquals(diagnostic, {
+    diagnostics: [],
+    uri: "file:///src/main.mf2",
+    version: 1,
+  });
+
+  // The end of the progress is sent after the diagnostics. Any request is
+  // answered after it, so it has been received once the response arrives.
+  await lsp.request("textDocument/hover", {
+    textDocument: { uri: "file:///src/main.mf2" },
+    position: { line: 0, character: 0 },
+  });
+
+  const token = "mf2lsp/analyze/file:///src/main.mf2/1";
+  assertEquals(progress, [
+    {
+      token,
+      value: { kind: "begin", title: "Analyzing message…", message: "Parsing" },
+    },
+    {
+      token,
+      value: { kind: "report", message: "Publishing diagnostics" },
+    },
+    { token, value: { kind: "end" } },
+  ]);
+});
+
 Deno.test("diagnostics with emoji", async () => {
   await using lsp = new AutoLSPTest();
   await lsp.initialize();
@@ -74,7 +122,8 @@ Deno.test("diagnostics with emoji", async () => {
           end: { character: 41, line: 0 },
           start: { character: 39, line: 0 },
         },
-        severity: 1,
+        severity: 2,
+        code: "escape-invalid-character",
         source: "mf2",
       },
     ],
@@ -97,7 +146,7 @@ Deno.test("scope diagnostics", async (t) => {
           uri: "file:///src/main.mf2",
           languageId: "mf2",
           version: 1,
-          text: ".local $foo = {1} .local $foo = {2} {{}}",
+          text: ".local $foo = {1} .local $foo = {2} {{{$foo}}}",
         },
       },
     );
@@ -111,7 +160,20 @@ Deno.test("scope diagnostics", async (t) => {
             start: { character: 25, line: 0 },
             end: { character: 29, line: 0 },
           },
+          relatedInformation: [
+            {
+              location: {
+                uri: "file:///src/main.mf2",
+                range: {
+                  start: { character: 7, line: 0 },
+                  end: { character: 11, line: 0 },
+                },
+              },
+              message: "$foo is first declared here.",
+            },
+          ],
           severity: 1,
+          code: "duplicate-declaration",
           source: "mf2",
         },
       ],
@@ -130,7 +192,7 @@ Deno.test("scope diagnostics", async (t) => {
           uri: "file:///src/main.mf2",
           languageId: "mf2",
           version: 2,
-          text: ".local $bar = {:fn a=$foo b=$asd} .input {$foo} {{}}",
+          text: ".local $bar = {:fn a=$foo b=$asd} .input {$foo} {{{$bar}}}",
         },
       },
     );
@@ -144,7 +206,20 @@ Deno.test("scope diagnostics", async (t) => {
             start: { character: 21, line: 0 },
             end: { character: 25, line: 0 },
           },
+          relatedInformation: [
+            {
+              location: {
+                uri: "file:///src/main.mf2",
+                range: {
+                  start: { character: 42, line: 0 },
+                  end: { character: 46, line: 0 },
+                },
+              },
+              message: "$foo is declared here.",
+            },
+          ],
           severity: 1,
+          code: "usage-before-declaration",
           source: "mf2",
         },
       ],
@@ -163,7 +238,7 @@ Deno.test("scope diagnostics", async (t) => {
           uri: "file:///src/main.mf2",
           languageId: "mf2",
           version: 3,
-          text: ".local $foo = {$foo :fn opt=$foo} {{}}",
+          text: ".local $foo = {$foo :fn opt=$foo} {{{$foo}}}",
         },
       },
     );
@@ -177,7 +252,20 @@ Deno.test("scope diagnostics", async (t) => {
             start: { character: 15, line: 0 },
             end: { character: 19, line: 0 },
           },
+          relatedInformation: [
+            {
+              location: {
+                uri: "file:///src/main.mf2",
+                range: {
+                  start: { character: 7, line: 0 },
+                  end: { character: 11, line: 0 },
+                },
+              },
+              message: "$foo is declared here.",
+            },
+          ],
           severity: 1,
+          code: "usage-before-declaration",
           source: "mf2",
         },
         {
@@ -186,7 +274,20 @@ Deno.test("scope diagnostics", async (t) => {
             start: { character: 28, line: 0 },
             end: { character: 32, line: 0 },
           },
+          relatedInformation: [
+            {
+              location: {
+                uri: "file:///src/main.mf2",
+                range: {
+                  start: { character: 7, line: 0 },
+                  end: { character: 11, line: 0 },
+                },
+              },
+              message: "$foo is declared here.",
+            },
+          ],
           severity: 1,
+          code: "usage-before-declaration",
           source: "mf2",
         },
       ],
@@ -194,6 +295,119 @@ Deno.test("scope diagnostics", async (t) => {
       version: 3,
     });
   });
+
+  await t.step("input redeclared as local", async () => {
+    const diagnosticPromise = lsp.waitNotify("textDocument/publishDiagnostics");
+
+    await lsp.notify(
+      "textDocument/didOpen",
+      {
+        textDocument: {
+          uri: "file:///src/main.mf2",
+          languageId: "mf2",
+          version: 4,
+          text: ".input {$foo :number} .local $foo = {$foo :date} {{}}",
+        },
+      },
+    );
+
+    const diagnostic = await diagnosticPromise;
+    assertEquals(diagnostic, {
+      diagnostics: [
+        {
+          message:
+            "$foo is declared with .input, so it can not be redeclared with .local. MessageFormat 2 does not allow redefining inputs as local variables.",
+          range: {
+            start: { character: 29, line: 0 },
+            end: { character: 33, line: 0 },
+          },
+          relatedInformation: [
+            {
+              location: {
+                uri: "file:///src/main.mf2",
+                range: {
+                  start: { character: 8, line: 0 },
+                  end: { character: 12, line: 0 },
+                },
+              },
+              message: "$foo is declared as an input here.",
+            },
+          ],
+          severity: 1,
+          code: "redeclare-input-as-local",
+          source: "mf2",
+        },
+      ],
+      uri: "file:///src/main.mf2",
+      version: 4,
+    });
+  });
+
+  await t.step("unused declaration", async () => {
+    const diagnosticPromise = lsp.waitNotify("textDocument/publishDiagnostics");
+
+    await lsp.notify(
+      "textDocument/didOpen",
+      {
+        textDocument: {
+          uri: "file:///src/main.mf2",
+          languageId: "mf2",
+          version: 5,
+          text: ".local $foo = {1} {{}}",
+        },
+      },
+    );
+
+    const diagnostic = await diagnosticPromise;
+    assertEquals(diagnostic, {
+      diagnostics: [
+        {
+          message: "$foo is declared, but never used.",
+          range: {
+            start: { character: 7, line: 0 },
+            end: { character: 11, line: 0 },
+          },
+          severity: 2,
+          code: "unused-declaration",
+          source: "mf2",
+          tags: [1],
+        },
+      ],
+      uri: "file:///src/main.mf2",
+      version: 5,
+    });
+  });
+});
+
+Deno.test("diagnostics are not published when disabled", async () => {
+  await using lsp = new AutoLSPTest();
+  await lsp.initialize({ publishDiagnostics: false });
+
+  let published = false;
+  lsp.waitNotify("textDocument/publishDiagnostics").then(() => {
+    published = true;
+  });
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: "{$",
+      },
+    },
+  );
+
+  // Requests are handled in order, so any diagnostics for the opened document
+  // would have been received before the response to this request.
+  await lsp.request("textDocument/hover", {
+    textDocument: { uri: "file:///src/main.mf2" },
+    position: { line: 0, character: 0 },
+  });
+
+  assertEquals(published, false);
 });
 
 Deno.test("variable rename", async (t) => {
@@ -321,9 +535,8 @@ Deno.test("variable rename", async (t) => {
   });
 });
 
-Deno.test("semantic tokens", async () => {
+Deno.test("variable rename in matcher", async (t) => {
   await using lsp = new AutoLSPTest();
-
   await lsp.initialize();
 
   await lsp.notify(
@@ -334,96 +547,158 @@ Deno.test("semantic tokens", async () => {
         languageId: "mf2",
         version: 1,
         text:
-          ".local $a = {:x c=1}\n.local $b = {2}\n.match $a\n* {{ {|a\nb\r\nc| } }}",
+          ".input {$count :number}\n.match $count\none {{One item}}\n* {{{$count} items}}",
       },
     },
   );
 
-  const semanticTokens = await lsp.request("textDocument/semanticTokens/full", {
-    textDocument: {
-      uri: "file:///src/main.mf2",
-    },
+  await t.step("rename count from the pattern", async () => {
+    const response = await lsp.request("textDocument/rename", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 3, character: 7 },
+      newName: "total",
+    });
+
+    assertEquals(response, {
+      changes: {
+        "file:///src/main.mf2": [
+          {
+            newText: "$total",
+            range: {
+              start: { character: 8, line: 0 },
+              end: { character: 14, line: 0 },
+            },
+          },
+          {
+            newText: "$total",
+            range: {
+              start: { character: 7, line: 1 },
+              end: { character: 13, line: 1 },
+            },
+          },
+          {
+            newText: "$total",
+            range: {
+              start: { character: 5, line: 3 },
+              end: { character: 11, line: 3 },
+            },
+          },
+        ],
+      },
+    });
   });
 
-  assert(semanticTokens);
-  // deno-fmt-ignore
-  assertEquals(semanticTokens.data, [
-    0, 7, 2, 0, 0, // $a
-    0, 7, 1, 2, 0, // :x
-    0, 2, 1, 1, 0, // c
-    0, 2, 1, 5, 0, // 1
-    1, 7, 2, 0, 0, // $b
-    0, 6, 1, 5, 0, // 2
-    1, 0, 6, 3, 0, // .match
-    0, 7, 2, 0, 0, // $a
-    1, 6, 3, 4, 0, // |a\n
-    1, 0, 3, 4, 0, // b\r\n
-    1, 0, 2, 4, 0, // c
-  ]);
-});
+  await t.step("rename count from the selector", async () => {
+    const response = await lsp.request("textDocument/rename", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 1, character: 9 },
+      newName: "total",
+    });
 
-for (const def of ["definition", "declaration"] as const) {
-  Deno.test(`go to ${def}`, async (t) => {
-    await using lsp = new AutoLSPTest();
+    assertEquals(response, {
+      changes: {
+        "file:///src/main.mf2": [
+          {
+            newText: "$total",
+            range: {
+              start: { character: 8, line: 0 },
+              end: { character: 14, line: 0 },
+            },
+          },
+          {
+            newText: "$total",
+            range: {
+              start: { character: 7, line: 1 },
+              end: { character: 13, line: 1 },
+            },
+          },
+          {
+            newText: "$total",
+            range: {
+              start: { character: 5, line: 3 },
+              end: { character: 11, line: 3 },
+            },
+          },
+        ],
+      },
+    });
+  });
+});
 
-    await lsp.initialize();
+Deno.test("hover on number literal", async (t) => {
+  await using lsp = new AutoLSPTest();
+  await lsp.initialize();
 
-    await lsp.notify(
-      "textDocument/didOpen",
-      {
-        textDocument: {
-          uri: "file:///src/main.mf2",
-          languageId: "mf2",
-          version: 1,
-          text: ".input {$bar} .local $foo = {$bar} .match $foo 1 {{}}",
-        },
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: "{1.5e3} {01} {1e300}",
       },
-    );
+    },
+  );
 
-    await t.step("for .input", async () => {
-      const response = await lsp.request(`textDocument/${def}`, {
-        textDocument: { uri: "file:///src/main.mf2" },
-        position: { line: 0, character: 31 },
-      });
+  await t.step("scientific notation", async () => {
+    const response = await lsp.request("textDocument/hover", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 3 },
+    });
 
-      assertEquals(response, {
-        uri: "file:///src/main.mf2",
-        range: {
-          start: { line: 0, character: 8 },
-          end: { line: 0, character: 12 },
-        },
-      });
+    assertEquals(response, {
+      contents: {
+        kind: "markdown",
+        value: "Number literal with the value `1500`.\n\n```mf2\n{1.5e3}\n```",
+      },
+      range: {
+        start: { line: 0, character: 1 },
+        end: { line: 0, character: 6 },
+      },
     });
+  });
 
-    await t.step("for .local", async () => {
-      const response = await lsp.request(`textDocument/${def}`, {
-        textDocument: { uri: "file:///src/main.mf2" },
-        position: { line: 0, character: 38 },
-      });
+  await t.step("leading zero", async () => {
+    const response = await lsp.request("textDocument/hover", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 10 },
+    });
 
-      assertEquals(response, null);
+    assertEquals(response, {
+      contents: {
+        kind: "markdown",
+        value:
+          "Number literal with the value `1`.\n\nNumber has leading zero in integral part, which is not allowed.\n\n```mf2\n{01}\n```",
+      },
+      range: {
+        start: { line: 0, character: 9 },
+        end: { line: 0, character: 11 },
+      },
     });
+  });
 
-    await t.step("somewhere else", async () => {
-      const response = await lsp.request(`textDocument/${def}`, {
-        textDocument: { uri: "file:///src/main.mf2" },
-        position: { line: 0, character: 44 },
-      });
+  await t.step("large exponent", async () => {
+    const response = await lsp.request("textDocument/hover", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 15 },
+    });
 
-      assertEquals(response, {
-        uri: "file:///src/main.mf2",
-        range: {
-          start: { line: 0, character: 21 },
-          end: { line: 0, character: 25 },
-        },
-      });
+    assertEquals(response, {
+      contents: {
+        kind: "markdown",
+        value: "Number literal with the value `1e300`.\n\n```mf2\n{1e300}\n```",
+      },
+      range: {
+        start: { line: 0, character: 14 },
+        end: { line: 0, character: 19 },
+      },
     });
   });
-}
+});
 
-Deno.test("completions", async (t) => {
+Deno.test("hover on literal with backticks", async () => {
   await using lsp = new AutoLSPTest();
-
   await lsp.initialize();
 
   await lsp.notify(
@@ -433,15 +708,449 @@ Deno.test("completions", async (t) => {
         uri: "file:///src/main.mf2",
         languageId: "mf2",
         version: 1,
-        text: ".local $foo = {1} .input {$bar}\n{{ {$f} {} }}",
+        text: "{|a ```` b|}",
       },
     },
   );
 
-  function sort(response: CompletionList | CompletionItem[] | null) {
-    if (Array.isArray(response)) {
-      response.sort((a: { label: string }, b: { label: string }) =>
-        a.label.localeCompare(b.label)
+  const response = await lsp.request("textDocument/hover", {
+    textDocument: { uri: "file:///src/main.mf2" },
+    position: { line: 0, character: 3 },
+  });
+
+  assertEquals(response, {
+    contents: {
+      kind: "markdown",
+      value: "Quoted literal.\n\n`````mf2\n{|a ```` b|}\n`````",
+    },
+    range: {
+      start: { line: 0, character: 2 },
+      end: { line: 0, character: 10 },
+    },
+  });
+});
+
+Deno.test("hover on local variable", async () => {
+  await using lsp = new AutoLSPTest();
+  await lsp.initialize();
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: ".local $b = {  $a :number}\n{{{$b}}}",
+      },
+    },
+  );
+
+  const response = await lsp.request("textDocument/hover", {
+    textDocument: { uri: "file:///src/main.mf2" },
+    position: { line: 1, character: 4 },
+  });
+
+  assertEquals(response, {
+    contents: {
+      kind: "markdown",
+      value:
+        "Local variable `$b`, declared as:\n\n```mf2\n.local $b = {$a :number}\n```",
+    },
+    range: {
+      start: { line: 1, character: 3 },
+      end: { line: 1, character: 5 },
+    },
+  });
+});
+
+Deno.test("hover on input and external variables", async (t) => {
+  await using lsp = new AutoLSPTest();
+  await lsp.initialize();
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: ".input {$a   :number}\n{{{$a} {$b :string}}}",
+      },
+    },
+  );
+
+  await t.step("input variable", async () => {
+    const response = await lsp.request("textDocument/hover", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 1, character: 4 },
+    });
+
+    assertEquals(response, {
+      contents: {
+        kind: "markdown",
+        value:
+          "Input variable `$a`, declared as:\n\n```mf2\n.input {$a :number}\n```",
+      },
+      range: {
+        start: { line: 1, character: 3 },
+        end: { line: 1, character: 5 },
+      },
+    });
+  });
+
+  await t.step("external variable", async () => {
+    const response = await lsp.request("textDocument/hover", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 1, character: 9 },
+    });
+
+    assertEquals(response, {
+      contents: {
+        kind: "markdown",
+        value:
+          "External variable `$b`. It is not declared in this message, so it is an implicit input.",
+      },
+      range: {
+        start: { line: 1, character: 8 },
+        end: { line: 1, character: 10 },
+      },
+    });
+  });
+
+  await t.step("function", async () => {
+    const response = await lsp.request("textDocument/hover", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 1, character: 14 },
+    });
+
+    assertEquals(response, {
+      contents: {
+        kind: "markdown",
+        value: "Function `:string`.\n\n```mf2\n{$b :string}\n```",
+      },
+      range: {
+        start: { line: 1, character: 12 },
+        end: { line: 1, character: 18 },
+      },
+    });
+  });
+});
+
+Deno.test("semantic tokens", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text:
+          ".local $a = {:x c=1}\n.local $b = {2}\n.match $a\n* {{ {|a\nb\r\nc| } }}",
+      },
+    },
+  );
+
+  const semanticTokens = await lsp.request("textDocument/semanticTokens/full", {
+    textDocument: {
+      uri: "file:///src/main.mf2",
+    },
+  });
+
+  assert(semanticTokens);
+  // deno-fmt-ignore
+  assertEquals(semanticTokens.data, [
+    0, 7, 2, 0, 0, // $a
+    0, 7, 1, 2, 0, // :x
+    0, 2, 1, 1, 0, // c
+    0, 2, 1, 5, 0, // 1
+    1, 7, 2, 0, 0, // $b
+    0, 6, 1, 5, 0, // 2
+    1, 0, 6, 3, 0, // .match
+    0, 7, 2, 0, 0, // $a
+    1, 4, 1, 6, 0, // (space)
+    0, 2, 3, 4, 0, // |a\n
+    1, 0, 3, 4, 0, // b\r\n
+    1, 0, 2, 4, 0, // c
+    0, 4, 1, 6, 0, // (space)
+  ]);
+});
+
+Deno.test("semantic tokens are skipped for large documents", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize({ semanticTokensMaxBytes: 10 });
+
+  for (const [uri, text] of [
+    ["file:///src/small.mf2", "{$a}"],
+    ["file:///src/large.mf2", "Hello {$a}, welcome!"],
+  ]) {
+    await lsp.notify("textDocument/didOpen", {
+      textDocument: { uri, languageId: "mf2", version: 1, text },
+    });
+  }
+
+  const small = await lsp.request("textDocument/semanticTokens/full", {
+    textDocument: { uri: "file:///src/small.mf2" },
+  });
+  assert(small);
+  assertEquals(small.data, [0, 1, 2, 0, 0]);
+
+  const large = await lsp.request("textDocument/semanticTokens/full", {
+    textDocument: { uri: "file:///src/large.mf2" },
+  });
+  assertEquals(large, null);
+});
+
+Deno.test("semantic tokens for a range of a large document", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize({ semanticTokensMaxBytes: 10 });
+
+  const text = Array.from({ length: 1000 }, (_, i) => `line ${i} {$a}`)
+    .join("\n");
+  await lsp.notify("textDocument/didOpen", {
+    textDocument: {
+      uri: "file:///src/main.mf2",
+      languageId: "mf2",
+      version: 1,
+      text,
+    },
+  });
+
+  const full = await lsp.request("textDocument/semanticTokens/full", {
+    textDocument: { uri: "file:///src/main.mf2" },
+  });
+  assertEquals(full, null);
+
+  // Only the tokens of the parts that intersect the range are returned, in
+  // full: the text before `{$a}` starts with the line break on line 499.
+  const range = await lsp.request("textDocument/semanticTokens/range", {
+    textDocument: { uri: "file:///src/main.mf2" },
+    range: {
+      start: { line: 500, character: 6 },
+      end: { line: 500, character: 12 },
+    },
+  });
+  assert(range);
+  // deno-fmt-ignore
+  assertEquals(range.data, [
+    499, 13, 1, 6, 0, // (line break)
+    1, 0, 9, 6, 0, // line 500 (space)
+    0, 10, 2, 0, 0, // $a
+  ]);
+
+  const large = await lsp.request("textDocument/semanticTokens/range", {
+    textDocument: { uri: "file:///src/main.mf2" },
+    range: {
+      start: { line: 0, character: 0 },
+      end: { line: 500, character: 0 },
+    },
+  });
+  assertEquals(large, null);
+});
+
+for (const def of ["definition", "declaration"] as const) {
+  Deno.test(`go to ${def}`, async (t) => {
+    await using lsp = new AutoLSPTest();
+
+    await lsp.initialize();
+
+    await lsp.notify(
+      "textDocument/didOpen",
+      {
+        textDocument: {
+          uri: "file:///src/main.mf2",
+          languageId: "mf2",
+          version: 1,
+          text: ".input {$bar} .local $foo = {$bar} .match $foo 1 {{}}",
+        },
+      },
+    );
+
+    await t.step("for .input", async () => {
+      const response = await lsp.request(`textDocument/${def}`, {
+        textDocument: { uri: "file:///src/main.mf2" },
+        position: { line: 0, character: 31 },
+      });
+
+      assertEquals(response, {
+        uri: "file:///src/main.mf2",
+        range: {
+          start: { line: 0, character: 8 },
+          end: { line: 0, character: 12 },
+        },
+      });
+    });
+
+    await t.step("for .local", async () => {
+      const response = await lsp.request(`textDocument/${def}`, {
+        textDocument: { uri: "file:///src/main.mf2" },
+        position: { line: 0, character: 38 },
+      });
+
+      assertEquals(response, null);
+    });
+
+    await t.step("somewhere else", async () => {
+      const response = await lsp.request(`textDocument/${def}`, {
+        textDocument: { uri: "file:///src/main.mf2" },
+        position: { line: 0, character: 44 },
+      });
+
+      assertEquals(response, {
+        uri: "file:///src/main.mf2",
+        range: {
+          start: { line: 0, character: 21 },
+          end: { line: 0, character: 25 },
+        },
+      });
+    });
+  });
+}
+
+Deno.test("references", async (t) => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text: ".input {$bar} .local $foo = {$bar} .match $foo 1 {{{$bar}}}",
+      },
+    },
+  );
+
+  const location = (start: number, end: number) => ({
+    uri,
+    range: {
+      start: { line: 0, character: start },
+      end: { line: 0, character: end },
+    },
+  });
+
+  await t.step("including the declaration", async () => {
+    const response = await lsp.request("textDocument/references", {
+      textDocument: { uri },
+      position: { line: 0, character: 31 },
+      context: { includeDeclaration: true },
+    });
+
+    assertEquals(response, [
+      location(8, 12),
+      location(29, 33),
+      location(52, 56),
+    ]);
+  });
+
+  await t.step("excluding the declaration", async () => {
+    const response = await lsp.request("textDocument/references", {
+      textDocument: { uri },
+      position: { line: 0, character: 31 },
+      context: { includeDeclaration: false },
+    });
+
+    assertEquals(response, [location(29, 33), location(52, 56)]);
+  });
+
+  await t.step("not on a variable", async () => {
+    const response = await lsp.request("textDocument/references", {
+      textDocument: { uri },
+      position: { line: 0, character: 2 },
+      context: { includeDeclaration: true },
+    });
+
+    assertEquals(response, null);
+  });
+});
+
+Deno.test("document highlight", async (t) => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text: ".input {$bar} .local $foo = {$bar} .match $foo 1 {{{$baz}}}",
+      },
+    },
+  );
+
+  const highlight = (start: number, end: number, kind: number) => ({
+    range: {
+      start: { line: 0, character: start },
+      end: { line: 0, character: end },
+    },
+    kind,
+  });
+
+  await t.step("declared variable", async () => {
+    const response = await lsp.request("textDocument/documentHighlight", {
+      textDocument: { uri },
+      position: { line: 0, character: 23 },
+    });
+
+    assertEquals(response, [highlight(21, 25, 3), highlight(42, 46, 2)]);
+  });
+
+  await t.step("undeclared variable", async () => {
+    const response = await lsp.request("textDocument/documentHighlight", {
+      textDocument: { uri },
+      position: { line: 0, character: 53 },
+    });
+
+    assertEquals(response, [highlight(52, 56, 2)]);
+  });
+
+  await t.step("not on a variable", async () => {
+    const response = await lsp.request("textDocument/documentHighlight", {
+      textDocument: { uri },
+      position: { line: 0, character: 2 },
+    });
+
+    assertEquals(response, null);
+  });
+});
+
+Deno.test("completions", async (t) => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: ".local $foo = {1} .input {$bar}\n{{ {$f} {} }}",
+      },
+    },
+  );
+
+  function sort(response: CompletionList | CompletionItem[] | null) {
+    if (Array.isArray(response)) {
+      response.sort((a: { label: string }, b: { label: string }) =>
+        a.label.localeCompare(b.label)
       );
     }
   }
@@ -456,7 +1165,8 @@ Deno.test("completions", async (t) => {
 
     assertEquals(response, [
       {
-        kind: 6,
+        detail: "input",
+        kind: 5,
         label: "$bar",
         textEdit: {
           newText: "$bar",
@@ -467,6 +1177,8 @@ Deno.test("completions", async (t) => {
         },
       },
       {
+        detail: "local",
+        documentation: "{1}",
         kind: 6,
         label: "$foo",
         textEdit: {
@@ -490,28 +1202,303 @@ Deno.test("completions", async (t) => {
 
     assertEquals(response, [
       {
-        kind: 6,
+        detail: "input",
+        kind: 5,
         label: "$bar",
       },
       {
-        kind: 6,
+        detail: "external",
+        kind: 5,
         label: "$f",
       },
       {
+        detail: "local",
+        documentation: "{1}",
         kind: 6,
         label: "$foo",
       },
-    ]);
+    ]);
+  });
+
+  await t.step("completions where no variable is allowed", async () => {
+    const response = await lsp.request("textDocument/completion", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 15 },
+    });
+
+    assertEquals(response, null);
+  });
+});
+
+Deno.test("function completions", async (t) => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: "{:} {$x :num}",
+      },
+    },
+  );
+
+  const functions = ["date", "datetime", "integer", "number", "string", "time"];
+
+  await t.step("completions after :", async () => {
+    const response = await lsp.request("textDocument/completion", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 2 },
+    });
+
+    assertEquals(
+      response,
+      functions.map((label) => ({ detail: "function", kind: 3, label })),
+    );
+  });
+
+  await t.step("completions for :num", async () => {
+    const response = await lsp.request("textDocument/completion", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 12 },
+    });
+
+    assertEquals(
+      response,
+      functions.map((label) => ({
+        detail: "function",
+        kind: 3,
+        label,
+        textEdit: {
+          newText: label,
+          range: {
+            start: { line: 0, character: 9 },
+            end: { line: 0, character: 12 },
+          },
+        },
+      })),
+    );
+  });
+});
+
+Deno.test("option key completions", async (t) => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: "{$x :integer } {$y :integer s} {$z :integer select=}",
+      },
+    },
+  );
+
+  const keys = [
+    "select",
+    "signDisplay",
+    "useGrouping",
+    "minimumIntegerDigits",
+    "maximumSignificantDigits",
+  ];
+
+  await t.step("completions after the function", async () => {
+    const response = await lsp.request("textDocument/completion", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 13 },
+    });
+
+    assertEquals(
+      response,
+      keys.map((label) => ({ detail: "option", kind: 10, label })),
+    );
+  });
+
+  await t.step("completions for a partial key", async () => {
+    const response = await lsp.request("textDocument/completion", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 29 },
+    });
+
+    assertEquals(
+      response,
+      keys.map((label) => ({
+        detail: "option",
+        kind: 10,
+        label,
+        textEdit: {
+          newText: label,
+          range: {
+            start: { line: 0, character: 28 },
+            end: { line: 0, character: 29 },
+          },
+        },
+      })),
+    );
+  });
+
+  await t.step("no completions after =", async () => {
+    const response = await lsp.request("textDocument/completion", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 51 },
+    });
+
+    assert(
+      !Array.isArray(response) ||
+        response.every((item) => item.detail !== "option"),
+    );
+  });
+});
+
+Deno.test("keyword completions", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: ".input {$x}\n.i\n{{}}",
+      },
+    },
+  );
+
+  const response = await lsp.request("textDocument/completion", {
+    textDocument: { uri: "file:///src/main.mf2" },
+    position: { line: 1, character: 2 },
+  });
+
+  assertEquals(
+    response,
+    ["input", "local", "match"].map((label) => ({
+      detail: "keyword",
+      kind: 14,
+      label,
+      textEdit: {
+        newText: label,
+        range: {
+          start: { line: 1, character: 1 },
+          end: { line: 1, character: 2 },
+        },
+      },
+    })),
+  );
+});
+
+Deno.test("signature help for function options", async (t) => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri: "file:///src/main.mf2",
+        languageId: "mf2",
+        version: 1,
+        text: "{$y :date style=long } {$z :string }",
+      },
+    },
+  );
+
+  const signature = {
+    label: ":date style",
+    parameters: [{ label: [6, 11] }],
+  };
+
+  await t.step("in an option", async () => {
+    const response = await lsp.request("textDocument/signatureHelp", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 12 },
+    });
+
+    assertEquals(response, {
+      signatures: [signature],
+      activeSignature: 0,
+      activeParameter: 0,
+    });
+  });
+
+  await t.step("after the options", async () => {
+    const response = await lsp.request("textDocument/signatureHelp", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 21 },
+    });
+
+    assertEquals(response, {
+      signatures: [signature],
+      activeSignature: 0,
+    });
+  });
+
+  await t.step("function without options", async () => {
+    const response = await lsp.request("textDocument/signatureHelp", {
+      textDocument: { uri: "file:///src/main.mf2" },
+      position: { line: 0, character: 35 },
+    });
+
+    assertEquals(response, null);
+  });
+});
+
+Deno.test("incremental document changes", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text: "Héllo {  $a }",
+      },
+    },
+  );
+
+  const range = (start: number, end: number) => ({
+    start: { line: 0, character: start },
+    end: { line: 0, character: end },
   });
 
-  await t.step("completions where no variable is allowed", async () => {
-    const response = await lsp.request("textDocument/completion", {
-      textDocument: { uri: "file:///src/main.mf2" },
-      position: { line: 0, character: 15 },
-    });
+  // Each change is relative to the text after the previous changes.
+  await lsp.notify("textDocument/didChange", {
+    textDocument: { uri, version: 2 },
+    contentChanges: [
+      { range: range(10, 11), text: "name" },
+      { range: range(0, 5), text: "Hi 😀" },
+      { range: range(5, 5), text: "!" },
+    ],
+  });
 
-    assertEquals(response, null);
+  const res = await lsp.request("textDocument/formatting", {
+    textDocument: { uri },
+    options: { tabSize: 2, insertSpaces: true },
   });
+
+  assertEquals(res, [
+    {
+      newText: "Hi 😀! {$name}",
+      range: range(0, 17),
+    },
+  ]);
 });
 
 Deno.test("formatting", async (t) => {
@@ -582,6 +1569,29 @@ Deno.test("formatting", async (t) => {
     ]);
   });
 
+  await t.step("returns no edits for formatted code", async () => {
+    const uri = "file:///src/test-5.mf2";
+
+    await lsp.notify(
+      "textDocument/didOpen",
+      {
+        textDocument: {
+          uri,
+          languageId: "mf2",
+          version: 1,
+          text: ".input {$bar}\n\n{{Hello {$bar}!}}\n",
+        },
+      },
+    );
+
+    const res = await lsp.request("textDocument/formatting", {
+      textDocument: { uri },
+      options: { tabSize: 2, insertSpaces: true },
+    });
+
+    assertEquals(res, []);
+  });
+
   await t.step({
     name: "formats code with recoverable syntax errors",
     ignore: true,
@@ -643,3 +1653,404 @@ Deno.test("formatting", async (t) => {
     },
   );
 });
+
+Deno.test("add selector code action", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text:
+          ".input {$a :number}\n.input {$b :string}\n.match $a\n1 {{one}}\n* {{other}}",
+      },
+    },
+  );
+
+  const res = await lsp.request("textDocument/codeAction", {
+    textDocument: { uri },
+    range: {
+      start: { line: 3, character: 0 },
+      end: { line: 3, character: 0 },
+    },
+    context: { diagnostics: [] },
+  });
+
+  assertEquals(res, [
+    {
+      title: "Add selector",
+      kind: "refactor",
+      edit: {
+        changes: {
+          [uri]: [
+            {
+              newText: " $b",
+              range: {
+                start: { line: 2, character: 9 },
+                end: { line: 2, character: 9 },
+              },
+            },
+            {
+              newText: " *",
+              range: {
+                start: { line: 3, character: 1 },
+                end: { line: 3, character: 1 },
+              },
+            },
+            {
+              newText: " *",
+              range: {
+                start: { line: 4, character: 1 },
+                end: { line: 4, character: 1 },
+              },
+            },
+          ],
+        },
+      },
+    },
+  ]);
+});
+
+Deno.test("no add selector code action when all variables are selectors", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text: ".input {$a :number}\n.match $a\n1 {{one}}\n* {{other}}",
+      },
+    },
+  );
+
+  const res = await lsp.request("textDocument/codeAction", {
+    textDocument: { uri },
+    range: {
+      start: { line: 2, character: 0 },
+      end: { line: 2, character: 0 },
+    },
+    context: { diagnostics: [] },
+  });
+
+  assertEquals(res, []);
+});
+
+Deno.test("remove attributes code action", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text: "{$name @a=1 @b}",
+      },
+    },
+  );
+
+  const res = await lsp.request("textDocument/codeAction", {
+    textDocument: { uri },
+    range: {
+      start: { line: 0, character: 2 },
+      end: { line: 0, character: 2 },
+    },
+    context: { diagnostics: [] },
+  });
+
+  assertEquals(res, [
+    {
+      title: "Remove attributes",
+      kind: "refactor",
+      edit: {
+        changes: {
+          [uri]: [
+            {
+              newText: "",
+              range: {
+                start: { line: 0, character: 6 },
+                end: { line: 0, character: 11 },
+              },
+            },
+            {
+              newText: "",
+              range: {
+                start: { line: 0, character: 11 },
+                end: { line: 0, character: 14 },
+              },
+            },
+          ],
+        },
+      },
+    },
+  ]);
+});
+
+Deno.test("fix all command", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text: "a } b \\c",
+      },
+    },
+  );
+
+  const res = await lsp.request("workspace/executeCommand", {
+    command: "mf2.fixAll",
+    arguments: [uri],
+  });
+
+  assertEquals(res, {
+    changes: {
+      [uri]: [
+        {
+          newText: "\\",
+          range: {
+            start: { line: 0, character: 2 },
+            end: { line: 0, character: 2 },
+          },
+        },
+        {
+          newText: "",
+          range: {
+            start: { line: 0, character: 6 },
+            end: { line: 0, character: 7 },
+          },
+        },
+      ],
+    },
+  });
+});
+
+Deno.test("document links", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text: ".input {$x :number}\n.local $y = {$x}\n{{{$y} {$z}}}",
+      },
+    },
+  );
+
+  const res = await lsp.request("textDocument/documentLink", {
+    textDocument: { uri },
+  });
+
+  assertEquals(res, [
+    {
+      range: {
+        start: { line: 1, character: 13 },
+        end: { line: 1, character: 15 },
+      },
+      target: `${uri}#L1,9`,
+      tooltip: "Go to declaration of $x",
+    },
+    {
+      range: {
+        start: { line: 2, character: 3 },
+        end: { line: 2, character: 5 },
+      },
+      target: `${uri}#L2,8`,
+      tooltip: "Go to declaration of $y",
+    },
+  ]);
+});
+
+Deno.test("document symbols", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text:
+          ".input {$x :number}\n.local $y = {$x}\n.match $x\none {{a}}\n* {{b}}",
+      },
+    },
+  );
+
+  const res = await lsp.request("textDocument/documentSymbol", {
+    textDocument: { uri },
+  });
+
+  const range = (
+    startLine: number,
+    startCharacter: number,
+    endLine: number,
+    endCharacter: number,
+  ) => ({
+    start: { line: startLine, character: startCharacter },
+    end: { line: endLine, character: endCharacter },
+  });
+
+  assertEquals(res, [
+    {
+      name: "$x",
+      detail: ".input",
+      kind: 13,
+      range: range(0, 0, 0, 19),
+      selectionRange: range(0, 8, 0, 10),
+    },
+    {
+      name: "$y",
+      detail: ".local",
+      kind: 13,
+      range: range(1, 0, 1, 16),
+      selectionRange: range(1, 7, 1, 9),
+    },
+    {
+      name: ".match",
+      detail: "$x",
+      kind: 19,
+      range: range(2, 0, 4, 7),
+      selectionRange: range(2, 0, 2, 6),
+      children: [
+        {
+          name: "one",
+          kind: 22,
+          range: range(3, 0, 3, 9),
+          selectionRange: range(3, 0, 3, 3),
+        },
+        {
+          name: "*",
+          kind: 22,
+          range: range(4, 0, 4, 7),
+          selectionRange: range(4, 0, 4, 1),
+        },
+      ],
+    },
+  ]);
+});
+
+Deno.test("inlay hints for matcher keys", async (t) => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text:
+          ".input {$count :number}\n.input {$kind :string}\n.match $count $kind\none a {{x}}\n2 {{y}}\n* * {{z}}",
+      },
+    },
+  );
+
+  const hint = (line: number, character: number, label: string) => ({
+    position: { line, character },
+    label,
+    kind: 2,
+    paddingRight: true,
+  });
+
+  await t.step("whole document", async () => {
+    const res = await lsp.request("textDocument/inlayHint", {
+      textDocument: { uri },
+      range: {
+        start: { line: 0, character: 0 },
+        end: { line: 5, character: 9 },
+      },
+    });
+
+    // The variant with a single key does not line up with the selectors.
+    assertEquals(res, [
+      hint(3, 0, "$count:"),
+      hint(3, 4, "$kind:"),
+      hint(5, 0, "$count:"),
+      hint(5, 2, "$kind:"),
+    ]);
+  });
+
+  await t.step("only in the requested range", async () => {
+    const res = await lsp.request("textDocument/inlayHint", {
+      textDocument: { uri },
+      range: {
+        start: { line: 5, character: 0 },
+        end: { line: 5, character: 9 },
+      },
+    });
+
+    assertEquals(res, [hint(5, 0, "$count:"), hint(5, 2, "$kind:")]);
+  });
+});
+
+Deno.test("folding ranges", async () => {
+  await using lsp = new AutoLSPTest();
+
+  await lsp.initialize();
+
+  const uri = "file:///src/main.mf2";
+
+  await lsp.notify(
+    "textDocument/didOpen",
+    {
+      textDocument: {
+        uri,
+        languageId: "mf2",
+        version: 1,
+        text:
+          ".input {$n :number}\n.match $n\none {{line one\nline two}}\n* {{x}}",
+      },
+    },
+  );
+
+  const res = await lsp.request("textDocument/foldingRange", {
+    textDocument: { uri },
+  });
+
+  assertEquals(res, [
+    { startLine: 1, endLine: 4 },
+    { startLine: 2, endLine: 3 },
+  ]);
+});
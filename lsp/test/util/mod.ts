@@ -1,4 +1,5 @@
 import {
+  ClientCapabilities,
   InitializeParams,
   Message,
   NotificationMessage,
@@ -24,6 +25,7 @@ export class LSPTest implements AsyncDisposable {
     string,
     (msg: NotificationMessage) => void
   >();
+  #notificationCollectors = new Map<string, unknown[]>();
 
   constructor() {
   }
@@ -42,21 +44,26 @@ export class LSPTest implements AsyncDisposable {
       const listener = this.#notificationListeners.get(msg.method);
       listener?.(msg);
       this.#notificationListeners.delete(msg.method);
+      this.#notificationCollectors.get(msg.method)?.push(msg.params);
     }
     return false;
   }
 
-  async initialize() {
+  async initialize(
+    initializationOptions?: unknown,
+    capabilities: ClientCapabilities = {},
+  ) {
     await this.request(
       "initialize",
       {
         processId: Deno.pid,
         rootUri: "file:///src/",
-        capabilities: {},
+        capabilities,
         clientInfo: {
           name: "lsp-test",
           version: "0.0.0",
         },
+        initializationOptions,
       } satisfies InitializeParams,
     );
     await this.notify("initialized", {});
@@ -97,6 +104,16 @@ export class LSPTest implements AsyncDisposable {
     return notification.params as NotificationParams<Method>;
   }
 
+  /**
+   * Collect the params of all notifications with the given method that are
+   * received from now on. The returned array is appended to as they arrive.
+   */
+  collectNotifications(method: string): unknown[] {
+    const collected: unknown[] = [];
+    this.#notificationCollectors.set(method, collected);
+    return collected;
+  }
+
   async [Symbol.asyncDispose]() {
     await this.request("shutdown", undefined);
     await this.notify("exit", undefined);
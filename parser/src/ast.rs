@@ -1,20 +1,26 @@
+use std::borrow::Cow;
 use std::fmt::Debug;
 
+use crate::chars;
 use crate::text::LengthShort;
 use crate::text::Location;
 use crate::text::Span;
 use crate::text::Spanned;
 use crate::visitor::Visit;
+use crate::visitor::VisitAny;
+use crate::visitor::VisitMut;
 use crate::visitor::Visitable;
+use crate::visitor::VisitableMut;
 
 macro_rules! ast_enum {
   {
-    #[visit($visit_method:ident)]
+    #[visit($visit_method:ident, $visit_mut_method:ident)]
     pub enum $name:ident<$lifetime:lifetime> {
       $( $item:ident $(<$item_lifetime:lifetime>)? ),* $(,)?
     }
   } => {
     #[derive(Clone)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize), serde(untagged))]
     pub enum $name<$lifetime> {
       $( $item ( $item$(<$item_lifetime>)? ), )*
     }
@@ -46,15 +52,234 @@ macro_rules! ast_enum {
         }
       }
     }
+
+    impl<'text> crate::visitor::VisitableMut<'text> for $name<'text> {
+      fn apply_visitor_mut<V: crate::visitor::VisitMut<'text> + ?Sized>(&mut self, visitor: &mut V) {
+        visitor.$visit_mut_method(self);
+      }
+
+      fn apply_visitor_to_children_mut<V: crate::visitor::VisitMut<'text> + ?Sized>(&mut self, visitor: &mut V) {
+        match self {
+          $( $name::$item(item) => item.apply_visitor_mut(visitor), )*
+        }
+      }
+    }
   };
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(untagged))]
 pub enum Message<'text> {
   Simple(Pattern<'text>),
   Complex(ComplexMessage<'text>),
 }
 
+impl<'text> Message<'text> {
+  /// Get all escape sequences in the message, in source text order. This
+  /// includes escapes in patterns and in quoted literals.
+  ///
+  /// ### Example
+  ///
+  /// ```rust
+  /// use mf2_parser::parse;
+  ///
+  /// let (ast, _, _) = parse(r"Hello \{ {|\|name\||}!");
+  /// let escaped = ast
+  ///   .escapes()
+  ///   .iter()
+  ///   .map(|escape| escape.escaped_char)
+  ///   .collect::<String>();
+  /// assert_eq!(escaped, "{||");
+  /// ```
+  pub fn escapes(&self) -> Vec<&Escape> {
+    struct EscapeCollector<'ast> {
+      escapes: Vec<&'ast Escape>,
+    }
+
+    impl<'ast, 'text> Visit<'ast, 'text> for EscapeCollector<'ast> {
+      fn visit_escape(&mut self, escape: &'ast Escape) {
+        self.escapes.push(escape);
+      }
+    }
+
+    let mut collector = EscapeCollector { escapes: vec![] };
+    self.apply_visitor(&mut collector);
+    collector.escapes
+  }
+
+  /// Get all function annotations in the message, in source text order. This
+  /// includes the annotations of expressions in declarations and patterns.
+  /// Markup is not a function, so it is not included.
+  ///
+  /// ### Example
+  ///
+  /// ```rust
+  /// use mf2_parser::parse;
+  ///
+  /// let (ast, _, _) =
+  ///   parse(".input {$n :number}\n{{{$n :integer} {#b}{:ns:date}{/b}}}");
+  /// let functions = ast
+  ///   .functions()
+  ///   .iter()
+  ///   .map(|function| (function.id.namespace, function.id.name))
+  ///   .collect::<Vec<_>>();
+  /// assert_eq!(
+  ///   functions,
+  ///   [(None, "number"), (None, "integer"), (Some("ns"), "date")]
+  /// );
+  /// ```
+  pub fn functions(&self) -> Vec<&Annotation<'text>> {
+    struct FunctionCollector<'ast, 'text> {
+      functions: Vec<&'ast Annotation<'text>>,
+    }
+
+    impl<'ast, 'text> Visit<'ast, 'text> for FunctionCollector<'ast, 'text> {
+      fn visit_annotation(&mut self, annotation: &'ast Annotation<'text>) {
+        self.functions.push(annotation);
+        annotation.apply_visitor_to_children(self);
+      }
+    }
+
+    let mut collector = FunctionCollector { functions: vec![] };
+    self.apply_visitor(&mut collector);
+    collector.functions
+  }
+
+  /// Get all attributes in the message, in source text order. This includes
+  /// the attributes of expressions and of markup.
+  ///
+  /// ### Example
+  ///
+  /// ```rust
+  /// use mf2_parser::parse;
+  ///
+  /// let (ast, _, _) = parse("{$name @translate=no} {#b @ns:id=|a b|}{/b}");
+  /// let attributes = ast
+  ///   .attributes()
+  ///   .iter()
+  ///   .map(|attr| (attr.name(), attr.value_text()))
+  ///   .collect::<Vec<_>>();
+  /// assert_eq!(
+  ///   attributes,
+  ///   [
+  ///     ("translate".into(), Some("no".into())),
+  ///     ("ns:id".into(), Some("a b".into())),
+  ///   ]
+  /// );
+  /// ```
+  pub fn attributes(&self) -> Vec<&Attribute<'text>> {
+    struct AttributeCollector<'ast, 'text> {
+      attributes: Vec<&'ast Attribute<'text>>,
+    }
+
+    impl<'ast, 'text> VisitAny<'ast, 'text> for AttributeCollector<'ast, 'text> {
+      fn before(&mut self, node: AnyNode<'ast, 'text>) {
+        if let AnyNode::Attribute(attribute) = node {
+          self.attributes.push(attribute);
+        }
+      }
+    }
+
+    let mut collector = AttributeCollector { attributes: vec![] };
+    self.apply_visitor(&mut collector);
+    collector.attributes
+  }
+
+  /// Count the placeholders (expressions and markup) in all patterns of the
+  /// message. For a message with a matcher, the placeholders of all variants
+  /// are counted. Expressions in declarations are not placeholders, so they
+  /// are not counted.
+  ///
+  /// ### Example
+  ///
+  /// ```rust
+  /// use mf2_parser::parse;
+  ///
+  /// let (ast, _, _) = parse(".input {$n :number}\n{{{$n} {#b}items{/b}}}");
+  /// assert_eq!(ast.placeholder_count(), 3);
+  /// ```
+  pub fn placeholder_count(&self) -> usize {
+    struct PlaceholderCounter {
+      count: usize,
+    }
+
+    impl<'ast, 'text> Visit<'ast, 'text> for PlaceholderCounter {
+      fn visit_pattern(&mut self, pattern: &'ast Pattern<'text>) {
+        self.count += placeholders(pattern).len();
+      }
+    }
+
+    let mut counter = PlaceholderCounter { count: 0 };
+    self.apply_visitor(&mut counter);
+    counter.count
+  }
+
+  /// Get all runs of human-readable text in the message, together with their
+  /// spans in the source text, in source text order. This is useful for spell
+  /// checking a message.
+  ///
+  /// The text of patterns is included, as well as the text of literal operands
+  /// of expressions (like `{|Hello world|}`). Quoted literals are decoded, so
+  /// escape sequences split a literal into multiple runs. Variables, function
+  /// annotations, attributes, markup, and the keys of variants are not text
+  /// that is displayed, so they are not included. Empty runs are skipped.
+  ///
+  /// ### Example
+  ///
+  /// ```rust
+  /// use mf2_parser::parse;
+  ///
+  /// let (ast, _, _) =
+  ///   parse(r"Helo {$name :string}, {#b}welcome{/b} {|to a\|b|}!");
+  /// let runs = ast
+  ///   .text_runs()
+  ///   .iter()
+  ///   .map(|(text, _)| *text)
+  ///   .collect::<Vec<_>>();
+  /// assert_eq!(runs, ["Helo ", ", ", "welcome", " ", "to a", "b", "!"]);
+  /// ```
+  pub fn text_runs(&self) -> Vec<(&'text str, Span)> {
+    struct TextRunCollector<'text> {
+      runs: Vec<(&'text str, Span)>,
+    }
+
+    impl<'ast, 'text> Visit<'ast, 'text> for TextRunCollector<'text> {
+      fn visit_text(&mut self, text: &'ast Text<'text>) {
+        if !text.content.is_empty() {
+          self.runs.push((text.content, text.span()));
+        }
+      }
+
+      fn visit_literal_expression(
+        &mut self,
+        expr: &'ast LiteralExpression<'text>,
+      ) {
+        expr.literal.apply_visitor(self);
+      }
+
+      fn visit_annotation_expression(
+        &mut self,
+        _expr: &'ast AnnotationExpression<'text>,
+      ) {
+      }
+
+      fn visit_variable_expression(
+        &mut self,
+        _expr: &'ast VariableExpression<'text>,
+      ) {
+      }
+
+      fn visit_markup(&mut self, _markup: &'ast Markup<'text>) {}
+
+      fn visit_key(&mut self, _key: &'ast Key<'text>) {}
+    }
+
+    let mut collector = TextRunCollector { runs: vec![] };
+    self.apply_visitor(&mut collector);
+    collector.runs
+  }
+}
+
 impl Debug for Message<'_> {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
@@ -92,13 +317,195 @@ impl<'text> Visitable<'text> for Message<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Message<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_message_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    match self {
+      Message::Simple(pattern) => pattern.apply_visitor_mut(visitor),
+      Message::Complex(complex) => complex.apply_visitor_mut(visitor),
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Pattern<'text> {
   /// Must be non-empty. Instead of an empty parts list, add a
   /// [PatternPart::Text] with an empty string.
   pub parts: Vec<PatternPart<'text>>,
 }
 
+impl Pattern<'_> {
+  /// Whether two patterns contain the same placeholders (expressions and
+  /// markup) in the same order, ignoring the text and escapes between them.
+  /// This is useful to check that a translation uses the same interpolations
+  /// as the source message.
+  ///
+  /// Placeholders are compared by value, ignoring spans and how literals are
+  /// quoted: `{|1| :number}` and `{1 :number}` are equal. The order of options
+  /// and attributes does not matter, but the number of times each of them
+  /// occurs does.
+  ///
+  /// ### Example
+  ///
+  /// ```rust
+  /// use mf2_parser::ast::Message;
+  /// use mf2_parser::parse;
+  ///
+  /// let (a, _, _) = parse("Hello {$name}, you have {$count :number}!");
+  /// let (b, _, _) = parse("{$name}: {$count :number} new messages");
+  /// let Message::Simple(a) = a else { unreachable!() };
+  /// let Message::Simple(b) = b else { unreachable!() };
+  /// assert!(a.structurally_eq(&b));
+  /// ```
+  pub fn structurally_eq(&self, other: &Pattern) -> bool {
+    let (a, b) = (placeholders(self), placeholders(other));
+
+    a.len() == b.len()
+      && a.iter().zip(&b).all(|(a, b)| match (a, b) {
+        (PatternPart::Expression(a), PatternPart::Expression(b)) => {
+          expression_eq(a, b)
+        }
+        (PatternPart::Markup(a), PatternPart::Markup(b)) => markup_eq(a, b),
+        _ => false,
+      })
+  }
+
+  /// Get the text of the pattern, with escape sequences replaced by the
+  /// character they escape, and every placeholder (expression or markup)
+  /// replaced by `placeholder`.
+  ///
+  /// ```
+  /// # use mf2_parser::ast::Message;
+  /// # use mf2_parser::parse;
+  /// let (ast, _, _) = parse(r"Hello \{ {$name}{#b}!{/b}");
+  /// let Message::Simple(pattern) = ast else {
+  ///   unreachable!()
+  /// };
+  /// assert_eq!(pattern.plain_text("_"), "Hello { __!_");
+  /// ```
+  pub fn plain_text(&self, placeholder: &str) -> String {
+    let mut text = String::new();
+    for part in &self.parts {
+      match part {
+        PatternPart::Text(part) => text.push_str(part.content),
+        PatternPart::Escape(escape) => text.push(escape.escaped_char),
+        PatternPart::Expression(_) | PatternPart::Markup(_) => {
+          text.push_str(placeholder)
+        }
+      }
+    }
+    text
+  }
+}
+
+fn placeholders<'a, 'text>(
+  pattern: &'a Pattern<'text>,
+) -> Vec<&'a PatternPart<'text>> {
+  pattern
+    .parts
+    .iter()
+    .filter(|part| {
+      !matches!(part, PatternPart::Text(_) | PatternPart::Escape(_))
+    })
+    .collect()
+}
+
+fn expression_eq(a: &Expression, b: &Expression) -> bool {
+  let operands_eq = match (a, b) {
+    (Expression::LiteralExpression(a), Expression::LiteralExpression(b)) => {
+      a.literal.value() == b.literal.value()
+    }
+    (Expression::VariableExpression(a), Expression::VariableExpression(b)) => {
+      a.variable.name == b.variable.name
+    }
+    (
+      Expression::AnnotationExpression(_),
+      Expression::AnnotationExpression(_),
+    ) => true,
+    _ => false,
+  };
+  operands_eq
+    && optional_annotation_eq(a.annotation(), b.annotation())
+    && attributes_eq(a.attributes(), b.attributes())
+}
+
+fn markup_eq(a: &Markup, b: &Markup) -> bool {
+  std::mem::discriminant(&a.kind) == std::mem::discriminant(&b.kind)
+    && identifier_eq(&a.id, &b.id)
+    && options_eq(&a.options, &b.options)
+    && attributes_eq(&a.attributes, &b.attributes)
+}
+
+fn identifier_eq(a: &Identifier, b: &Identifier) -> bool {
+  a.namespace == b.namespace && a.name == b.name
+}
+
+fn optional_annotation_eq(
+  a: Option<&Annotation>,
+  b: Option<&Annotation>,
+) -> bool {
+  match (a, b) {
+    (Some(a), Some(b)) => annotation_eq(a, b),
+    (None, None) => true,
+    _ => false,
+  }
+}
+
+fn annotation_eq(a: &Annotation, b: &Annotation) -> bool {
+  identifier_eq(&a.id, &b.id) && options_eq(&a.options, &b.options)
+}
+
+/// Options are compared as multisets, so the order does not matter but the
+/// number of times an option occurs does.
+fn options_eq(a: &[FnOrMarkupOption], b: &[FnOrMarkupOption]) -> bool {
+  /// The key, whether the value is a variable, and the value of an option.
+  type SortKey<'a> = (Option<&'a str>, &'a str, bool, Cow<'a, str>);
+
+  fn sorted<'a>(options: &'a [FnOrMarkupOption]) -> Vec<SortKey<'a>> {
+    let mut options = options
+      .iter()
+      .map(|option| {
+        let (is_variable, value) = match &option.value {
+          LiteralOrVariable::Literal(literal) => (false, literal.value()),
+          LiteralOrVariable::Variable(variable) => {
+            (true, Cow::Borrowed(variable.name))
+          }
+        };
+        (option.key.namespace, option.key.name, is_variable, value)
+      })
+      .collect::<Vec<_>>();
+    options.sort();
+    options
+  }
+  sorted(a) == sorted(b)
+}
+
+/// Attributes are compared as multisets, like in [options_eq].
+fn attributes_eq(a: &[Attribute], b: &[Attribute]) -> bool {
+  /// The key and the value of an attribute.
+  type SortKey<'a> = (Option<&'a str>, &'a str, Option<Cow<'a, str>>);
+
+  fn sorted<'a>(attributes: &'a [Attribute]) -> Vec<SortKey<'a>> {
+    let mut attributes = attributes
+      .iter()
+      .map(|attr| (attr.key.namespace, attr.key.name, attr.value_text()))
+      .collect::<Vec<_>>();
+    attributes.sort();
+    attributes
+  }
+  sorted(a) == sorted(b)
+}
+
 impl Spanned for Pattern<'_> {
   fn span(&self) -> Span {
     match (self.parts.first(), self.parts.last()) {
@@ -128,8 +535,26 @@ impl<'text> Visitable<'text> for Pattern<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Pattern<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_pattern_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    for part in &mut self.parts {
+      part.apply_visitor_mut(visitor);
+    }
+  }
+}
+
 ast_enum! {
-  #[visit(visit_pattern_part)]
+  #[visit(visit_pattern_part, visit_pattern_part_mut)]
   pub enum PatternPart<'text> {
     Text<'text>,
     Escape,
@@ -139,6 +564,7 @@ ast_enum! {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Text<'text> {
   pub start: Location,
   pub content: &'text str,
@@ -165,12 +591,49 @@ impl<'text> Visitable<'text> for Text<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Text<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_text_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    _visitor: &mut V,
+  ) {
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Escape {
   pub start: Location,
   pub escaped_char: char,
 }
 
+impl Escape {
+  /// Check if the escaped character is one of the characters that can be
+  /// escaped: `{`, `}`, `|`, and `\`. The parser keeps escapes of any other
+  /// character in the AST, and reports them with a diagnostic.
+  pub fn is_valid(&self) -> bool {
+    matches!(self.escaped_char, chars::escaped!())
+  }
+
+  /// Get the canonical source text of the escape. For a valid escape, this is
+  /// a backslash followed by the escaped character. An invalid escape has no
+  /// meaning other than the character itself, so its canonical form is just
+  /// the character, without the backslash.
+  pub fn normalized(&self) -> String {
+    if self.is_valid() {
+      format!("\\{}", self.escaped_char)
+    } else {
+      self.escaped_char.to_string()
+    }
+  }
+}
+
 impl Spanned for Escape {
   fn span(&self) -> Span {
     Span::new(self.start..self.start + '\\' + self.escaped_char)
@@ -192,8 +655,23 @@ impl<'text> Visitable<'text> for Escape {
   }
 }
 
+impl<'text> VisitableMut<'text> for Escape {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_escape_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    _visitor: &mut V,
+  ) {
+  }
+}
+
 ast_enum! {
-  #[visit(visit_expression)]
+  #[visit(visit_expression, visit_expression_mut)]
   pub enum Expression<'text> {
     LiteralExpression<'text>,
     VariableExpression<'text>,
@@ -201,7 +679,29 @@ ast_enum! {
   }
 }
 
+impl<'text> Expression<'text> {
+  /// The annotation of the expression, if it has one. This is always `Some`
+  /// for an [AnnotationExpression].
+  pub fn annotation(&self) -> Option<&Annotation<'text>> {
+    match self {
+      Expression::LiteralExpression(expr) => expr.annotation.as_ref(),
+      Expression::VariableExpression(expr) => expr.annotation.as_ref(),
+      Expression::AnnotationExpression(expr) => Some(&expr.annotation),
+    }
+  }
+
+  /// The attributes of the expression, like `@locale=en` in `{$x @locale=en}`.
+  pub fn attributes(&self) -> &[Attribute<'text>] {
+    match self {
+      Expression::LiteralExpression(expr) => &expr.attributes,
+      Expression::VariableExpression(expr) => &expr.attributes,
+      Expression::AnnotationExpression(expr) => &expr.attributes,
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct LiteralExpression<'text> {
   pub span: Span,
   pub literal: Literal<'text>,
@@ -237,7 +737,30 @@ impl<'text> Visitable<'text> for LiteralExpression<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for LiteralExpression<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_literal_expression_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.literal.apply_visitor_mut(visitor);
+    if let Some(annotation) = &mut self.annotation {
+      annotation.apply_visitor_mut(visitor);
+    }
+    for attribute in &mut self.attributes {
+      attribute.apply_visitor_mut(visitor);
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct VariableExpression<'text> {
   pub span: Span,
   pub variable: Variable<'text>,
@@ -273,7 +796,30 @@ impl<'text> Visitable<'text> for VariableExpression<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for VariableExpression<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_variable_expression_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.variable.apply_visitor_mut(visitor);
+    if let Some(annotation) = &mut self.annotation {
+      annotation.apply_visitor_mut(visitor);
+    }
+    for attribute in &mut self.attributes {
+      attribute.apply_visitor_mut(visitor);
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Variable<'text> {
   pub span: Span,
   pub name: &'text str,
@@ -306,7 +852,23 @@ impl<'text> Visitable<'text> for Variable<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Variable<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_variable_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    _visitor: &mut V,
+  ) {
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct AnnotationExpression<'text> {
   pub span: Span,
   pub annotation: Annotation<'text>,
@@ -338,13 +900,44 @@ impl<'text> Visitable<'text> for AnnotationExpression<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for AnnotationExpression<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_annotation_expression_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.annotation.apply_visitor_mut(visitor);
+    for attribute in &mut self.attributes {
+      attribute.apply_visitor_mut(visitor);
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Identifier<'text> {
   pub start: Location,
   pub namespace: Option<&'text str>,
   pub name: &'text str,
 }
 
+impl<'text> Identifier<'text> {
+  /// Get the full name of the identifier, including the namespace if there is
+  /// one, like `ns:name`.
+  pub fn full_name(&self) -> Cow<'text, str> {
+    match self.namespace {
+      Some(namespace) => Cow::Owned(format!("{namespace}:{}", self.name)),
+      None => Cow::Borrowed(self.name),
+    }
+  }
+}
+
 impl Spanned for Identifier<'_> {
   fn span(&self) -> Span {
     let mut end = self.start;
@@ -372,7 +965,23 @@ impl<'text> Visitable<'text> for Identifier<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Identifier<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_identifier_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    _visitor: &mut V,
+  ) {
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Annotation<'text> {
   pub start: Location,
   pub id: Identifier<'text>,
@@ -409,7 +1018,27 @@ impl<'text> Visitable<'text> for Annotation<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Annotation<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_annotation_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.id.apply_visitor_mut(visitor);
+    for option in &mut self.options {
+      option.apply_visitor_mut(visitor);
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct FnOrMarkupOption<'text> {
   pub key: Identifier<'text>,
   pub value: LiteralOrVariable<'text>,
@@ -440,13 +1069,45 @@ impl<'text> Visitable<'text> for FnOrMarkupOption<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for FnOrMarkupOption<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_fn_or_markup_option_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.key.apply_visitor_mut(visitor);
+    self.value.apply_visitor_mut(visitor);
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Attribute<'text> {
   pub span: Span,
   pub key: Identifier<'text>,
   pub value: Option<Literal<'text>>,
 }
 
+impl<'text> Attribute<'text> {
+  /// Get the full name of the attribute, including the namespace if there is
+  /// one.
+  pub fn name(&self) -> Cow<'text, str> {
+    self.key.full_name()
+  }
+
+  /// Get the value of the attribute as text, or `None` if the attribute has no
+  /// value. See [Literal::value].
+  pub fn value_text(&self) -> Option<Cow<'text, str>> {
+    self.value.as_ref().map(Literal::value)
+  }
+}
+
 impl Spanned for Attribute<'_> {
   fn span(&self) -> Span {
     self.span
@@ -472,8 +1133,27 @@ impl<'text> Visitable<'text> for Attribute<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Attribute<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_attribute_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.key.apply_visitor_mut(visitor);
+    if let Some(value) = &mut self.value {
+      value.apply_visitor_mut(visitor);
+    }
+  }
+}
+
 ast_enum! {
-  #[visit(visit_literal_or_variable)]
+  #[visit(visit_literal_or_variable, visit_literal_or_variable_mut)]
   pub enum LiteralOrVariable<'text> {
     Literal<'text>,
     Variable<'text>,
@@ -481,7 +1161,7 @@ ast_enum! {
 }
 
 ast_enum! {
-  #[visit(visit_literal)]
+  #[visit(visit_literal, visit_literal_mut)]
   pub enum Literal<'text> {
     Quoted<'text>,
     Text<'text>,
@@ -489,7 +1169,25 @@ ast_enum! {
   }
 }
 
+impl<'text> Literal<'text> {
+  /// Get the value of the literal as text. For quoted literals, this is the
+  /// content between the quotes with escape sequences resolved. For other
+  /// literals, this is the literal as written.
+  pub fn value(&self) -> Cow<'text, str> {
+    match self {
+      Literal::Text(text) => Cow::Borrowed(text.content),
+      Literal::Number(number) => Cow::Borrowed(number.raw),
+      Literal::Quoted(quoted) => match quoted.parts.as_slice() {
+        [] => Cow::Borrowed(""),
+        [QuotedPart::Text(text)] => Cow::Borrowed(text.content),
+        parts => Cow::Owned(crate::unescape_text_parts(parts)),
+      },
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Quoted<'text> {
   pub span: Span,
   pub parts: Vec<QuotedPart<'text>>,
@@ -519,8 +1217,26 @@ impl<'text> Visitable<'text> for Quoted<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Quoted<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_quoted_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    for part in &mut self.parts {
+      part.apply_visitor_mut(visitor);
+    }
+  }
+}
+
 ast_enum! {
-  #[visit(visit_quoted_part)]
+  #[visit(visit_quoted_part, visit_quoted_part_mut)]
   pub enum QuotedPart<'text> {
     Text<'text>,
     Escape,
@@ -528,6 +1244,7 @@ ast_enum! {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ExponentSign {
   Plus,
   Minus,
@@ -535,6 +1252,7 @@ pub enum ExponentSign {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Number<'text> {
   pub start: Location,
   pub raw: &'text str,
@@ -565,6 +1283,21 @@ impl<'text> Visitable<'text> for Number<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Number<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_number_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    _visitor: &mut V,
+  ) {
+  }
+}
+
 impl<'text> Number<'text> {
   fn slice(&self, span: Span) -> &'text str {
     &self.raw[span.start.inner() as usize - self.start.inner() as usize
@@ -628,9 +1361,157 @@ impl<'text> Number<'text> {
       .exponent_span()
       .map(|span| (self.exponent_len.as_ref().unwrap().0, self.slice(span)))
   }
+
+  /// Get the value of the number as a floating point number. Numbers that are
+  /// too large for an `f64` are infinite.
+  ///
+  /// Returns `None` if the number is missing its integral, fractional, or
+  /// exponent digits (like `-`, `1.`, or `1e`). A number with a leading zero in
+  /// its integral part (like `01`) is still an error, but has an unambiguous
+  /// value, so it is returned.
+  ///
+  /// ### Example
+  ///
+  /// ```rust
+  /// use mf2_parser::ast::Expression;
+  /// use mf2_parser::ast::Literal;
+  /// use mf2_parser::ast::Message;
+  /// use mf2_parser::ast::PatternPart;
+  /// use mf2_parser::parse;
+  ///
+  /// let (ast, _, _) = parse("{-1.5e3}");
+  /// let Message::Simple(pattern) = ast else { unreachable!() };
+  /// let PatternPart::Expression(Expression::LiteralExpression(expr)) =
+  ///   &pattern.parts[0]
+  /// else {
+  ///   unreachable!()
+  /// };
+  /// let Literal::Number(number) = &expr.literal else { unreachable!() };
+  /// assert_eq!(number.as_f64(), Some(-1500.0));
+  /// ```
+  pub fn as_f64(&self) -> Option<f64> {
+    if self.integral_len.inner() == 0
+      || self.fractional_len.is_some_and(|len| len.inner() == 0)
+      || self.exponent_len.is_some_and(|(_, len)| len.inner() == 0)
+    {
+      return None;
+    }
+    self.raw.parse().ok()
+  }
+
+  /// The largest absolute value of an exponent that
+  /// [Number::as_decimal_string] applies. Larger exponents would need a string
+  /// with an unreasonable number of zeros.
+  pub const MAX_DECIMAL_EXPONENT: i64 = 10_000;
+
+  /// Get the value of the number as a decimal string without an exponent, like
+  /// `0.0015` for `1.5e-3`. Leading zeros of the integral part and trailing
+  /// zeros of the fractional part are removed, the decimal point is omitted
+  /// for integers, and zero is never negative.
+  ///
+  /// Missing digits (like in `1.` or `1e`) are treated as zero. The exponent is
+  /// applied exactly, so the result is as long as the number of digits it
+  /// represents. Returns `None` if the absolute value of the exponent is larger
+  /// than [Number::MAX_DECIMAL_EXPONENT], like for `1e1000000`, unless the
+  /// number is zero.
+  ///
+  /// ### Example
+  ///
+  /// ```rust
+  /// use mf2_parser::ast::Expression;
+  /// use mf2_parser::ast::Literal;
+  /// use mf2_parser::ast::Message;
+  /// use mf2_parser::ast::PatternPart;
+  /// use mf2_parser::parse;
+  ///
+  /// let (ast, _, _) = parse("{-12.50e1}");
+  /// let Message::Simple(pattern) = ast else { unreachable!() };
+  /// let PatternPart::Expression(Expression::LiteralExpression(expr)) =
+  ///   &pattern.parts[0]
+  /// else {
+  ///   unreachable!()
+  /// };
+  /// let Literal::Number(number) = &expr.literal else { unreachable!() };
+  /// assert_eq!(number.as_decimal_string().unwrap(), "-125");
+  /// ```
+  pub fn as_decimal_string(&self) -> Option<String> {
+    let integral = self.integral_part();
+    let fractional = self.fractional_part().unwrap_or("");
+    let digits = format!("{integral}{fractional}");
+    // The exponent of zero does not matter, however large it is.
+    if digits.bytes().all(|b| b == b'0') {
+      return Some("0".to_owned());
+    }
+
+    let exponent = match self.exponent_part() {
+      Some((_, "")) | None => 0,
+      Some((sign, digits)) => {
+        let value = digits
+          .parse::<i64>()
+          .ok()
+          .filter(|value| *value <= Self::MAX_DECIMAL_EXPONENT)?;
+        match sign {
+          ExponentSign::Minus => -value,
+          ExponentSign::Plus | ExponentSign::None => value,
+        }
+      }
+    };
+
+    // The position of the decimal point in `digits`, after applying the
+    // exponent. It may be before the first or after the last digit.
+    let point = integral.len() as i64 + exponent;
+    let (int_part, frac_part) = if point <= 0 {
+      let zeros = "0".repeat(point.unsigned_abs() as usize);
+      (String::new(), format!("{zeros}{digits}"))
+    } else if point as usize >= digits.len() {
+      let zeros = "0".repeat(point as usize - digits.len());
+      (format!("{digits}{zeros}"), String::new())
+    } else {
+      let (int_part, frac_part) = digits.split_at(point as usize);
+      (int_part.to_owned(), frac_part.to_owned())
+    };
+
+    let int_part = int_part.trim_start_matches('0');
+    let frac_part = frac_part.trim_end_matches('0');
+    let mut out = String::new();
+    if self.is_negative && !(int_part.is_empty() && frac_part.is_empty()) {
+      out.push('-');
+    }
+    out.push_str(if int_part.is_empty() { "0" } else { int_part });
+    if !frac_part.is_empty() {
+      out.push('.');
+      out.push_str(frac_part);
+    }
+    Some(out)
+  }
+
+  /// Whether two numbers have the same value, regardless of how they are
+  /// written. For example `1`, `1.0`, and `1e0` are all equal.
+  ///
+  /// Integers (numbers without a fractional part or exponent) are compared
+  /// exactly, even if they are too large to be represented as an `f64`. Other
+  /// numbers are compared by their [Number::as_f64] value, and are never equal
+  /// if either of them is missing digits.
+  pub fn numeric_eq(&self, other: &Number) -> bool {
+    let is_integer =
+      |n: &Number| n.fractional_len.is_none() && n.exponent_len.is_none();
+    if is_integer(self) && is_integer(other) {
+      let digits = |n: &Number| {
+        let digits = n.integral_part().trim_start_matches('0');
+        // `-0` and `0` are the same value.
+        (n.is_negative && !digits.is_empty(), digits.to_owned())
+      };
+      return digits(self) == digits(other);
+    }
+    match (self.as_f64(), other.as_f64()) {
+      (Some(a), Some(b)) => a == b,
+      _ => false,
+    }
+  }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Markup<'text> {
   pub span: Span,
   pub kind: MarkupKind,
@@ -640,12 +1521,63 @@ pub struct Markup<'text> {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum MarkupKind {
   Open,
   Standalone,
   Close,
 }
 
+impl<'text> Markup<'text> {
+  /// Whether this is an opening markup tag, like `{#b}`.
+  pub fn is_open(&self) -> bool {
+    matches!(self.kind, MarkupKind::Open)
+  }
+
+  /// Whether this is a closing markup tag, like `{/b}`.
+  pub fn is_close(&self) -> bool {
+    matches!(self.kind, MarkupKind::Close)
+  }
+
+  /// Whether this is a standalone markup tag, like `{#img /}`.
+  pub fn is_standalone(&self) -> bool {
+    matches!(self.kind, MarkupKind::Standalone)
+  }
+
+  /// Get the full name of the tag, including the namespace if there is one.
+  ///
+  /// ### Example
+  ///
+  /// ```rust
+  /// use mf2_parser::ast::Message;
+  /// use mf2_parser::ast::PatternPart;
+  /// use mf2_parser::parse;
+  ///
+  /// let (ast, _, _) = parse("{#html:a href=|/|}link{/html:a}{#br /}");
+  /// let Message::Simple(pattern) = ast else { unreachable!() };
+  /// let tags = pattern
+  ///   .parts
+  ///   .iter()
+  ///   .filter_map(|part| match part {
+  ///     PatternPart::Markup(markup) => Some(markup),
+  ///     _ => None,
+  ///   })
+  ///   .map(|markup| (markup.tag_name(), markup.is_open(), markup.is_close()))
+  ///   .collect::<Vec<_>>();
+  /// assert_eq!(
+  ///   tags,
+  ///   [
+  ///     ("html:a".into(), true, false),
+  ///     ("html:a".into(), false, true),
+  ///     ("br".into(), false, false),
+  ///   ]
+  /// );
+  /// ```
+  pub fn tag_name(&self) -> Cow<'text, str> {
+    self.id.full_name()
+  }
+}
+
 impl Spanned for Markup<'_> {
   fn span(&self) -> Span {
     self.span
@@ -692,7 +1624,41 @@ impl<'text> Visitable<'text> for Markup<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Markup<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_markup_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.id.apply_visitor_mut(visitor);
+    let mut options = self.options.iter_mut().peekable();
+    let mut attributes = self.attributes.iter_mut().peekable();
+    loop {
+      let option_first = match (options.peek(), attributes.peek()) {
+        (Some(option), Some(attribute)) => {
+          option.span().end <= attribute.span().start
+        }
+        (Some(_), None) => true,
+        (None, Some(_)) => false,
+        (None, None) => break,
+      };
+      if option_first {
+        options.next().unwrap().apply_visitor_mut(visitor);
+      } else {
+        attributes.next().unwrap().apply_visitor_mut(visitor);
+      }
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct ComplexMessage<'text> {
   pub span: Span,
   pub declarations: Vec<Declaration<'text>>,
@@ -705,6 +1671,49 @@ impl Spanned for ComplexMessage<'_> {
   }
 }
 
+impl<'text> ComplexMessage<'text> {
+  /// Iterate over the top level parts of the message: each declaration in
+  /// order, and then the body. This is the order in which the printer prints
+  /// the message, even if a declaration appears after the body in the source
+  /// text.
+  ///
+  /// ```
+  /// # use mf2_parser::ast::Message;
+  /// # use mf2_parser::ast::MessageTopLevel;
+  /// # use mf2_parser::parse;
+  /// let (ast, _, _) = parse(".input {$a} .local $b = {$a} {{{$b}}}");
+  /// let Message::Complex(message) = ast else { unreachable!() };
+  /// let parts = message.parts().collect::<Vec<_>>();
+  /// assert_eq!(parts.len(), 3);
+  /// assert!(matches!(parts[0], MessageTopLevel::Declaration(_)));
+  /// assert!(matches!(parts[2], MessageTopLevel::Body(_)));
+  /// ```
+  pub fn parts(&self) -> impl Iterator<Item = MessageTopLevel<'_, 'text>> {
+    self
+      .declarations
+      .iter()
+      .map(MessageTopLevel::Declaration)
+      .chain(std::iter::once(MessageTopLevel::Body(&self.body)))
+  }
+}
+
+/// A top level part of a complex message, as returned by
+/// [ComplexMessage::parts].
+#[derive(Debug, Clone, Copy)]
+pub enum MessageTopLevel<'a, 'text> {
+  Declaration(&'a Declaration<'text>),
+  Body(&'a ComplexMessageBody<'text>),
+}
+
+impl Spanned for MessageTopLevel<'_, '_> {
+  fn span(&self) -> Span {
+    match self {
+      MessageTopLevel::Declaration(decl) => decl.span(),
+      MessageTopLevel::Body(body) => body.span(),
+    }
+  }
+}
+
 impl<'text> Visitable<'text> for ComplexMessage<'text> {
   fn apply_visitor<'ast, V: Visit<'ast, 'text> + ?Sized>(
     &'ast self,
@@ -731,8 +1740,34 @@ impl<'text> Visitable<'text> for ComplexMessage<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for ComplexMessage<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_complex_message_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    let mut visited_body = false;
+    for declaration in &mut self.declarations {
+      if !visited_body && declaration.span().start >= self.body.span().end {
+        visited_body = true;
+        self.body.apply_visitor_mut(visitor);
+      }
+      declaration.apply_visitor_mut(visitor);
+    }
+    if !visited_body {
+      self.body.apply_visitor_mut(visitor);
+    }
+  }
+}
+
 ast_enum! {
-  #[visit(visit_declaration)]
+  #[visit(visit_declaration, visit_declaration_mut)]
   pub enum Declaration<'text> {
     InputDeclaration<'text>,
     LocalDeclaration<'text>,
@@ -740,6 +1775,7 @@ ast_enum! {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct InputDeclaration<'text> {
   pub start: Location,
   pub expression: VariableExpression<'text>,
@@ -769,7 +1805,24 @@ impl<'text> Visitable<'text> for InputDeclaration<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for InputDeclaration<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_input_declaration_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.expression.apply_visitor_mut(visitor);
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct LocalDeclaration<'text> {
   pub start: Location,
   pub variable: Variable<'text>,
@@ -801,8 +1854,25 @@ impl<'text> Visitable<'text> for LocalDeclaration<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for LocalDeclaration<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_local_declaration_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.variable.apply_visitor_mut(visitor);
+    self.expression.apply_visitor_mut(visitor);
+  }
+}
+
 ast_enum! {
-  #[visit(visit_complex_message_body)]
+  #[visit(visit_complex_message_body, visit_complex_message_body_mut)]
   pub enum ComplexMessageBody<'text> {
     QuotedPattern<'text>,
     Matcher<'text>,
@@ -810,6 +1880,7 @@ ast_enum! {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct QuotedPattern<'text> {
   pub span: Span,
   pub pattern: Pattern<'text>,
@@ -837,13 +1908,79 @@ impl<'text> Visitable<'text> for QuotedPattern<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for QuotedPattern<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_quoted_pattern_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    self.pattern.apply_visitor_mut(visitor);
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Matcher<'text> {
   pub start: Location,
   pub selectors: Vec<Variable<'text>>,
   pub variants: Vec<Variant<'text>>,
 }
 
+impl Matcher<'_> {
+  /// The location right after the last selector, or right after the
+  /// `.match` keyword if there are no selectors. A new selector can be
+  /// inserted here (prefixed with a space).
+  pub fn selectors_end_location(&self) -> Location {
+    self
+      .selectors
+      .last()
+      .map(|last| last.span().end)
+      .unwrap_or_else(|| self.start + ".match")
+  }
+
+  /// Get a summary of the shape of the matcher: how many selectors and
+  /// variants it has, whether it has a catch-all variant, and the width of
+  /// its widest key.
+  pub fn shape(&self) -> MatcherShape {
+    MatcherShape {
+      selectors: self.selectors.len(),
+      variants: self.variants.len(),
+      has_fallback: self.variants.iter().any(Variant::is_catch_all),
+      max_key_width: self
+        .variants
+        .iter()
+        .flat_map(|v| &v.keys)
+        .map(|key| match key {
+          Key::Literal(literal) => literal.value().chars().count(),
+          Key::Star(_) => 1,
+        })
+        .max()
+        .unwrap_or(0),
+    }
+  }
+}
+
+/// A summary of the shape of a [Matcher], returned by [Matcher::shape].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatcherShape {
+  /// The number of selectors.
+  pub selectors: usize,
+  /// The number of variants.
+  pub variants: usize,
+  /// Whether any variant is a catch-all variant (see [Variant::is_catch_all]).
+  pub has_fallback: bool,
+  /// The number of characters of the widest key, or 0 if there are no keys.
+  /// Keys are measured by their value, so the quotes of a quoted key and the
+  /// backslashes of its escapes are not counted. A `*` key is 1 wide.
+  pub max_key_width: usize,
+}
+
 impl Spanned for Matcher<'_> {
   fn span(&self) -> Span {
     let start = self.start;
@@ -883,12 +2020,58 @@ impl<'text> Visitable<'text> for Matcher<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Matcher<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_matcher_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    for selector in &mut self.selectors {
+      selector.apply_visitor_mut(visitor);
+    }
+    for variant in &mut self.variants {
+      variant.apply_visitor_mut(visitor);
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Variant<'text> {
   pub keys: Vec<Key<'text>>,
   pub pattern: QuotedPattern<'text>,
 }
 
+impl Variant<'_> {
+  /// Whether this is a catch-all variant, where all keys are `*`. A variant
+  /// without any keys (which is an error) is also considered a catch-all.
+  pub fn is_catch_all(&self) -> bool {
+    self.keys.iter().all(|key| matches!(key, Key::Star(_)))
+  }
+
+  /// The span from the start of the first key to the end of the last key. If
+  /// the variant has no keys, this is an empty span at the start of the
+  /// pattern. A new key can be inserted at the end of this span (prefixed with
+  /// a space).
+  pub fn keys_region(&self) -> Span {
+    match (self.keys.first(), self.keys.last()) {
+      (Some(first), Some(last)) => {
+        Span::new(first.span().start..last.span().end)
+      }
+      _ => {
+        let start = self.pattern.span().start;
+        Span::new(start..start)
+      }
+    }
+  }
+}
+
 impl Spanned for Variant<'_> {
   fn span(&self) -> Span {
     let start = self
@@ -920,8 +2103,27 @@ impl<'text> Visitable<'text> for Variant<'text> {
   }
 }
 
+impl<'text> VisitableMut<'text> for Variant<'text> {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_variant_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    for key in &mut self.keys {
+      key.apply_visitor_mut(visitor);
+    }
+    self.pattern.apply_visitor_mut(visitor);
+  }
+}
+
 ast_enum! {
-  #[visit(visit_key)]
+  #[visit(visit_key, visit_key_mut)]
   pub enum Key<'text> {
     Literal<'text>,
     Star,
@@ -929,6 +2131,7 @@ ast_enum! {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub struct Star {
   pub start: Location,
 }
@@ -954,8 +2157,24 @@ impl<'text> Visitable<'text> for Star {
   }
 }
 
+impl<'text> VisitableMut<'text> for Star {
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  ) {
+    visitor.visit_star_mut(self);
+  }
+
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    _visitor: &mut V,
+  ) {
+  }
+}
+
 macro_rules! any_node {
     (
+      #[kind($kind:ident)]
       pub enum $name:ident<$ast_lifetime:lifetime, $text_lifetime:lifetime> {
         $( $item:ident $(<$item_lifetime:lifetime>)? ),* $(,)?
       }
@@ -965,13 +2184,52 @@ macro_rules! any_node {
         $( $item ( &'ast $item$(<$item_lifetime>)? ), )*
       }
 
+      /// The kind of an [AnyNode], without a reference to the node itself.
+      /// This is useful to cheaply compare or filter nodes by their type.
+      ///
+      /// ### Example
+      ///
+      /// ```rust
+      /// use mf2_parser::ast::*;
+      /// use mf2_parser::VisitAny;
+      /// use mf2_parser::Visitable as _;
+      /// use mf2_parser::parse;
+      ///
+      /// struct VariableCollector<'text>(Vec<&'text str>);
+      ///
+      /// impl<'ast, 'text: 'ast> VisitAny<'ast, 'text> for VariableCollector<'text> {
+      ///   fn before(&mut self, node: AnyNode<'ast, 'text>) {
+      ///     if let AnyNode::Variable(var) = node {
+      ///       assert_eq!(node.kind(), AnyNodeKind::Variable);
+      ///       self.0.push(var.name);
+      ///     }
+      ///   }
+      /// }
+      ///
+      /// let (ast, _, _) = parse("Hello, {$name} and {$other}!");
+      /// let mut visitor = VariableCollector(vec![]);
+      /// ast.apply_visitor(&mut visitor);
+      /// assert_eq!(visitor.0, vec!["name", "other"]);
+      /// ```
+      #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+      pub enum $kind {
+        $( $item, )*
+      }
+
       impl $name<'_, '_> {
         pub fn same(&self, other: &Self) -> bool {
           match (self, other) {
-            $( ($name::$item(a), $name::$item(b)) => ::std::ptr::eq(a as *const _, b as *const _), )*
+            $( ($name::$item(a), $name::$item(b)) => ::std::ptr::eq(*a, *b), )*
             _ => false,
           }
         }
+
+        /// Get the kind of this node.
+        pub fn kind(&self) -> $kind {
+          match self {
+            $( $name::$item(_) => $kind::$item, )*
+          }
+        }
       }
 
       impl ::std::fmt::Debug for $name<'_, '_> {
@@ -1001,6 +2259,7 @@ macro_rules! any_node {
 }
 
 any_node! {
+  #[kind(AnyNodeKind)]
   pub enum AnyNode<'ast, 'text> {
     Message<'text>,
     Pattern<'text>,
@@ -1034,3 +2293,393 @@ any_node! {
     Star,
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parse;
+
+  fn parse_matcher(message: &str) -> Matcher {
+    let (ast, _, _) = parse(message);
+    let Message::Complex(complex) = ast else {
+      panic!("expected a complex message");
+    };
+    let ComplexMessageBody::Matcher(matcher) = complex.body else {
+      panic!("expected a matcher");
+    };
+    matcher
+  }
+
+  fn range(span: Span) -> (u32, u32) {
+    (
+      span.start.inner_byte_index_for_test(),
+      span.end.inner_byte_index_for_test(),
+    )
+  }
+
+  fn parse_pattern(message: &str) -> Pattern {
+    let (ast, _, _) = parse(message);
+    let Message::Simple(pattern) = ast else {
+      panic!("expected a simple message");
+    };
+    pattern
+  }
+
+  #[test]
+  fn pattern_structurally_eq() {
+    let eq =
+      |a: &str, b: &str| parse_pattern(a).structurally_eq(&parse_pattern(b));
+
+    assert!(eq("Hello {$a}!", "Hallo  {$a}"));
+    assert!(eq("a \\{ b", "c"));
+    assert!(eq(
+      "{|1| :number minimumFractionDigits=2 style=percent @a}",
+      "x {1   :number style=|percent| minimumFractionDigits=2 @a} y"
+    ));
+    assert!(eq("{#b}{$a}{/b}", "{#b} {$a} {/b}"));
+
+    assert!(!eq("{$a} {$b}", "{$b} {$a}"));
+    assert!(!eq("{$a}", "{$a} {$a}"));
+    assert!(!eq("{$a :number}", "{$a :integer}"));
+    assert!(!eq("{$a :number}", "{$a}"));
+    assert!(!eq("{$a @x}", "{$a @y}"));
+    assert!(!eq("{:fn a=1}", "{:fn a=2}"));
+    assert!(!eq("{:fn a=1}", "{:fn a=$a}"));
+    // Duplicate keys are counted, not just looked up.
+    assert!(!eq("{:fn a=1 a=1}", "{:fn a=1 b=2}"));
+    assert!(!eq("{:fn a=1 b=2}", "{:fn a=1 a=1}"));
+    assert!(eq("{:fn a=1 b=2 a=1}", "{:fn a=1 a=1 b=2}"));
+    assert!(!eq("{$a @x @x}", "{$a @x @y}"));
+    assert!(!eq("{#b x=1 x=1}", "{#b x=1 y=1}"));
+    assert!(!eq("{#b}", "{/b}"));
+    assert!(!eq("{#b}", "{|b|}"));
+  }
+
+  fn parse_number(message: &str) -> Number {
+    let pattern = parse_pattern(message);
+    let Some(PatternPart::Expression(Expression::LiteralExpression(expr))) =
+      pattern.parts.into_iter().next()
+    else {
+      panic!("expected a literal expression");
+    };
+    let Literal::Number(number) = expr.literal else {
+      panic!("expected a number literal");
+    };
+    number
+  }
+
+  #[test]
+  fn number_numeric_eq() {
+    let eq = |a: &str, b: &str| {
+      let (a, b) = (format!("{{{a}}}"), format!("{{{b}}}"));
+      parse_number(&a).numeric_eq(&parse_number(&b))
+    };
+
+    assert!(eq("1", "1"));
+    assert!(eq("1", "1.0"));
+    assert!(eq("1", "1e0"));
+    assert!(eq("1.0", "1e0"));
+    assert!(eq("10", "1e1"));
+    assert!(eq("0.5", "5e-1"));
+    assert!(eq("0", "-0"));
+    assert!(eq(
+      "123456789012345678901234567890",
+      "123456789012345678901234567890"
+    ));
+
+    assert!(!eq("1", "2"));
+    assert!(!eq("1", "-1"));
+    assert!(!eq("1.5", "1"));
+    assert!(!eq(
+      "123456789012345678901234567890",
+      "123456789012345678901234567891"
+    ));
+  }
+
+  #[test]
+  fn number_as_f64() {
+    let value = |n: &str| parse_number(&format!("{{{n}}}")).as_f64();
+
+    assert_eq!(value("0"), Some(0.0));
+    assert_eq!(value("-12.5"), Some(-12.5));
+    assert_eq!(value("1.5e-3"), Some(0.0015));
+    assert_eq!(value("1.5E+3"), Some(1500.0));
+    assert_eq!(value("-2e2"), Some(-200.0));
+    assert_eq!(value("1e400"), Some(f64::INFINITY));
+    // Leading zeros are an error, but do not make the value ambiguous.
+    assert_eq!(value("007"), Some(7.0));
+    assert_eq!(value("-01.50"), Some(-1.5));
+
+    assert_eq!(value("-"), None);
+    assert_eq!(value("-.5"), None);
+    assert_eq!(value("1."), None);
+    assert_eq!(value("1e"), None);
+    assert_eq!(value("1.5e-"), None);
+  }
+
+  #[test]
+  fn number_as_decimal_string() {
+    let value = |n: &str| {
+      parse_number(&format!("{{{n}}}"))
+        .as_decimal_string()
+        .unwrap()
+    };
+
+    assert_eq!(value("0"), "0");
+    assert_eq!(value("-0"), "0");
+    assert_eq!(value("-0.000"), "0");
+    assert_eq!(value("12"), "12");
+    assert_eq!(value("-12.50"), "-12.5");
+    assert_eq!(value("1.5e-3"), "0.0015");
+    assert_eq!(value("-1.5e-3"), "-0.0015");
+    assert_eq!(value("1.5E+3"), "1500");
+    assert_eq!(value("123e-1"), "12.3");
+    assert_eq!(value("123e-3"), "0.123");
+    assert_eq!(value("0.001e3"), "1");
+    assert_eq!(value("1e0"), "1");
+    assert_eq!(
+      value("123456789012345678901234567890"),
+      "123456789012345678901234567890"
+    );
+    // Leading zeros are an error, but do not make the value ambiguous.
+    assert_eq!(value("007"), "7");
+    assert_eq!(value("-00.10"), "-0.1");
+    // Missing digits are treated as zero.
+    assert_eq!(value("1."), "1");
+    assert_eq!(value("1e"), "1");
+    assert_eq!(value("-.5"), "-0.5");
+  }
+
+  #[test]
+  fn number_as_decimal_string_huge_exponent() {
+    let value = |n: &str| parse_number(&format!("{{{n}}}")).as_decimal_string();
+
+    assert_eq!(value("1e99999999999999999999"), None);
+    assert_eq!(value("1e-99999999999999999999"), None);
+    assert_eq!(value("1e1000000000"), None);
+    assert_eq!(value("-1.5e-1000000000"), None);
+    assert_eq!(value("1e10001"), None);
+    assert_eq!(value("1e10000").map(|s| s.len()), Some(10_001));
+    assert_eq!(value("1e-10000").map(|s| s.len()), Some(10_002));
+    // Zero has the same value for every exponent.
+    assert_eq!(value("0e99999999999999999999").as_deref(), Some("0"));
+    assert_eq!(value("-0.0e-1000000000").as_deref(), Some("0"));
+  }
+
+  #[test]
+  fn expression_annotation_and_attributes() {
+    let pattern = parse_pattern("{1 :number @a} {$x @b @c} {:fn} {$y}");
+    let expressions = pattern
+      .parts
+      .iter()
+      .filter_map(|part| match part {
+        PatternPart::Expression(expr) => Some(expr),
+        _ => None,
+      })
+      .collect::<Vec<_>>();
+    let summary = expressions
+      .iter()
+      .map(|expr| {
+        let annotation = expr.annotation().map(|ann| ann.id.name);
+        let attributes = expr
+          .attributes()
+          .iter()
+          .map(|attr| attr.key.name)
+          .collect::<Vec<_>>();
+        (annotation, attributes)
+      })
+      .collect::<Vec<_>>();
+
+    assert_eq!(
+      summary,
+      vec![
+        (Some("number"), vec!["a"]),
+        (None, vec!["b", "c"]),
+        (Some("fn"), vec![]),
+        (None, vec![]),
+      ]
+    );
+  }
+
+  #[test]
+  fn message_placeholder_count() {
+    let count = |message: &str| parse(message).0.placeholder_count();
+
+    assert_eq!(count("Hello"), 0);
+    assert_eq!(count("Hello {$a} and {|b|}!"), 2);
+    assert_eq!(count("{#a}{#b/}\\{{/a}"), 3);
+    assert_eq!(count(".local $a = {1} {{{$a}}}"), 1);
+    assert_eq!(
+      count(".input {$a :x}\n.match $a\n1 {{{$a} {$a}}}\n* {{{#b}}}"),
+      3
+    );
+  }
+
+  #[test]
+  fn matcher_selectors_end_location() {
+    let matcher = parse_matcher(".match $a  $b\n1 2 {{}}\n* * {{}}");
+    assert_eq!(matcher.selectors_end_location(), Location::new_for_test(13));
+
+    let matcher = parse_matcher(".match\n* {{}}");
+    assert_eq!(matcher.selectors_end_location(), Location::new_for_test(6));
+  }
+
+  #[test]
+  fn variant_is_catch_all() {
+    let matcher = parse_matcher(".match $a $b\n1 * {{}}\n* |*| {{}}\n* * {{}}");
+    let catch_all = matcher
+      .variants
+      .iter()
+      .map(Variant::is_catch_all)
+      .collect::<Vec<_>>();
+    assert_eq!(catch_all, vec![false, false, true]);
+  }
+
+  #[test]
+  fn matcher_shape() {
+    let matcher =
+      parse_matcher(".match $a $b\n1 one {{}}\n1 |a\\|bc| {{}}\n* 1 {{}}");
+    assert_eq!(
+      matcher.shape(),
+      MatcherShape {
+        selectors: 2,
+        variants: 3,
+        has_fallback: false,
+        max_key_width: 4,
+      }
+    );
+    let matcher = parse_matcher(".match $a\n* {{}}");
+    assert!(matcher.shape().has_fallback);
+  }
+
+  #[test]
+  fn variant_keys_region() {
+    let matcher = parse_matcher(".match $a $b\n1  |two| {{}}\n* * {{}}");
+    assert_eq!(range(matcher.variants[0].keys_region()), (13, 21));
+    assert_eq!(range(matcher.variants[1].keys_region()), (27, 30));
+
+    let matcher = parse_matcher(".match $a\n{{}}");
+    assert_eq!(range(matcher.variants[0].keys_region()), (10, 10));
+  }
+
+  #[test]
+  fn escape_validity() {
+    let (ast, _, _) = parse(r"\{ \a {|\|\b|}");
+    let escapes = ast.escapes();
+    let valid = escapes.iter().map(|e| e.is_valid()).collect::<Vec<_>>();
+    assert_eq!(valid, vec![true, false, true, false]);
+    let normalized = escapes.iter().map(|e| e.normalized()).collect::<String>();
+    assert_eq!(normalized, r"\{a\|b");
+  }
+
+  #[test]
+  fn text_runs() {
+    let (ast, _, _) = parse(
+      ".local $x = {|Hi there| @a=|b|}\n.match $x\n|one two| {{A {|c d| :f o=|e|}}}\n* {{}}",
+    );
+    let runs = ast
+      .text_runs()
+      .into_iter()
+      .map(|(text, span)| (text, range(span)))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      runs,
+      vec![("Hi there", (14, 22)), ("A ", (54, 56)), ("c d", (58, 61))]
+    );
+  }
+
+  #[test]
+  fn visit_mut_modifies_nodes_in_source_order() {
+    struct Normalize {
+      visited: Vec<String>,
+    }
+
+    impl<'text> VisitMut<'text> for Normalize {
+      fn visit_identifier_mut(&mut self, id: &mut Identifier<'text>) {
+        self.visited.push(id.name.to_string());
+        if id.name == "NUMBER" {
+          id.name = "number";
+        }
+      }
+
+      fn visit_variable_mut(&mut self, var: &mut Variable<'text>) {
+        self.visited.push(format!("${}", var.name));
+        var.name = "y";
+      }
+    }
+
+    let (mut ast, _, _) = parse(
+      ".input {$x :NUMBER}\n.match $x\none {{{#b a=$x @c} {$x :NUMBER}}}\n* {{}}",
+    );
+    let mut visitor = Normalize { visited: vec![] };
+    ast.apply_visitor_mut(&mut visitor);
+    assert_eq!(
+      visitor.visited,
+      ["$x", "NUMBER", "$x", "b", "a", "$x", "c", "$x", "NUMBER"]
+    );
+
+    let functions = ast
+      .functions()
+      .iter()
+      .map(|function| function.id.name)
+      .collect::<Vec<_>>();
+    assert_eq!(functions, ["number", "number"]);
+    let Message::Complex(complex) = &ast else {
+      panic!("expected a complex message");
+    };
+    let ComplexMessageBody::Matcher(matcher) = &complex.body else {
+      panic!("expected a matcher");
+    };
+    assert_eq!(matcher.selectors[0].name, "y");
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serialize() {
+    let (ast, _, _) = parse("Hi {$x :number} {#b/}");
+    assert_eq!(
+      serde_json::to_value(&ast).unwrap(),
+      serde_json::json!({
+        "type": "Pattern",
+        "parts": [
+          { "type": "Text", "start": 0, "content": "Hi " },
+          {
+            "type": "VariableExpression",
+            "span": { "start": 3, "end": 15 },
+            "variable": {
+              "type": "Variable",
+              "span": { "start": 4, "end": 6 },
+              "name": "x",
+            },
+            "annotation": {
+              "type": "Annotation",
+              "start": 7,
+              "id": {
+                "type": "Identifier",
+                "start": 8,
+                "namespace": null,
+                "name": "number",
+              },
+              "options": [],
+            },
+            "attributes": [],
+          },
+          { "type": "Text", "start": 15, "content": " " },
+          {
+            "type": "Markup",
+            "span": { "start": 16, "end": 21 },
+            "kind": "Standalone",
+            "id": {
+              "type": "Identifier",
+              "start": 18,
+              "namespace": null,
+              "name": "b",
+            },
+            "options": [],
+            "attributes": [],
+          },
+        ],
+      })
+    );
+  }
+}
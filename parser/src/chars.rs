@@ -14,6 +14,13 @@ macro_rules! space {
 }
 pub(crate) use space;
 
+macro_rules! escaped {
+  () => {
+    '{' | '}' | '|' | '\\'
+  };
+}
+pub(crate) use escaped;
+
 macro_rules! name_start {
   () => {
     'a'..='z' | 'A'..='Z' | '_' |
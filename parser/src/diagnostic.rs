@@ -9,6 +9,20 @@ use crate::Location;
 use crate::Span;
 use crate::Spanned as _;
 
+/// Get the code of a diagnostic variant at compile time. See
+/// [Diagnostic::code].
+macro_rules! diagnostic_code {
+  ($variant:ident) => {{
+    const NAME: &str = stringify!($variant);
+    const CODE: [u8; kebab_case_len(NAME)] = kebab_case(NAME);
+    const CODE_STR: &str = match std::str::from_utf8(&CODE) {
+      Ok(code) => code,
+      Err(_) => panic!("diagnostic code is not valid UTF-8"),
+    };
+    CODE_STR
+  }};
+}
+
 macro_rules! diagnostics {
   (
     pub enum $name:ident<$life:lifetime> {
@@ -16,7 +30,10 @@ macro_rules! diagnostics {
         message: ($($message:expr),*$(,)?),
         span: $span:expr,
         fatal: $fatal:expr,
+        category: $category:ident,
+        $(severity: $severity:expr,)?
         fixes: [$({ label: $label:expr, fix($($this:tt $(, $info:tt)?)?) $fix:block }),* $(,)?] $(,)?
+        $(related: $related:block $(,)?)?
       }), *$(,)?
     }
   ) => {
@@ -43,6 +60,32 @@ macro_rules! diagnostics {
         }
       }
 
+      /// Get a stable identifier for the kind of the diagnostic, like
+      /// `"number-missing-integral-part"`. This is the name of the variant in
+      /// kebab-case, and can be used by tools to filter diagnostics.
+      pub fn code(&self) -> &'static str {
+        match self {
+          $(Self::$variant { .. } => diagnostic_code!($variant),)*
+        }
+      }
+
+      /// Get the codes of all kinds of diagnostics.
+      #[cfg(test)]
+      pub(crate) fn all_codes() -> Vec<&'static str> {
+        vec![$(diagnostic_code!($variant)),*]
+      }
+
+      /// Get the code, category, and severity of all kinds of diagnostics.
+      #[cfg(test)]
+      pub(crate) fn all_classifications(
+      ) -> Vec<(&'static str, DiagnosticCategory, Severity)> {
+        vec![$({
+          let severity = Severity::Error;
+          $(let severity = $severity;)?
+          (diagnostic_code!($variant), DiagnosticCategory::$category, severity)
+        }),*]
+      }
+
       /// Get a human-readable message describing the diagnostic.
       pub fn message(&self) -> String {
         match self {
@@ -64,6 +107,40 @@ macro_rules! diagnostics {
         }
       }
 
+      /// Get the category of the diagnostic.
+      pub fn category(&self) -> DiagnosticCategory {
+        match self {
+          $(Self::$variant { .. } => DiagnosticCategory::$category,)*
+        }
+      }
+
+      /// Get the severity of the diagnostic. Diagnostics that do not specify a
+      /// severity, which are all syntax and semantic errors, are errors. Lints
+      /// are warnings or infos.
+      pub fn severity(&self) -> Severity {
+        match self {
+          $(Self::$variant { .. } => {
+            let severity = Severity::Error;
+            $(let severity = $severity;)?
+            severity
+          },)*
+        }
+      }
+
+      /// Get a list of secondary spans that are related to the diagnostic,
+      /// together with a label describing each span. For example, a duplicate
+      /// declaration diagnostic has the span of the first declaration as a
+      /// related span.
+      pub fn related_spans(&self) -> Vec<(Span, String)> {
+        match self {
+          $(Self::$variant { $($field),* } => {
+            let related: Vec<(Span, String)> = vec![];
+            $(let related = $related;)?
+            related
+          },)*
+        }
+      }
+
       /// Get a list of fixes that can be applied to the source text to resolve
       /// the diagnostic. Each fix has a label that describes the fix, and a list
       /// of edits that describe the changes to make to the source text if the
@@ -79,10 +156,66 @@ macro_rules! diagnostics {
           }),*],)*
         }
       }
+
+      /// Build a placeholder diagnostic of the kind with the given name (the
+      /// name of the enum variant, like `"EscapeInvalidCharacter"`), located at
+      /// the given span. Returns `None` if there is no diagnostic with that
+      /// name.
+      ///
+      /// Any AST nodes carried by the diagnostic are replaced by placeholder
+      /// nodes that start at `span.start`. This means that the span of the
+      /// returned diagnostic is only guaranteed to match `span` for diagnostics
+      /// that do not carry AST nodes.
+      ///
+      /// This is only intended for use in tests of code that consumes
+      /// diagnostics, and is only available with the `testing` feature.
+      #[cfg(feature = "testing")]
+      pub fn for_test_with_span(name: &str, span: Span) -> Option<Self> {
+        use testing::Placeholder as _;
+        match name {
+          $(stringify!($variant) => Some(Self::$variant {
+            $($field: <$ty>::placeholder(span)),*
+          }),)*
+          _ => None,
+        }
+      }
     }
   };
 }
 
+/// Get the length of the kebab-case form of a PascalCase name.
+const fn kebab_case_len(name: &str) -> usize {
+  let bytes = name.as_bytes();
+  let mut len = bytes.len();
+  let mut i = 1;
+  while i < bytes.len() {
+    if bytes[i].is_ascii_uppercase() {
+      len += 1;
+    }
+    i += 1;
+  }
+  len
+}
+
+/// Convert a PascalCase name to kebab-case, like `NumberMissingIntegralPart`
+/// to `number-missing-integral-part`. `N` must be [kebab_case_len] of the name.
+const fn kebab_case<const N: usize>(name: &str) -> [u8; N] {
+  let bytes = name.as_bytes();
+  let mut out = [0; N];
+  let mut i = 0;
+  let mut j = 0;
+  while i < bytes.len() {
+    if bytes[i].is_ascii_uppercase() && i > 0 {
+      out[j] = b'-';
+      j += 1;
+    }
+    out[j] = bytes[i].to_ascii_lowercase();
+    i += 1;
+    j += 1;
+  }
+  out
+}
+
 diagnostics! {
   pub enum Diagnostic<'text> {
     // Syntax Errors
@@ -90,6 +223,7 @@ diagnostics! {
       message: ("Number is missing an integral part."),
       span: number.span(),
       fatal: false,
+      category: Syntax,
       fixes: [
         // TODO: this should only be activated if the number has a fractional part
         // {
@@ -107,6 +241,8 @@ diagnostics! {
       message: ("Number has leading zero in integral part, which is not allowed."),
       span: number.span(),
       fatal: false,
+      category: Lint,
+      severity: Severity::Warning,
       fixes: [
         {
           label: "Remove leading zeros",
@@ -136,6 +272,7 @@ diagnostics! {
       message: ("Number is missing a fractional part, which it must have because it has a decimal point."),
       span: number.span(),
       fatal: false,
+      category: Syntax,
       fixes: [
         {
           label: "Add 0 after the decimal point",
@@ -161,6 +298,7 @@ diagnostics! {
       message: ("Number is missing an exponent part, which it must have because it is written in scientific notation."),
       span: number.span(),
       fatal: false,
+      category: Syntax,
       fixes: [{
         label: "Remove the 'e'",
         fix() {
@@ -175,30 +313,35 @@ diagnostics! {
       message: ("Found equals sign followed by value, but equals sign is not preceeded by a key. Did you forget to add a key to make this an option?"),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     OptionMissingValue { span: Span } => {
       message: ("Found an identifier followed by an equals sign, but not followed by a value. Did you forget to add a value to make this an option?"),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     LoneEqualsSign { loc: Location } => {
       message: ("Found an equals sign without a key or value. Did you mean to add a key and value to make this an option?"),
       span: Span { start: *loc, end: *loc + '=' },
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     MarkupMissingIdentifier { span: Span } => {
       message: ("Markup tag is missing an identifier."),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     MarkupInvalidSpaceBeforeIdentifier { id: Identifier<'text>, start_loc: Location } => {
       message: ("Identifier of the markup tag is preceeded by spaces, which is not allowed."),
       span: Span { start: *start_loc + '#', end: id.span().start },
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Remove space before identifier",
         fix(this) {
@@ -213,12 +356,14 @@ diagnostics! {
       message: ("Markup tag is not closed with a closing brace."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     MarkupCloseInvalidSelfClose { self_close_loc: Location } => {
       message: ("Markup tag can not be self-closing if it is a close tag."),
       span: Span::new(*self_close_loc..(*self_close_loc + '/')),
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Remove self-closing slash",
         fix(this) {
@@ -233,6 +378,7 @@ diagnostics! {
       message: ("Self-closing tag of a markup tag can not have trailing spaces before the closing brace."),
       span: *space,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Remove space before closing brace",
         fix() {
@@ -247,6 +393,7 @@ diagnostics! {
       message: ("Markup tag has an option after an attribute, which is not allowed. All options must come before any attribute."),
       span: option.span(),
       fatal: false,
+      category: Syntax,
       fixes: [{
         label: "Move option before attribute",
         fix(_, info) {
@@ -263,35 +410,58 @@ diagnostics! {
           ]
         }
       }],
+      related: {
+        vec![(previous_attribute.span(), "Option is after this attribute.".to_owned())]
+      },
+    },
+    FunctionInvalidSpaceBeforeIdentifier { id: Identifier<'text>, start_loc: Location } => {
+      message: ("Identifier of the function is preceeded by spaces, which is not allowed."),
+      span: Span { start: *start_loc + ':', end: id.span().start },
+      fatal: true,
+      category: Syntax,
+      fixes: [{
+        label: "Remove space before identifier",
+        fix(this) {
+          vec![DiagnosticEdit {
+            span: this.span(),
+            new_text: "".to_string(),
+          }]
+        }
+      }],
     },
     FunctionMissingIdentifier { span: Span } => {
       message: ("Function is missing an identifier."),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     UnterminatedQuoted { span: Span } => {
       message: ("Quoted string is missing the closing quote."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     PlaceholderMissingClosingBrace { span: Span } => {
       message: ("Placeholder is missing the closing brace."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     PlaceholderMissingBody { span: Span } => {
       message: ("Placeholder is empty, but should have at least a variable reference, literal, or annotation."),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     PlaceholderInvalidLiteral { span: Span } => {
       message: ("Placeholder expression contains a literal that is not valid when unquoted. Did you mean to quote it?"),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [{
         label: "Quote literal",
         fix() {
@@ -312,12 +482,14 @@ diagnostics! {
       message: ("Placeholder expression contains invalid content."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     QuotedPatternInsidePattern { open_span: Span, close_span: Option<Span> } => {
       message: ("Quoted pattern is not allowed inside of a pattern."),
       span: *open_span,
       fatal: false,
+      category: Syntax,
       fixes: [{
         label: "Remove quotes",
         fix() {
@@ -336,29 +508,40 @@ diagnostics! {
           fixes
         }
       }],
+      related: {
+        close_span
+          .iter()
+          .map(|close_span| (*close_span, "Quoted pattern is closed here.".to_owned()))
+          .collect()
+      },
     },
     MarkupInvalidContents { span: Span } => {
       message: ("Markup tag contains invalid content."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     MissingIdentifierName { identifier: Identifier<'text> } => {
       message: ("Namespaced identifier is missing a name, which is required after the colon following the namespace."),
       span: identifier.span(),
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     MissingIdentifierNamespace { identifier: Identifier<'text> } => {
       message: ("Identifiers with a colon before the name are namespaced identifiers, but this identifier is missing a namespace before the colon."),
       span: identifier.span(),
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     EscapeInvalidCharacter { slash_loc: Location, char: char } => {
       message: ( "The character '{char}' can not be escaped, as escape sequences can only escape '}}', '{{', '|', and '\\'."),
       span: Span::new(*slash_loc..(*slash_loc + '\\' + *char)),
       fatal: false,
+      category: Lint,
+      severity: Severity::Warning,
       fixes: [{
         label: "Remove backslash",
         fix() {
@@ -369,16 +552,34 @@ diagnostics! {
         }
       }],
     },
+    UnnecessaryEscape { span: Span, char: char } => {
+      message: ("The character '{char}' does not need to be escaped here, as it is allowed unescaped in this context."),
+      span: *span,
+      fatal: false,
+      category: Lint,
+      severity: Severity::Info,
+      fixes: [{
+        label: "Remove backslash",
+        fix() {
+          vec![DiagnosticEdit {
+            span: Span::new(span.start..span.start + '\\'),
+            new_text: "".to_string(),
+          }]
+        }
+      }],
+    },
     EscapeMissingCharacter { slash_loc: Location } => {
       message: ("Backslashes start an escape sequence, but no character to be escaped was found. A literal '\\' must be written as '\\\\'."),
       span: Span::new(*slash_loc..(*slash_loc + '\\')),
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     InvalidNullCharacter { char_loc: Location } => {
       message: ("The NULL character (0x00) is invalid anywhere inside of messages."),
       span: Span::new(*char_loc..(*char_loc + '\0')),
       fatal: false,
+      category: Syntax,
       fixes: [{
         label: "Remove NULL character",
         fix(this) {
@@ -393,6 +594,7 @@ diagnostics! {
       message: ("The closing brace character ('}}') is invalid inside of messages, and must be escaped as '\\}}'."),
       span: Span::new(*brace_loc..(*brace_loc + '}')),
       fatal: false,
+      category: Syntax,
       fixes: [{
         label: "Escape the brace",
         fix() {
@@ -407,6 +609,7 @@ diagnostics! {
       message: ("Annotation is missing a leading space."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Add space before annotation",
         fix() {
@@ -421,6 +624,7 @@ diagnostics! {
       message: ("Attribute is missing a leading space."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Add space before attribute",
         fix() {
@@ -435,36 +639,57 @@ diagnostics! {
       message: ("Attribute is missing a key after the '@' sign."),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     AttributeMissingValue { span: Span } => {
       message: ("Attribute is missing a value after the '=' sign."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     AttributeValueIsVariable { span: Span } => {
       message: ("Attribute value can not be a variable, but must be a literal value."),
       span: *span,
       fatal: false,
+      category: Lint,
+      severity: Severity::Info,
       fixes: [],
     },
     VariableMissingName { span: Span } => {
       message: ("Variable is missing a name after the dollar sign ('$')."),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     UnterminatedQuotedPattern { span: Span } => {
       message: ("Quoted pattern is missing the closing braces ('}}}}')."),
       span: *span,
       fatal: true,
-      fixes: [],
+      category: Syntax,
+      fixes: [{
+        label: "Add closing '}}'",
+        fix(_, info) {
+          // If the pattern already ends with a single unescaped '}', only the
+          // second brace is missing.
+          let text = info.text(*span);
+          let ends_with_brace = text.strip_suffix('}').is_some_and(|rest| {
+            (rest.len() - rest.trim_end_matches('\\').len()) % 2 == 0
+          });
+          vec![DiagnosticEdit {
+            span: Span::new(span.end..span.end),
+            new_text: if ends_with_brace { "}" } else { "}}" }.to_owned(),
+          }]
+        }
+      }],
     },
     LocalKeywordMissingTrailingSpace { span: Span } => {
       message: ("'.local' keyword is not followed by a space."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Add space after '.local'",
         fix() {
@@ -480,6 +705,7 @@ diagnostics! {
       message: ("Variable is not prefixed with a dollar sign ('$')."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Add dollar sign",
         fix() {
@@ -495,6 +721,7 @@ diagnostics! {
       message: ("Matcher selector is missing a leading space."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Add space before selector",
         fix() {
@@ -509,6 +736,7 @@ diagnostics! {
       message: ("Matcher key is missing a leading space."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Add space before key",
         fix() {
@@ -523,18 +751,21 @@ diagnostics! {
       message: ("Message is missing a body (a matcher or quoted pattern)."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     ComplexMessageTrailingContent { span: Span } => {
       message: ("Message has additional invalid content after the body."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     ComplexMessageBodyNotQuoted { span: Span } => {
       message: ("Using an unquoted pattern as the body is invalid, because the message contains declarations. Did you mean to quote the pattern?."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Surround with quotes",
         fix() {
@@ -555,16 +786,22 @@ diagnostics! {
       message: ("Declarations are not valid after the message body. Did you mean to put the declaration before the body?"),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Move declaration before body",
         fix(_, info) {
+          // Also remove the whitespace that separates the declaration from
+          // whatever precedes it, so that no empty lines are left behind.
+          let before = info.text(Span::new(*body_start..span.start));
+          let removal_start = *body_start
+            + before.trim_end_matches(|c| matches!(c, crate::chars::space!()));
           vec![
             DiagnosticEdit {
               span: Span::new(*body_start..*body_start),
               new_text: format!("{}\n", info.text(*span))
             },
             DiagnosticEdit {
-              span: *span,
+              span: Span::new(removal_start..span.end),
               new_text: "".to_owned()
             }
           ]
@@ -575,18 +812,21 @@ diagnostics! {
       message: ("Message has multiple bodies, but only one is allowed."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     MatcherKeyIsVariable { span: Span } => {
       message: ("Matcher key is a variable, which is not allowed. Matcher keys must be literal values, or the wildcard ('*')."),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     InvalidMatcherLiteralKey { span: Span } => {
       message: ("Found an invalid matcher key (not a valid literal). Did you mean to quote the key to make it a literal?"),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [{
         label: "Quote literal",
         fix() {
@@ -603,22 +843,40 @@ diagnostics! {
         }
       }],
     },
+    UnexpectedContentBetweenVariants { span: Span } => {
+      message: ("Found unexpected content between matcher variants. Expected a variant key, or the end of the matcher."),
+      span: *span,
+      fatal: true,
+      category: Syntax,
+      fixes: [{
+        label: "Remove content",
+        fix() {
+          vec![DiagnosticEdit {
+            span: *span,
+            new_text: "".to_owned(),
+          }]
+        }
+      }],
+    },
     InvalidStatement { span: Span, keyword: &'text str } => {
       message: ("Found a statement that is invalid because the keyword '{keyword}' is unrecognized."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     LocalDeclarationMalformed { span: Span } => {
       message: ("Found a local declaration that is missing or malformed name."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     LocalDeclarationValueNotWrappedInBraces { span: Span } => {
       message: ("Value of a local declaration is a literal or variable, but must be an expression. Did you mean to wrap the value in braces?"),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Wrap value in braces",
         fix() {
@@ -639,6 +897,7 @@ diagnostics! {
       message: ("Local declaration is missing an equals sign after the variable."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [{
         label: "Add equals after variable",
         fix() {
@@ -653,42 +912,49 @@ diagnostics! {
       message: ("Local declaration is missing an expression as the value after the equals sign."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     InputDeclarationMissingExpression { span: Span } => {
       message: ("Input declaration is missing an expression."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     InputDeclarationWithInvalidExpression { span: Span, expression: Expression<'text> } => {
       message: ("Input declaration has a non-variable expression, which is invalid. Did you mean to use a local declaration instead of an input declaration?"),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     MatcherMissingSelectors { span: Span } => {
       message: ("Matcher is missing a selector, but at least one is required."),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     MatcherVariantKeysMismatch { span: Span, selectors: usize, keys: usize } => {
       message: ("Matcher variant has {keys} keys, but there are {selectors} selectors."),
       span: *span,
       fatal: false,
+      category: Semantic,
       fixes: [],
     },
     MatcherVariantMissingKeys { span: Span } => {
       message: ("Matcher variant is missing key(s), but at least one is required."),
       span: *span,
       fatal: false,
+      category: Syntax,
       fixes: [],
     },
     MatcherVariantExpressionBodyNotQuoted { span: Span } => {
       message: ("Matcher variant has an expression as a body, but only quoted patterns are allowed. Did you mean to wrap the expression in a quoted pattern?"),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [
         {
           label: "Quote the expression",
@@ -727,12 +993,35 @@ diagnostics! {
       message: ("Matcher variant is missing a body."),
       span: *span,
       fatal: true,
+      category: Syntax,
       fixes: [],
     },
     MatcherMissingFallback { span: Span } => {
       message: ("Matcher is missing a catch-all variant, where all keys are *."),
       span: *span,
       fatal: false,
+      category: Semantic,
+      fixes: [],
+    },
+
+    // Registry Errors
+    KeyTypeMismatch { key_span: Span, function: Identifier<'text>, annotation_span: Span } => {
+      message: ("Key can never match the selector, because :{} only matches numbers and plural categories.", function.full_name()),
+      span: *key_span,
+      fatal: false,
+      category: Lint,
+      severity: Severity::Warning,
+      fixes: [],
+      related: {
+        vec![(*annotation_span, format!("The selector is annotated with :{} here.", function.full_name()))]
+      },
+    },
+    AnnotationLiteralMismatch { span: Span, function: Identifier<'text> } => {
+      message: ("Literal is not a number, but :{} only accepts numbers.", function.full_name()),
+      span: *span,
+      fatal: false,
+      category: Lint,
+      severity: Severity::Warning,
       fixes: [],
     },
 
@@ -741,17 +1030,102 @@ diagnostics! {
       message: ("${name} has already been declared."),
       span: *second_span,
       fatal: false,
+      category: Semantic,
+      fixes: [],
+      related: {
+        vec![(*first_span, format!("${name} is first declared here."))]
+      },
+    },
+    SelectorNotDeclared { span: Span, name: &'text str } => {
+      message: ("${name} is used as a selector, but is not declared. Declare it with .input or .local to specify how it is selected on."),
+      span: *span,
+      fatal: false,
+      category: Lint,
+      severity: Severity::Warning,
+      fixes: [],
+    },
+    UnusedDeclaration { span: Span, name: &'text str } => {
+      message: ("${name} is declared, but never used."),
+      span: *span,
+      fatal: false,
+      category: Lint,
+      severity: Severity::Warning,
       fixes: [],
     },
+    RedeclareInputAsLocal { name: &'text str, input_span: Span, local_span: Span, usages: Vec<Span>, new_name: String } => {
+      message: ("${name} is declared with .input, so it can not be redeclared with .local. MessageFormat 2 does not allow redefining inputs as local variables."),
+      span: *local_span,
+      fatal: false,
+      category: Semantic,
+      fixes: [
+        {
+          label: "Rename the local variable",
+          fix() {
+            std::iter::once(*local_span)
+              .chain(usages.iter().copied())
+              .map(|span| DiagnosticEdit {
+                span,
+                new_text: format!("${new_name}"),
+              })
+              .collect()
+          }
+        }
+      ],
+      related: {
+        vec![(*input_span, format!("${name} is declared as an input here."))]
+      },
+    },
     UsageBeforeDeclaration { declaration_span: Span, usage_span: Span, name: &'text str } => {
       message: ("${name} is used before it is declared."),
       span: *usage_span,
       fatal: false,
+      category: Semantic,
       fixes: [],
+      related: {
+        vec![(*declaration_span, format!("${name} is declared here."))]
+      },
     },
   }
 }
 
+/// The kind of problem that a [Diagnostic] reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticCategory {
+  /// The message does not match the syntax of MessageFormat 2. All fatal
+  /// diagnostics are syntax errors.
+  Syntax,
+  /// The message is syntactically valid, but violates one of the data model
+  /// rules of the specification, like declaring a variable twice or missing a
+  /// catch-all variant.
+  Semantic,
+  /// The message can be used and formatted as written, but likely contains a
+  /// mistake or could be written more clearly, like an unnecessary escape or
+  /// a key that can never match. Some lints, like a variable as the value of
+  /// an attribute, are not allowed by the specification, but their meaning is
+  /// unambiguous.
+  Lint,
+}
+
+/// How severe the problem that a [Diagnostic] reports is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+  /// The message is invalid, or the parser could not understand it.
+  Error,
+  /// The message is likely not doing what the author intended, like a number
+  /// with a leading zero or a key that can never match.
+  Warning,
+  /// The message could be written more clearly, like an unnecessary escape.
+  Info,
+}
+
+impl Diagnostic<'_> {
+  /// Check if the diagnostic is a lint, meaning that it does not prevent the
+  /// message from being used or formatted. See [DiagnosticCategory::Lint].
+  pub fn is_lint(&self) -> bool {
+    self.category() == DiagnosticCategory::Lint
+  }
+}
+
 impl fmt::Display for Diagnostic<'_> {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "{} (at {:?})", self.message(), self.span())
@@ -764,6 +1138,137 @@ impl fmt::Debug for Diagnostic<'_> {
   }
 }
 
+#[cfg(feature = "testing")]
+mod testing {
+  use crate::ast::Attribute;
+  use crate::ast::ExponentSign;
+  use crate::ast::Expression;
+  use crate::ast::FnOrMarkupOption;
+  use crate::ast::Identifier;
+  use crate::ast::Literal;
+  use crate::ast::LiteralExpression;
+  use crate::ast::LiteralOrVariable;
+  use crate::ast::Number;
+  use crate::ast::Text;
+  use crate::text::LengthShort;
+  use crate::Location;
+  use crate::Span;
+
+  /// Builds a placeholder value for a field of a [super::Diagnostic].
+  pub trait Placeholder {
+    fn placeholder(span: Span) -> Self;
+  }
+
+  impl Placeholder for Span {
+    fn placeholder(span: Span) -> Self {
+      span
+    }
+  }
+
+  impl Placeholder for Vec<Span> {
+    fn placeholder(span: Span) -> Self {
+      vec![span]
+    }
+  }
+
+  impl Placeholder for Option<Span> {
+    fn placeholder(span: Span) -> Self {
+      Some(span)
+    }
+  }
+
+  impl Placeholder for Location {
+    fn placeholder(span: Span) -> Self {
+      span.start
+    }
+  }
+
+  impl Placeholder for char {
+    fn placeholder(_span: Span) -> Self {
+      'a'
+    }
+  }
+
+  impl Placeholder for usize {
+    fn placeholder(_span: Span) -> Self {
+      0
+    }
+  }
+
+  impl Placeholder for &str {
+    fn placeholder(_span: Span) -> Self {
+      ""
+    }
+  }
+
+  impl Placeholder for String {
+    fn placeholder(_span: Span) -> Self {
+      String::new()
+    }
+  }
+
+  impl Placeholder for Identifier<'_> {
+    fn placeholder(span: Span) -> Self {
+      Identifier {
+        start: span.start,
+        namespace: None,
+        name: "",
+      }
+    }
+  }
+
+  impl Placeholder for Number<'_> {
+    fn placeholder(span: Span) -> Self {
+      // A number with all parts present, so that all number diagnostics can
+      // compute their fixes.
+      Number {
+        start: span.start,
+        raw: "0.e",
+        is_negative: false,
+        integral_len: LengthShort::new_from_str("0"),
+        fractional_len: Some(LengthShort::new_from_str("")),
+        exponent_len: Some((ExponentSign::None, LengthShort::new_from_str(""))),
+      }
+    }
+  }
+
+  impl Placeholder for Attribute<'_> {
+    fn placeholder(span: Span) -> Self {
+      Attribute {
+        span,
+        key: Identifier::placeholder(span),
+        value: None,
+      }
+    }
+  }
+
+  impl Placeholder for FnOrMarkupOption<'_> {
+    fn placeholder(span: Span) -> Self {
+      FnOrMarkupOption {
+        key: Identifier::placeholder(span),
+        value: LiteralOrVariable::Literal(Literal::Text(Text {
+          start: span.start,
+          content: "",
+        })),
+      }
+    }
+  }
+
+  impl Placeholder for Expression<'_> {
+    fn placeholder(span: Span) -> Self {
+      Expression::LiteralExpression(LiteralExpression {
+        span,
+        literal: Literal::Text(Text {
+          start: span.start,
+          content: "",
+        }),
+        annotation: None,
+        attributes: vec![],
+      })
+    }
+  }
+}
+
 pub struct DiagnosticFix {
   pub label: &'static str,
   pub edits: Vec<DiagnosticEdit>,
@@ -773,3 +1278,31 @@ pub struct DiagnosticEdit {
   pub span: Span,
   pub new_text: String,
 }
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+  use super::Diagnostic;
+  use crate::Location;
+  use crate::Span;
+
+  #[test]
+  fn for_test_with_span() {
+    let (_, _, info) = crate::parse("Hello, World!");
+    let span = Span::new(Location::new_for_test(2)..Location::new_for_test(5));
+
+    let diag =
+      Diagnostic::for_test_with_span("PlaceholderInvalidLiteral", span)
+        .expect("diagnostic exists");
+    assert!(matches!(diag, Diagnostic::PlaceholderInvalidLiteral { .. }));
+    assert_eq!(diag.span().start, span.start);
+    assert_eq!(diag.span().end, span.end);
+    assert_eq!(diag.fixes(&info).len(), 1);
+
+    let diag =
+      Diagnostic::for_test_with_span("NumberMissingFractionalPart", span)
+        .expect("diagnostic exists");
+    assert_eq!(diag.fixes(&info).len(), 2);
+
+    assert!(Diagnostic::for_test_with_span("NotADiagnostic", span).is_none());
+  }
+}
@@ -1,22 +1,28 @@
 #![doc = include_str!("../README.md")]
 
 use ast::Message;
+use ast::QuotedPart;
 use parser::Parser;
 
 pub mod ast;
 mod chars;
 mod diagnostic;
 mod parser;
+mod registry;
 mod scope;
 mod text;
 mod visitor;
 
-pub use diagnostic::Diagnostic;
-pub use scope::Scope;
+pub use diagnostic::{Diagnostic, DiagnosticCategory, Severity};
+pub use registry::{
+  validate_annotations, validate_matcher, FunctionRegistry, KeyDomain,
+  ValueKind,
+};
+pub use scope::{Scope, VariableKind};
 pub use text::{
   LineColUtf16, LineColUtf8, Location, SourceTextInfo, Span, Spanned,
 };
-pub use visitor::{Visit, VisitAny, Visitable};
+pub use visitor::{Visit, VisitAny, VisitMut, Visitable, VisitableMut};
 
 /// Parse a message and return the AST, diagnostics, and source text info.
 ///
@@ -28,6 +34,9 @@ pub use visitor::{Visit, VisitAny, Visitable};
 /// an AST that fully represents the input string, but may be invalid in some
 /// other way (like escaping a character that can not be escaped).
 ///
+/// The diagnostics are sorted by their position in the source text (see
+/// [sort_by_span]).
+///
 /// The source text info contains the original source text and the line and
 /// column information for each character in the source text. This is useful for
 /// mapping locations in the AST or the diagnostics back to actual locations in
@@ -46,7 +55,165 @@ pub use visitor::{Visit, VisitAny, Visitable};
 /// println!("AST: {:?}", ast);
 /// ```
 pub fn parse(message: &str) -> (Message, Vec<Diagnostic>, SourceTextInfo) {
-  Parser::new(message).parse()
+  parse_with_options(message, &ParseOptions::default())
+}
+
+/// Options that control how a message is parsed, passed to
+/// [parse_with_options]. All options are disabled by default, which parses
+/// messages exactly as described by the specification. [parse] uses the
+/// default options.
+///
+/// Options can be set on the fields directly, or with the builder methods of
+/// the same name.
+///
+/// ### Example
+///
+/// ```rust
+/// use mf2_parser::ast::Message;
+/// use mf2_parser::parse_with_options;
+/// use mf2_parser::ParseOptions;
+///
+/// let options = ParseOptions::new().leading_dot_requires_keyword(true);
+/// let (ast, diagnostics, _) = parse_with_options("...loading", &options);
+/// assert!(matches!(ast, Message::Simple(_)));
+/// assert!(diagnostics.is_empty());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+  /// Treat U+3000 IDEOGRAPHIC SPACE as content instead of whitespace at the
+  /// start and end of the pattern of a message.
+  ///
+  /// The specification treats U+3000 as whitespace, just like a regular space.
+  /// In most places this makes no difference, because whitespace inside of
+  /// pattern text is always part of the text. This option only changes the
+  /// places where whitespace around a pattern is not part of the message,
+  /// which matters for CJK text that starts with an ideographic space:
+  ///
+  /// - A message that starts with U+3000 is parsed as a simple message, with
+  ///   the U+3000 as the first character of its pattern. Without this option,
+  ///   leading whitespace is skipped to decide whether the message is a simple
+  ///   or a complex message, so `"\u{3000}.end"` would be parsed as an
+  ///   (invalid) complex message.
+  /// - When recovering from an unquoted pattern in the body of a complex
+  ///   message, trailing U+3000 characters are kept as part of the pattern.
+  ///
+  /// U+3000 remains a separator in all syntax positions, like between
+  /// declarations, inside of placeholders, and around the keys of variants.
+  ///
+  /// Printing a message parsed with this option produces a message that should
+  /// also be parsed with this option: `"\u{3000}.end"` is printed as is.
+  ///
+  /// Defaults to `false`.
+  pub ideographic_space_is_content: bool,
+  /// Only parse a message that starts with `.` as a complex message if the `.`
+  /// is followed by one of the keywords `input`, `local`, or `match`. Other
+  /// messages that start with `.` (like `...loading`) are parsed as simple
+  /// messages, with the `.` as the first character of the pattern.
+  ///
+  /// The specification does not allow simple messages to start with `.`, so
+  /// without this option such messages are parsed as (invalid) complex
+  /// messages. Enable this option for catalogs that contain messages written
+  /// for other formats, where a leading `.` is just text.
+  ///
+  /// Printing a message parsed with this option produces a message that should
+  /// also be parsed with this option: `...loading` is printed as is.
+  ///
+  /// Defaults to `false`.
+  pub leading_dot_requires_keyword: bool,
+}
+
+impl ParseOptions {
+  /// Create the default options, with all options disabled.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Set the `ideographic_space_is_content` option.
+  pub fn ideographic_space_is_content(mut self, enabled: bool) -> Self {
+    self.ideographic_space_is_content = enabled;
+    self
+  }
+
+  /// Set the `leading_dot_requires_keyword` option.
+  pub fn leading_dot_requires_keyword(mut self, enabled: bool) -> Self {
+    self.leading_dot_requires_keyword = enabled;
+    self
+  }
+}
+
+/// Parse a message using the given [ParseOptions]. See [parse] for details
+/// about the return value.
+pub fn parse_with_options<'text>(
+  message: &'text str,
+  options: &ParseOptions,
+) -> (
+  Message<'text>,
+  Vec<Diagnostic<'text>>,
+  SourceTextInfo<'text>,
+) {
+  let (ast, mut diagnostics, info) =
+    Parser::with_options(message, options).parse();
+  sort_by_span(&mut diagnostics);
+  (ast, diagnostics, info)
+}
+
+/// The character that replaces every placeholder in the skeleton returned by
+/// [parse_with_skeleton]: U+FFFC OBJECT REPLACEMENT CHARACTER.
+pub const SKELETON_PLACEHOLDER: char = '\u{FFFC}';
+
+/// Parse a message, and also return a skeleton of the message: the text of its
+/// patterns, with escape sequences resolved and every placeholder replaced by
+/// [SKELETON_PLACEHOLDER]. This is useful to index messages for search,
+/// ignoring the details of the values that are interpolated into them.
+///
+/// Declarations are not part of the skeleton. The skeleton of a message with a
+/// matcher contains the pattern of each variant, in source text order,
+/// separated by line breaks. See [parse] for details about the other return
+/// values.
+///
+/// ### Example
+///
+/// ```rust
+/// use mf2_parser::parse_with_skeleton;
+///
+/// let (_, _, _, skeleton) = parse_with_skeleton("Hello, {$name :string}!");
+/// assert_eq!(skeleton, "Hello, \u{FFFC}!");
+/// ```
+pub fn parse_with_skeleton(
+  message: &str,
+) -> (Message, Vec<Diagnostic>, SourceTextInfo, String) {
+  let (ast, diagnostics, info) = parse(message);
+  let placeholder = SKELETON_PLACEHOLDER.to_string();
+  let skeleton = match &ast {
+    Message::Simple(pattern) => pattern.plain_text(&placeholder),
+    Message::Complex(complex) => match &complex.body {
+      ast::ComplexMessageBody::QuotedPattern(quoted) => {
+        quoted.pattern.plain_text(&placeholder)
+      }
+      ast::ComplexMessageBody::Matcher(matcher) => matcher
+        .variants
+        .iter()
+        .map(|variant| variant.pattern.pattern.plain_text(&placeholder))
+        .collect::<Vec<_>>()
+        .join("\n"),
+    },
+  };
+  (ast, diagnostics, info, skeleton)
+}
+
+/// Sort diagnostics by the start of their span, and then by the end of their
+/// span. The sort is stable, so diagnostics with the same span keep the order
+/// in which they were reported.
+///
+/// The parser may report diagnostics out of source order while recovering from
+/// errors, so [parse] sorts its diagnostics with this function. Call it again
+/// after adding more diagnostics, for example with [analyze_semantics], to get
+/// a list that is sorted as a whole.
+pub fn sort_by_span(diagnostics: &mut [Diagnostic]) {
+  diagnostics.sort_by_key(|diagnostic| {
+    let span = diagnostic.span();
+    (span.start, span.end)
+  });
 }
 
 pub fn analyze_semantics<'text>(
@@ -56,6 +223,46 @@ pub fn analyze_semantics<'text>(
   Scope::analyze(message, diagnostics)
 }
 
+/// Parse a message and analyze its semantics, returning the AST, the
+/// diagnostics of both steps, the source text info, and the [Scope] of the
+/// message. The diagnostics are sorted by their position in the source text.
+///
+/// This is a shorthand for calling [parse], [analyze_semantics], and
+/// [sort_by_span] in sequence.
+///
+/// ### Example
+///
+/// ```rust
+/// use mf2_parser::parse_all;
+///
+/// let (_, diagnostics, _, scope) =
+///   parse_all(".local $a = {1}\n.local $a = {2}\n{{{$a} {$b}}}");
+/// assert_eq!(diagnostics.len(), 1);
+/// assert!(scope.get_declaration_span("a").is_some());
+/// ```
+pub fn parse_all(
+  message: &str,
+) -> (Message, Vec<Diagnostic>, SourceTextInfo, Scope) {
+  let (ast, mut diagnostics, info) = parse(message);
+  let scope = analyze_semantics(&ast, &mut diagnostics);
+  sort_by_span(&mut diagnostics);
+  (ast, diagnostics, info, scope)
+}
+
+/// Concatenate the parts of a quoted literal into the text they represent. Text
+/// parts are included as written, and escapes are resolved to the character
+/// they escape.
+pub fn unescape_text_parts(parts: &[QuotedPart]) -> String {
+  let mut text = String::new();
+  for part in parts {
+    match part {
+      QuotedPart::Text(part) => text.push_str(part.content),
+      QuotedPart::Escape(escape) => text.push(escape.escaped_char),
+    }
+  }
+  text
+}
+
 /// Check if a string is a syntactically valid name in MF2.
 pub fn is_valid_name(name: &str) -> bool {
   let mut ch_it = name.chars();
@@ -63,3 +270,185 @@ pub fn is_valid_name(name: &str) -> bool {
   matches!(ch_it.next(), Some(chars::name_start!()))
     && ch_it.all(|c| matches!(c, chars::name!()))
 }
+
+/// Check if a string is a syntactically valid number literal in MF2, like `1`,
+/// `-0.5`, or `1e+3`.
+pub fn is_valid_number_literal(value: &str) -> bool {
+  let value = value.strip_prefix('-').unwrap_or(value);
+  let (mantissa, exponent) = match value.split_once(['e', 'E']) {
+    Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+    None => (value, None),
+  };
+  let (integral, fractional) = match mantissa.split_once('.') {
+    Some((integral, fractional)) => (integral, Some(fractional)),
+    None => (mantissa, None),
+  };
+
+  let is_digits =
+    |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+  is_digits(integral)
+    && (integral == "0" || !integral.starts_with('0'))
+    && fractional.map_or(true, is_digits)
+    && exponent.map_or(true, |exponent| {
+      is_digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ast::Literal;
+
+  fn parse_literal(message: &str) -> Literal {
+    let (ast, _, _) = parse(message);
+    let Message::Simple(mut pattern) = ast else {
+      panic!("expected a simple message");
+    };
+    let ast::PatternPart::Expression(ast::Expression::LiteralExpression(expr)) =
+      pattern.parts.remove(0)
+    else {
+      panic!("expected a literal expression");
+    };
+    expr.literal
+  }
+
+  #[test]
+  fn unescape_literals() {
+    let value = |message| parse_literal(message).value().into_owned();
+    let unescaped = |message| {
+      let Literal::Quoted(quoted) = parse_literal(message) else {
+        panic!("expected a quoted literal");
+      };
+      unescape_text_parts(&quoted.parts)
+    };
+
+    assert_eq!(value("{||}"), "");
+    assert_eq!(unescaped("{||}"), "");
+    assert_eq!(value("{|\\\\\\|\\{|}"), "\\|{");
+    assert_eq!(unescaped("{|\\\\\\|\\{|}"), "\\|{");
+    assert_eq!(value("{|a b\\|c|}"), "a b|c");
+    assert_eq!(unescaped("{|a b\\|c|}"), "a b|c");
+    assert_eq!(value("{abc}"), "abc");
+    assert_eq!(value("{-1.5}"), "-1.5");
+  }
+
+  #[test]
+  fn diagnostics_are_sorted_by_span() {
+    let (_, diagnostics, _) = parse("{: foo=} {$a b} {|c");
+    let spans = diagnostics
+      .iter()
+      .map(|diagnostic| diagnostic.span())
+      .collect::<Vec<_>>();
+    assert!(spans.len() > 2);
+    assert!(spans
+      .windows(2)
+      .all(|w| (w[0].start, w[0].end) <= (w[1].start, w[1].end)));
+    assert!(matches!(
+      diagnostics[0],
+      Diagnostic::FunctionMissingIdentifier { .. }
+    ));
+  }
+
+  #[test]
+  fn diagnostic_categories() {
+    let categories = |message| {
+      let (ast, mut diagnostics, _) = parse(message);
+      analyze_semantics(&ast, &mut diagnostics);
+      diagnostics
+        .iter()
+        .map(Diagnostic::category)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(categories("{|a\\{b|}"), vec![DiagnosticCategory::Lint]);
+    assert_eq!(
+      categories(".local $a = {1}\n.local $a = {2}\n{{{$a}}}"),
+      vec![DiagnosticCategory::Semantic]
+    );
+    assert_eq!(categories("{$a"), vec![DiagnosticCategory::Syntax]);
+    assert_eq!(categories("{01}"), vec![DiagnosticCategory::Lint]);
+    assert_eq!(categories("a \\a"), vec![DiagnosticCategory::Lint]);
+    assert_eq!(categories("{1 @a=$b}"), vec![DiagnosticCategory::Lint]);
+  }
+
+  #[test]
+  fn diagnostic_severities() {
+    let severities = |message| {
+      let (_, diagnostics, _) = parse(message);
+      diagnostics
+        .iter()
+        .map(Diagnostic::severity)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(severities("{|a\\{b|}"), vec![Severity::Info]);
+    assert_eq!(severities("{01}"), vec![Severity::Warning]);
+    assert_eq!(severities("{$a"), vec![Severity::Error]);
+  }
+
+  #[test]
+  fn diagnostic_severities_match_categories() {
+    for (code, category, severity) in Diagnostic::all_classifications() {
+      let is_error = severity == Severity::Error;
+      assert_eq!(
+        category != DiagnosticCategory::Lint,
+        is_error,
+        "{code} is a {category:?} diagnostic with {severity:?} severity"
+      );
+    }
+  }
+
+  #[test]
+  fn diagnostic_codes() {
+    let (_, diagnostics, _) = parse("{0.}");
+    assert_eq!(diagnostics[0].code(), "number-missing-fractional-part");
+
+    let codes = Diagnostic::all_codes();
+    let unique = codes.iter().collect::<std::collections::HashSet<_>>();
+    assert_eq!(unique.len(), codes.len());
+    for code in codes {
+      assert!(
+        code.bytes().all(|b| b.is_ascii_lowercase() || b == b'-'),
+        "{code} is not kebab-case"
+      );
+    }
+  }
+
+  #[test]
+  fn skeleton_of_matcher() {
+    let (_, diagnostics, _, skeleton) = parse_with_skeleton(
+      ".input {$n :number}\n.match $n\none {{{$n} item}}\n* {{{$n} \\{items\\}}}",
+    );
+    assert!(diagnostics.is_empty());
+    assert_eq!(skeleton, "\u{FFFC} item\n\u{FFFC} {items}");
+  }
+
+  #[test]
+  fn parse_all_sorts_semantic_diagnostics() {
+    let (_, diagnostics, _, _) =
+      parse_all(".local $a = {$b}\n.local $b = {1}\n{{{$a} \\a}}");
+    assert!(matches!(
+      diagnostics[..],
+      [
+        Diagnostic::UsageBeforeDeclaration { .. },
+        Diagnostic::EscapeInvalidCharacter { .. },
+      ]
+    ));
+  }
+
+  #[test]
+  fn matcher_variant_keys_mismatch() {
+    let (_, diagnostics, _) = parse(
+      ".input {$a :string}\n.input {$b :string}\n.match $a $b\nx {{1}}\nx y z {{2}}\n* * {{3}}",
+    );
+    let mismatches = diagnostics
+      .iter()
+      .map(|diagnostic| match diagnostic {
+        Diagnostic::MatcherVariantKeysMismatch {
+          selectors, keys, ..
+        } => (*selectors, *keys, diagnostic.fatal()),
+        _ => panic!("unexpected diagnostic: {diagnostic}"),
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(mismatches, [(2, 1, false), (2, 3, false)]);
+  }
+}
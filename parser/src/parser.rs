@@ -33,6 +33,7 @@ use crate::ast::Variable;
 use crate::ast::VariableExpression;
 use crate::ast::Variant;
 use crate::diagnostic::Diagnostic;
+use crate::ParseOptions;
 
 use crate::chars;
 
@@ -46,13 +47,15 @@ use crate::text::Spanned as _;
 pub struct Parser<'text> {
   text: SourceTextIterator<'text>,
   diagnostics: Vec<Diagnostic<'text>>,
+  options: ParseOptions,
 }
 
 impl<'text> Parser<'text> {
-  pub fn new(input: &'text str) -> Self {
+  pub fn with_options(input: &'text str, options: &ParseOptions) -> Self {
     Self {
       text: SourceTextIterator::new(input),
       diagnostics: vec![],
+      options: options.clone(),
     }
   }
 
@@ -65,10 +68,27 @@ impl<'text> Parser<'text> {
   ) {
     while let Some((_, c)) = self.peek() {
       match c {
+        '\u{3000}' if self.options.ideographic_space_is_content => {
+          return (
+            Message::Simple(self.parse_pattern(self.text.start_location(), false)),
+            self.diagnostics,
+            self.text.into_info(),
+          )
+        }
         chars::space!() => {
           self.next();
         }
 
+        '.' if self.options.leading_dot_requires_keyword
+          && !self.is_at_declaration_keyword() =>
+        {
+          return (
+            Message::Simple(self.parse_pattern(self.text.start_location(), false)),
+            self.diagnostics,
+            self.text.into_info(),
+          )
+        }
+
         chars::content!() | '@' | '|' // simple-start-char
          | '\\' // escaped-char
          | '\0' | '}' // error recovery
@@ -150,7 +170,8 @@ impl<'text> Parser<'text> {
           if loc != start {
             parts.push(PatternPart::Text(self.slice_text(start..loc)));
           }
-          let escape = self.parse_escape();
+          // '|' only needs to be escaped in quoted literals.
+          let escape = self.parse_escape(&['|']);
           if let Some(escape) = escape {
             parts.push(PatternPart::Escape(escape));
           }
@@ -218,12 +239,22 @@ impl<'text> Parser<'text> {
     Pattern { parts }
   }
 
-  fn parse_escape(&mut self) -> Option<Escape> {
+  /// Parse an escape sequence. Escaping any of the characters in `unnecessary`
+  /// is reported, because they are allowed unescaped where the escape is.
+  fn parse_escape(&mut self, unnecessary: &[char]) -> Option<Escape> {
     let (start, c) = self.next().unwrap(); // consume '\'
     debug_assert_eq!(c, '\\');
 
     let escaped_char = match self.next() {
-      Some((_, c @ ('}' | '{' | '|' | '\\'))) => c,
+      Some((loc, c @ chars::escaped!())) => {
+        if unnecessary.contains(&c) {
+          self.report(Diagnostic::UnnecessaryEscape {
+            span: Span::new(start..loc + c),
+            char: c,
+          });
+        }
+        c
+      }
       Some((_, c)) => {
         self.report(Diagnostic::EscapeInvalidCharacter {
           char: c,
@@ -343,7 +374,7 @@ impl<'text> Parser<'text> {
           self.next();
         }
         Some((_, '\\')) => {
-          self.parse_escape();
+          self.parse_escape(&[]);
           after_invalid = Some(self.current_location());
         }
         Some(_) => {
@@ -545,6 +576,20 @@ impl<'text> Parser<'text> {
     (id, is_empty)
   }
 
+  /// Whether the parser is at a `.` that is followed by one of the keywords
+  /// that start a declaration or matcher, like `.input`.
+  fn is_at_declaration_keyword(&mut self) -> bool {
+    let Some((loc, '.')) = self.peek() else {
+      return false;
+    };
+    let rest = self.text.slice(loc + '.'..self.text.end_location());
+    ["input", "local", "match"].iter().any(|keyword| {
+      rest.strip_prefix(keyword).is_some_and(|after| {
+        !matches!(after.chars().next(), Some(chars::name!()))
+      })
+    })
+  }
+
   fn skip_name(&mut self) {
     if let Some((_, chars::name_start!())) = self.peek() {
       self.next();
@@ -612,7 +657,13 @@ impl<'text> Parser<'text> {
         // function
         self.next(); // consume ':'
 
-        let (id, is_id_empty) = self.parse_identifier();
+        let (mut id, mut is_id_empty) = self.parse_identifier();
+        if is_id_empty {
+          if let Some(recovered) = self.recover_function_identifier(start) {
+            id = recovered;
+            is_id_empty = false;
+          }
+        }
 
         let mut options = vec![];
 
@@ -651,6 +702,41 @@ impl<'text> Parser<'text> {
     }
   }
 
+  /// Error recovery for `{: fn}`: if the colon of a function is followed by
+  /// spaces and an identifier that is not the key of an option, the identifier
+  /// is used as the identifier of the function.
+  fn recover_function_identifier(
+    &mut self,
+    colon_loc: Location,
+  ) -> Option<Identifier<'text>> {
+    let before_spaces = self.current_location();
+    if !self.skip_spaces()
+      || !matches!(self.peek(), Some((_, chars::name_start!())))
+    {
+      self.text.reset_to(before_spaces);
+      return None;
+    }
+
+    let id_start = self.current_location();
+    self.skip_name();
+    if self.eat(':').is_some() {
+      self.skip_name();
+    }
+    self.skip_spaces();
+    if matches!(self.peek(), Some((_, '='))) {
+      self.text.reset_to(before_spaces);
+      return None;
+    }
+
+    self.text.reset_to(id_start);
+    let (id, _) = self.parse_identifier();
+    self.report(Diagnostic::FunctionInvalidSpaceBeforeIdentifier {
+      start_loc: colon_loc,
+      id: id.clone(),
+    });
+    Some(id)
+  }
+
   fn parse_option(&mut self) -> Option<FnOrMarkupOption<'text>> {
     let (key, is_key_empty) = self.parse_identifier();
     self.skip_spaces();
@@ -727,7 +813,7 @@ impl<'text> Parser<'text> {
           }
         }
         '\\' => {
-          self.parse_escape();
+          self.parse_escape(&[]);
           last_space_start = None;
           had_name = false;
         }
@@ -760,7 +846,8 @@ impl<'text> Parser<'text> {
           if start != loc {
             parts.push(QuotedPart::Text(self.slice_text(start..loc)));
           }
-          let escape = self.parse_escape();
+          // '{' and '}' only need to be escaped in patterns.
+          let escape = self.parse_escape(&['{', '}']);
           if let Some(escape) = escape {
             parts.push(QuotedPart::Escape(escape));
           }
@@ -1000,7 +1087,7 @@ impl<'text> Parser<'text> {
           break;
         }
         '\\' => {
-          self.parse_escape();
+          self.parse_escape(&[]);
           last_space_start = None;
         }
         '|' => {
@@ -1153,9 +1240,12 @@ impl<'text> Parser<'text> {
       } else {
         let mut pattern = self.parse_pattern(self.current_location(), false);
         if let Some(PatternPart::Text(text)) = pattern.parts.last_mut() {
-          text.content = text
-            .content
-            .trim_end_matches(|c| matches!(c, chars::space!()));
+          let ideographic_space_is_content =
+            self.options.ideographic_space_is_content;
+          text.content = text.content.trim_end_matches(|c| match c {
+            '\u{3000}' => !ideographic_space_is_content,
+            c => matches!(c, chars::space!()),
+          });
         }
         self.report(Diagnostic::ComplexMessageBodyNotQuoted {
           span: pattern.span(),
@@ -1394,10 +1484,13 @@ impl<'text> Parser<'text> {
 
     let mut variants = vec![];
     let mut current_variant_keys = vec![];
-    let mut had_fallback = false;
-    let mut current_variant_is_fallback = true;
+    // The number of diagnostics before the keys of the current variant.
+    let mut keys_diagnostics_start = self.diagnostics.len();
 
     while let Some((loc, c)) = self.peek() {
+      if current_variant_keys.is_empty() {
+        keys_diagnostics_start = self.diagnostics.len();
+      }
       match c {
         '*' => {
           self.next();
@@ -1411,8 +1504,41 @@ impl<'text> Parser<'text> {
           had_space = self.skip_spaces();
         }
         '{' => {
-          had_fallback = had_fallback || current_variant_is_fallback;
-          current_variant_is_fallback = true;
+          // A variant with more keys than there are selectors, that starts on
+          // the same line as the body of the previous variant, starts with
+          // stray content, like `garbage` in `one {{a}} garbage two {{b}}`.
+          // Only the keys right before the body belong to the variant. On its
+          // own line, the extra keys are reported as a keys mismatch instead.
+          let after_previous_body = variants
+            .last()
+            .zip(current_variant_keys.first())
+            .is_some_and(|(previous, key): (&Variant, &Key)| {
+              !self
+                .text
+                .slice(previous.span().end..key.span().start)
+                .contains(['\n', '\r'])
+            });
+          if after_previous_body
+            && !selectors.is_empty()
+            && current_variant_keys.len() > selectors.len()
+          {
+            let stray = current_variant_keys.len() - selectors.len();
+            let span = Span::new(
+              current_variant_keys[0].span().start
+                ..current_variant_keys[stray - 1].span().end,
+            );
+            current_variant_keys.drain(..stray);
+            // The diagnostics of the stray keys (like an invalid key) are
+            // replaced by a single diagnostic for all of the stray content.
+            let key_diagnostics =
+              self.diagnostics.split_off(keys_diagnostics_start);
+            self.diagnostics.extend(
+              key_diagnostics
+                .into_iter()
+                .filter(|diagnostic| !span.contains(&diagnostic.span())),
+            );
+            self.report(Diagnostic::UnexpectedContentBetweenVariants { span });
+          }
 
           let pattern = if let Some((_, '{')) = self.peek2() {
             self.parse_quoted_pattern(loc)
@@ -1529,7 +1655,6 @@ impl<'text> Parser<'text> {
           }
           current_variant_keys.push(key);
           had_space = self.skip_spaces();
-          current_variant_is_fallback = false;
         }
       }
     }
@@ -1553,7 +1678,7 @@ impl<'text> Parser<'text> {
         span: variant.span(),
       });
       variants.push(variant);
-    } else if !had_fallback {
+    } else if !variants.iter().any(Variant::is_catch_all) {
       self.report(Diagnostic::MatcherMissingFallback {
         span: Span::new(start..start + ".match"),
       });
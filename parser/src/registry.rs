@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+
+use crate::ast;
+use crate::is_valid_number_literal;
+use crate::Diagnostic;
+use crate::Spanned as _;
+use crate::Visit;
+use crate::Visitable as _;
+
+/// The plural categories defined by CLDR. A selector annotated with a function
+/// that selects on numbers accepts these as keys, in addition to numbers.
+const PLURAL_CATEGORIES: &[&str] =
+  &["zero", "one", "two", "few", "many", "other"];
+
+/// The kind of keys that the variants of a matcher may use, when the selector
+/// is annotated with a given function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDomain {
+  /// Any key is accepted, like for `:string`.
+  String,
+  /// Only number literals and plural categories (like `one` or `few`) are
+  /// accepted, like for `:number` and `:integer`.
+  Number,
+}
+
+/// The kind of values that a function accepts as its operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+  /// Any value is accepted, like for `:string`.
+  Any,
+  /// Only numbers are accepted, like for `:number` and `:integer`. A literal
+  /// operand must be a number literal, either quoted or unquoted.
+  Number,
+}
+
+/// A registry of known functions, used to validate messages against the
+/// expectations of the functions they use, and to suggest functions and their
+/// options in editors.
+///
+/// Functions are registered by their full identifier, including the namespace
+/// if there is one (like `"number"` or `"ns:fn"`).
+#[derive(Debug, Clone, Default)]
+pub struct FunctionRegistry {
+  key_domains: HashMap<String, KeyDomain>,
+  value_kinds: HashMap<String, ValueKind>,
+  option_keys: HashMap<String, Vec<String>>,
+}
+
+impl FunctionRegistry {
+  /// Create an empty registry.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Create a registry containing the functions from the default function
+  /// registry of the MessageFormat 2 specification, together with the keys of
+  /// the options they accept. Of these, `:string`, `:number`, and `:integer`
+  /// are registered as selectors.
+  pub fn with_builtins() -> Self {
+    let mut registry = Self::new();
+    registry.register_options(
+      "number",
+      [
+        "select",
+        "style",
+        "signDisplay",
+        "useGrouping",
+        "minimumIntegerDigits",
+        "minimumFractionDigits",
+        "maximumFractionDigits",
+        "minimumSignificantDigits",
+        "maximumSignificantDigits",
+      ],
+    );
+    registry.register_options(
+      "integer",
+      [
+        "select",
+        "signDisplay",
+        "useGrouping",
+        "minimumIntegerDigits",
+        "maximumSignificantDigits",
+      ],
+    );
+    registry.register_options(
+      "datetime",
+      [
+        "dateStyle",
+        "timeStyle",
+        "calendar",
+        "numberingSystem",
+        "hourCycle",
+        "timeZone",
+        "weekday",
+        "era",
+        "year",
+        "month",
+        "day",
+        "hour",
+        "minute",
+        "second",
+        "fractionalSecondDigits",
+        "timeZoneName",
+      ],
+    );
+    registry.register_options("date", ["style"]);
+    registry.register_options("time", ["style"]);
+    registry.register("string", KeyDomain::String);
+    registry.register("number", KeyDomain::Number);
+    registry.register("integer", KeyDomain::Number);
+    registry.register_value_kind("string", ValueKind::Any);
+    registry.register_value_kind("number", ValueKind::Number);
+    registry.register_value_kind("integer", ValueKind::Number);
+    registry
+  }
+
+  /// Register a function that can be used as a selector, together with the
+  /// kind of keys it accepts. Registering a function a second time replaces
+  /// the previous registration.
+  pub fn register(&mut self, name: impl Into<String>, key_domain: KeyDomain) {
+    self.key_domains.insert(name.into(), key_domain);
+  }
+
+  /// Register the kind of values that a function accepts as its operand.
+  /// Registering a function a second time replaces the previous registration.
+  pub fn register_value_kind(
+    &mut self,
+    name: impl Into<String>,
+    value_kind: ValueKind,
+  ) {
+    self.value_kinds.insert(name.into(), value_kind);
+  }
+
+  /// Register the keys of the options that a function accepts. Registering a
+  /// function a second time replaces the previous registration.
+  pub fn register_options<K: Into<String>>(
+    &mut self,
+    name: impl Into<String>,
+    keys: impl IntoIterator<Item = K>,
+  ) {
+    let keys = keys.into_iter().map(Into::into).collect();
+    self.option_keys.insert(name.into(), keys);
+  }
+
+  /// Get the kind of keys accepted by the function with the given identifier,
+  /// or `None` if the function is not registered.
+  pub fn key_domain(&self, id: &ast::Identifier) -> Option<KeyDomain> {
+    lookup(&self.key_domains, id)
+  }
+
+  /// Get the kind of values accepted as operand by the function with the given
+  /// identifier, or `None` if the function is not registered.
+  pub fn value_kind(&self, id: &ast::Identifier) -> Option<ValueKind> {
+    lookup(&self.value_kinds, id)
+  }
+
+  /// Get the keys of the options accepted by the function with the given
+  /// identifier, in the order they were registered, or `None` if the options
+  /// of the function are not registered.
+  pub fn option_keys(&self, id: &ast::Identifier) -> Option<&[String]> {
+    self
+      .option_keys
+      .get(id.full_name().as_ref())
+      .map(Vec::as_slice)
+  }
+
+  /// Get the full names of all registered functions, in alphabetical order.
+  pub fn function_names(&self) -> Vec<&str> {
+    let mut names = self
+      .key_domains
+      .keys()
+      .chain(self.value_kinds.keys())
+      .chain(self.option_keys.keys())
+      .map(String::as_str)
+      .collect::<Vec<_>>();
+    names.sort_unstable();
+    names.dedup();
+    names
+  }
+}
+
+fn lookup<T: Copy>(
+  map: &HashMap<String, T>,
+  id: &ast::Identifier,
+) -> Option<T> {
+  map.get(id.full_name().as_ref()).copied()
+}
+
+/// Check that the keys of every variant in the matcher can be matched by the
+/// function that the corresponding selector is annotated with.
+///
+/// The annotation of a selector is found by looking up the declaration of the
+/// selector variable in `declarations`, following `.local` declarations that
+/// alias another variable without annotating it. Selectors without a known
+/// annotation, or with an annotation that is not in the registry, are not
+/// checked.
+pub fn validate_matcher<'text>(
+  matcher: &ast::Matcher<'text>,
+  declarations: &[ast::Declaration<'text>],
+  registry: &FunctionRegistry,
+  diagnostics: &mut Vec<Diagnostic<'text>>,
+) {
+  for (i, selector) in matcher.selectors.iter().enumerate() {
+    let Some(annotation) = find_annotation(selector.name, declarations) else {
+      continue;
+    };
+    let Some(domain) = registry.key_domain(&annotation.id) else {
+      continue;
+    };
+
+    for variant in &matcher.variants {
+      let Some(ast::Key::Literal(key)) = variant.keys.get(i) else {
+        continue;
+      };
+      if !key_in_domain(key, domain) {
+        diagnostics.push(Diagnostic::KeyTypeMismatch {
+          key_span: key.span(),
+          function: annotation.id.clone(),
+          annotation_span: annotation.span(),
+        });
+      }
+    }
+  }
+}
+
+/// Check that the literal operand of every annotated literal expression in the
+/// message is a value that the function of the annotation accepts, like a
+/// number literal for `:number`. Functions that are not in the registry are
+/// not checked.
+pub fn validate_annotations<'text>(
+  message: &ast::Message<'text>,
+  registry: &FunctionRegistry,
+  diagnostics: &mut Vec<Diagnostic<'text>>,
+) {
+  let mut validator = AnnotationValidator {
+    registry,
+    diagnostics,
+  };
+  message.apply_visitor(&mut validator);
+}
+
+struct AnnotationValidator<'a, 'text> {
+  registry: &'a FunctionRegistry,
+  diagnostics: &'a mut Vec<Diagnostic<'text>>,
+}
+
+impl<'ast, 'text> Visit<'ast, 'text> for AnnotationValidator<'_, 'text> {
+  fn visit_literal_expression(
+    &mut self,
+    expr: &'ast ast::LiteralExpression<'text>,
+  ) {
+    if let Some(annotation) = &expr.annotation {
+      let value_kind = self.registry.value_kind(&annotation.id);
+      if value_kind
+        .is_some_and(|kind| !literal_is_value_kind(&expr.literal, kind))
+      {
+        self
+          .diagnostics
+          .push(Diagnostic::AnnotationLiteralMismatch {
+            span: expr.literal.span(),
+            function: annotation.id.clone(),
+          });
+      }
+    }
+    expr.apply_visitor_to_children(self);
+  }
+}
+
+fn literal_is_value_kind(literal: &ast::Literal, kind: ValueKind) -> bool {
+  match kind {
+    ValueKind::Any => true,
+    ValueKind::Number => match literal {
+      ast::Literal::Number(_) => true,
+      literal => is_valid_number_literal(&literal.value()),
+    },
+  }
+}
+
+/// Find the annotation of the variable with the given name, by looking at the
+/// last declaration of the variable.
+fn find_annotation<'a, 'text>(
+  name: &str,
+  declarations: &'a [ast::Declaration<'text>],
+) -> Option<&'a ast::Annotation<'text>> {
+  let index = declarations.iter().rposition(|decl| match decl {
+    ast::Declaration::InputDeclaration(decl) => {
+      decl.expression.variable.name == name
+    }
+    ast::Declaration::LocalDeclaration(decl) => decl.variable.name == name,
+  })?;
+
+  match &declarations[index] {
+    ast::Declaration::InputDeclaration(decl) => {
+      decl.expression.annotation.as_ref()
+    }
+    ast::Declaration::LocalDeclaration(decl) => {
+      decl
+        .expression
+        .annotation()
+        .or_else(|| match &decl.expression {
+          // Only look at earlier declarations, so that self-referencing
+          // declarations can not cause infinite recursion.
+          ast::Expression::VariableExpression(expr) => {
+            find_annotation(expr.variable.name, &declarations[..index])
+          }
+          _ => None,
+        })
+    }
+  }
+}
+
+fn key_in_domain(key: &ast::Literal, domain: KeyDomain) -> bool {
+  match domain {
+    KeyDomain::String => true,
+    KeyDomain::Number => match key {
+      ast::Literal::Number(_) => true,
+      key => is_number_key(&key.value()),
+    },
+  }
+}
+
+/// Check if a key value is a plural category or a number literal.
+fn is_number_key(value: &str) -> bool {
+  PLURAL_CATEGORIES.contains(&value) || is_valid_number_literal(value)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parse;
+
+  fn validate(message: &str) -> Vec<String> {
+    let (ast, _, _) = parse(message);
+    let ast::Message::Complex(complex) = &ast else {
+      panic!("expected a complex message");
+    };
+    let ast::ComplexMessageBody::Matcher(matcher) = &complex.body else {
+      panic!("expected a matcher");
+    };
+    let mut diagnostics = vec![];
+    validate_matcher(
+      matcher,
+      &complex.declarations,
+      &FunctionRegistry::with_builtins(),
+      &mut diagnostics,
+    );
+    diagnostics.iter().map(|d| d.to_string()).collect()
+  }
+
+  #[test]
+  fn number_keys() {
+    assert_eq!(
+      validate(
+        ".input {$x :number}\n.match $x\n1 {{}}\n|1.5e3| {{}}\none {{}}\n* {{}}"
+      ),
+      Vec::<String>::new()
+    );
+    assert_eq!(
+      validate(".input {$x :integer}\n.match $x\nfoo {{}}\n|01| {{}}\n* {{}}"),
+      vec![
+        "Key can never match the selector, because :integer only matches numbers and plural categories. (at @31..34)",
+        "Key can never match the selector, because :integer only matches numbers and plural categories. (at @40..44)",
+      ]
+    );
+  }
+
+  #[test]
+  fn string_keys() {
+    assert_eq!(
+      validate(".input {$x :string}\n.match $x\nfoo {{}}\n1 {{}}\n* {{}}"),
+      Vec::<String>::new()
+    );
+  }
+
+  #[test]
+  fn unknown_or_missing_annotation() {
+    assert_eq!(
+      validate(".input {$x :ns:fn}\n.match $x\nfoo {{}}\n* {{}}"),
+      Vec::<String>::new()
+    );
+    assert_eq!(
+      validate(".input {$x}\n.match $x\nfoo {{}}\n* {{}}"),
+      Vec::<String>::new()
+    );
+  }
+
+  #[test]
+  fn aliased_selector() {
+    assert_eq!(
+      validate(".input {$x :number}\n.local $y = {$x}\n.match $y\nfoo {{}}\n* {{}}"),
+      vec![
+        "Key can never match the selector, because :number only matches numbers and plural categories. (at @47..50)",
+      ]
+    );
+    assert_eq!(
+      validate(".local $y = {$y}\n.match $y\nfoo {{}}\n* {{}}"),
+      Vec::<String>::new()
+    );
+  }
+
+  #[test]
+  fn builtin_function_names_and_options() {
+    let registry = FunctionRegistry::with_builtins();
+    assert_eq!(
+      registry.function_names(),
+      ["date", "datetime", "integer", "number", "string", "time"]
+    );
+    let (ast, _, _) = parse("{$x :date}");
+    let annotation = &ast.functions()[0];
+    assert_eq!(registry.option_keys(&annotation.id).unwrap(), ["style"]);
+  }
+
+  #[test]
+  fn registered_function() {
+    let mut registry = FunctionRegistry::new();
+    registry.register("ns:fn", KeyDomain::Number);
+    let (ast, _, _) = parse(".input {$x :ns:fn}\n.match $x\nfoo {{}}\n* {{}}");
+    let ast::Message::Complex(complex) = &ast else {
+      panic!("expected a complex message");
+    };
+    let ast::ComplexMessageBody::Matcher(matcher) = &complex.body else {
+      panic!("expected a matcher");
+    };
+    let mut diagnostics = vec![];
+    validate_matcher(
+      matcher,
+      &complex.declarations,
+      &registry,
+      &mut diagnostics,
+    );
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+      diagnostics[0].related_spans()[0].1,
+      "The selector is annotated with :ns:fn here."
+    );
+  }
+}
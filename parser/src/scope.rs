@@ -1,8 +1,10 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::collections::HashSet;
 
 use crate::ast;
 use crate::Diagnostic;
+use crate::Location;
 use crate::Span;
 use crate::Spanned as _;
 use crate::Visit;
@@ -10,11 +12,25 @@ use crate::Visitable as _;
 
 pub struct VariableUsage {
   pub declaration: Option<Span>,
+  pub kind: VariableKind,
   pub all: Vec<Span>,
 }
 
+/// How a variable was introduced into a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableKind {
+  /// Declared with an `.input` declaration.
+  Input,
+  /// Declared with a `.local` declaration.
+  Local,
+  /// Referenced, but never declared. The variable is an implicit input.
+  External,
+}
+
 pub struct Scope<'text> {
   variables: HashMap<&'text str, VariableUsage>,
+  /// The expression of the first `.local` declaration of each variable.
+  local_expressions: HashMap<&'text str, ast::Expression<'text>>,
 }
 
 impl Scope<'_> {
@@ -25,10 +41,83 @@ impl Scope<'_> {
     let mut visitor = ScopeVisitor {
       scope: Scope {
         variables: HashMap::new(),
+        local_expressions: HashMap::new(),
       },
       diagnostics,
+      inputs: HashSet::new(),
+      redeclared_inputs: vec![],
+      local_declarations: vec![],
+      selectors: vec![],
     };
     visitor.visit_message(ast);
+
+    for (name, span) in &visitor.selectors {
+      let declared = visitor
+        .scope
+        .variables
+        .get(name)
+        .is_some_and(|usage| usage.declaration.is_some());
+      if !declared {
+        visitor
+          .diagnostics
+          .push(Diagnostic::SelectorNotDeclared { span: *span, name });
+      }
+    }
+
+    // A local variable is used if it is referenced outside of its own
+    // declarations.
+    let locals = &visitor.local_declarations;
+    for (name, variable_span, _) in locals {
+      let Some(usage) = visitor.scope.variables.get(name) else {
+        continue;
+      };
+      if usage.kind != VariableKind::Local {
+        continue;
+      }
+      let used = usage.all.iter().any(|span| {
+        !locals.iter().any(|(other, _, declaration_span)| {
+          other == name && declaration_span.contains(span)
+        })
+      });
+      if !used {
+        visitor.diagnostics.push(Diagnostic::UnusedDeclaration {
+          span: *variable_span,
+          name,
+        });
+      }
+    }
+
+    // The fix for an input that is redeclared as a local renames all usages of
+    // the local to a name that is not used yet, which are only known once the
+    // whole message is visited.
+    for (index, declaration_end) in visitor.redeclared_inputs {
+      let Diagnostic::RedeclareInputAsLocal {
+        name,
+        usages,
+        new_name,
+        ..
+      } = &mut visitor.diagnostics[index]
+      else {
+        unreachable!();
+      };
+      *new_name = (1..)
+        .map(|i| match i {
+          1 => format!("{name}Local"),
+          i => format!("{name}Local{i}"),
+        })
+        .find(|candidate| !visitor.scope.variables.contains_key(&**candidate))
+        .unwrap();
+      if let Some(usage) = visitor.scope.variables.get(name) {
+        usages.extend(
+          usage
+            .all
+            .iter()
+            .filter(|span| span.start >= declaration_end)
+            .copied(),
+        );
+      }
+    }
+
     visitor.scope
   }
 
@@ -40,6 +129,19 @@ impl Scope<'_> {
     self.variables.get(name).and_then(|u| u.declaration)
   }
 
+  /// Get how the variable with the given name was introduced. If a variable is
+  /// declared more than once, the first declaration determines the kind.
+  pub fn get_kind(&self, name: &str) -> Option<VariableKind> {
+    self.variables.get(name).map(|u| u.kind)
+  }
+
+  /// Get the expression on the right-hand side of the `.local` declaration of
+  /// the variable with the given name. If a variable is declared with `.local`
+  /// more than once, the expression of the first declaration is returned.
+  pub fn get_local_expression(&self, name: &str) -> Option<&ast::Expression> {
+    self.local_expressions.get(name)
+  }
+
   pub fn get_names(&self) -> impl Iterator<Item = &str> {
     self.variables.keys().copied()
   }
@@ -48,22 +150,52 @@ impl Scope<'_> {
 struct ScopeVisitor<'diag, 'text> {
   scope: Scope<'text>,
   diagnostics: &'diag mut Vec<Diagnostic<'text>>,
+  /// Names of variables declared with `.input`.
+  inputs: HashSet<&'text str>,
+  /// Index of each [Diagnostic::RedeclareInputAsLocal] in `diagnostics`,
+  /// together with the end of the local declaration.
+  redeclared_inputs: Vec<(usize, Location)>,
+  /// The name, variable span, and declaration span of each `.local`
+  /// declaration.
+  local_declarations: Vec<(&'text str, Span, Span)>,
+  /// The name and span of each selector of the matcher.
+  selectors: Vec<(&'text str, Span)>,
 }
 
 impl<'text> ScopeVisitor<'_, 'text> {
   fn push_variable_declaration<'ast>(
     &mut self,
     var: &'ast ast::Variable<'text>,
+    local_declaration_end: Option<Location>,
   ) {
+    let kind = match local_declaration_end {
+      Some(_) => VariableKind::Local,
+      None => VariableKind::Input,
+    };
     match self.scope.variables.entry(var.name) {
       Entry::Occupied(existing) => {
         let existing = existing.into_mut();
         if let Some(existing_span) = existing.declaration {
-          self.diagnostics.push(Diagnostic::DuplicateDeclaration {
-            name: var.name,
-            first_span: existing_span,
-            second_span: var.span(),
-          });
+          if let (Some(declaration_end), true) =
+            (local_declaration_end, self.inputs.contains(var.name))
+          {
+            self
+              .redeclared_inputs
+              .push((self.diagnostics.len(), declaration_end));
+            self.diagnostics.push(Diagnostic::RedeclareInputAsLocal {
+              name: var.name,
+              input_span: existing_span,
+              local_span: var.span(),
+              usages: vec![],
+              new_name: String::new(),
+            });
+          } else {
+            self.diagnostics.push(Diagnostic::DuplicateDeclaration {
+              name: var.name,
+              first_span: existing_span,
+              second_span: var.span(),
+            });
+          }
         } else {
           for reference in &existing.all {
             self.diagnostics.push(Diagnostic::UsageBeforeDeclaration {
@@ -74,6 +206,7 @@ impl<'text> ScopeVisitor<'_, 'text> {
           }
 
           existing.declaration = Some(var.span());
+          existing.kind = kind;
         }
 
         existing.all.push(var.span());
@@ -81,6 +214,7 @@ impl<'text> ScopeVisitor<'_, 'text> {
       Entry::Vacant(vacant) => {
         vacant.insert(VariableUsage {
           declaration: Some(var.span()),
+          kind,
           all: vec![var.span()],
         });
       }
@@ -95,6 +229,7 @@ impl<'text> ScopeVisitor<'_, 'text> {
         var.name,
         VariableUsage {
           declaration: None,
+          kind: VariableKind::External,
           all: vec![var.span()],
         },
       );
@@ -109,7 +244,17 @@ impl<'ast, 'text> Visit<'ast, 'text> for ScopeVisitor<'_, 'text> {
   ) {
     decl.expression.apply_visitor(self);
 
-    self.push_variable_declaration(&decl.variable);
+    self
+      .scope
+      .local_expressions
+      .entry(decl.variable.name)
+      .or_insert_with(|| decl.expression.clone());
+    self.push_variable_declaration(&decl.variable, Some(decl.span().end));
+    self.local_declarations.push((
+      decl.variable.name,
+      decl.variable.span(),
+      decl.span(),
+    ));
   }
 
   fn visit_input_declaration(
@@ -120,10 +265,79 @@ impl<'ast, 'text> Visit<'ast, 'text> for ScopeVisitor<'_, 'text> {
       annotation.apply_visitor(self);
     }
 
-    self.push_variable_declaration(&decl.expression.variable);
+    self.push_variable_declaration(&decl.expression.variable, None);
+    self.inputs.insert(decl.expression.variable.name);
+  }
+
+  fn visit_matcher(&mut self, matcher: &'ast ast::Matcher<'text>) {
+    self.selectors.extend(
+      matcher
+        .selectors
+        .iter()
+        .map(|selector| (selector.name, selector.span())),
+    );
+    matcher.apply_visitor_to_children(self);
   }
 
   fn visit_variable(&mut self, var: &'ast ast::Variable<'text>) {
     self.push_variable_reference(var);
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::analyze_semantics;
+  use crate::parse;
+
+  #[test]
+  fn variable_kinds() {
+    let (ast, mut diagnostics, _) =
+      parse(".input {$a}\n.local $b = {$c}\n.local $a = {1}\n{{{$d}}}");
+    let scope = analyze_semantics(&ast, &mut diagnostics);
+    assert_eq!(scope.get_kind("a"), Some(VariableKind::Input));
+    assert_eq!(scope.get_kind("b"), Some(VariableKind::Local));
+    assert_eq!(scope.get_kind("c"), Some(VariableKind::External));
+    assert_eq!(scope.get_kind("d"), Some(VariableKind::External));
+    assert_eq!(scope.get_kind("f"), None);
+  }
+
+  #[test]
+  fn usage_before_declaration_kind() {
+    let (ast, mut diagnostics, _) =
+      parse(".local $a = {$b}\n.local $b = {1}\n{{}}");
+    let scope = analyze_semantics(&ast, &mut diagnostics);
+    assert_eq!(scope.get_kind("b"), Some(VariableKind::Local));
+  }
+
+  #[test]
+  fn local_expressions() {
+    let (ast, mut diagnostics, _) =
+      parse(".input {$a}\n.local $b = {$a :number}\n.local $b = {1}\n{{{$c}}}");
+    let scope = analyze_semantics(&ast, &mut diagnostics);
+    let Some(ast::Expression::VariableExpression(expr)) =
+      scope.get_local_expression("b")
+    else {
+      panic!("expected a variable expression");
+    };
+    assert_eq!(expr.variable.name, "a");
+    assert!(scope.get_local_expression("a").is_none());
+    assert!(scope.get_local_expression("c").is_none());
+  }
+
+  #[test]
+  fn redeclared_input_rename_is_not_taken() {
+    let (ast, mut diagnostics, info) = parse(
+      ".input {$a}\n.input {$aLocal}\n.local $aLocal2 = {1}\n.local $a = {$a}\n{{{$a}}}",
+    );
+    analyze_semantics(&ast, &mut diagnostics);
+    let fixes = diagnostics
+      .iter()
+      .find(|diag| matches!(diag, Diagnostic::RedeclareInputAsLocal { .. }))
+      .unwrap()
+      .fixes(&info);
+    let edits = &fixes[0].edits;
+    assert_eq!(edits.len(), 2);
+    assert!(edits.iter().all(|edit| edit.new_text == "$aLocal3"));
+  }
+}
@@ -47,15 +47,23 @@ impl<'text> SourceTextIterator<'text> {
 
   /// Resets the iterator to the given location.
   ///
-  /// The location reset to must be before the current location to ensure line
-  /// start tracking is correct.
+  /// The location reset to must not be after the current location. Line starts
+  /// are recorded as the iterator advances, so skipping ahead would skip
+  /// recording the line starts in between. This is only used for backtracking
+  /// inside of the parser, and is not exposed in the public API.
   ///
   /// ## Panics
   ///
-  /// Panics if the location falls outside of the source text, or if the
-  /// location is not at a character boundary.
+  /// Panics if the location is after the current location, if the location
+  /// falls outside of the source text, or if the location is not at a
+  /// character boundary.
   pub fn reset_to(&mut self, loc: Location) {
-    assert!(loc <= self.front_loc);
+    assert!(
+      loc <= self.front_loc,
+      "can only reset to an earlier location, but tried to reset from {:?} to {:?}",
+      self.front_loc,
+      loc
+    );
     assert!(loc.0 <= self.end_location().0);
     self.front_loc = loc;
     self.str_index = loc.0;
@@ -173,6 +181,7 @@ impl<'text> SourceTextIterator<'text> {
 /// UTF-8 line and column indices, and UTF-16 line and column indices. It also
 /// provides methods to calculate the length of a span in UTF-8 bytes or UTF-16
 /// code units.
+#[derive(Clone)]
 pub struct SourceTextInfo<'text> {
   text: &'text str,
   utf8_line_starts: Vec<u32>,
@@ -188,9 +197,30 @@ impl Spanned for SourceTextInfo<'_> {
 }
 
 impl<'text> SourceTextInfo<'text> {
+  /// Create the info for a source text without parsing it, for example to map
+  /// positions in a text that is being edited.
+  pub fn new(text: &'text str) -> SourceTextInfo<'text> {
+    let mut iter = SourceTextIterator::new(text);
+    while iter.next().is_some() {}
+    iter.into_info()
+  }
+
   pub fn text(&self, span: Span) -> &'text str {
     &self.text[span.start.0 as usize..span.end.0 as usize]
   }
+
+  /// Like [SourceTextInfo::text], but returns `None` instead of panicking if
+  /// the span is not within the source text, is reversed, or does not start
+  /// and end on character boundaries. This is useful when the span may come
+  /// from a different source text.
+  pub fn try_text(&self, span: Span) -> Option<&'text str> {
+    self.text.get(span.start.0 as usize..span.end.0 as usize)
+  }
+
+  /// Returns the entire original source text.
+  pub fn full_text(&self) -> &'text str {
+    self.text
+  }
 }
 
 impl SourceTextInfo<'_> {
@@ -243,8 +273,11 @@ impl SourceTextInfo<'_> {
   }
 
   /// Returns the length of the given span in UTF-8 bytes.
+  ///
+  /// This is equivalent to [Span::len_utf8], which does not require access to
+  /// the source text.
   pub fn utf8_len(&self, span: Span) -> u32 {
-    span.end.0 - span.start.0
+    span.len_utf8()
   }
 
   /// Returns the length of the given span in UTF-16 code units.
@@ -253,6 +286,13 @@ impl SourceTextInfo<'_> {
     text.chars().fold(0, |acc, c| acc + c.len_utf16() as u32)
   }
 
+  /// Returns whether the start and end of the given span are on different
+  /// lines. A span that ends right after a line break, at the start of the
+  /// next line, is multiline.
+  pub fn is_multiline(&self, span: Span) -> bool {
+    self.utf8_line_col(span.start).line != self.utf8_line_col(span.end).line
+  }
+
   /// Returns the location of the given UTF-8 line and column index pair.
   ///
   /// If the line index is out of bounds, returns a location pointing to the end
@@ -330,10 +370,36 @@ impl SourceTextInfo<'_> {
 /// source text. It can be mapped to UTF-8 byte indices, UTF-8 line and column,
 /// or UTF-16 line and column indices in the source text using the
 /// [SourceTextInfo] struct.
+///
+/// Nodes that do not come from parsing source text, like nodes of a message
+/// that is built programmatically, can use [Location::synthetic]. Adding a
+/// length to a synthetic location results in a synthetic location.
 #[derive(Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
 pub struct Location(u32);
 
 impl Location {
+  /// A location that does not correspond to any position in a source text.
+  pub const fn synthetic() -> Location {
+    Location(u32::MAX)
+  }
+
+  /// Whether this location is [Location::synthetic].
+  pub fn is_synthetic(&self) -> bool {
+    self.0 == u32::MAX
+  }
+
+  /// Move the location by `delta` bytes, for example to map a location in a
+  /// message that was extracted from a larger file back to the file. The
+  /// result saturates at 0 for negative deltas, and never becomes synthetic.
+  /// Shifting a synthetic location results in a synthetic location.
+  pub fn shift(&self, delta: i64) -> Location {
+    if self.is_synthetic() {
+      return *self;
+    }
+    let shifted = (self.0 as i64).saturating_add(delta);
+    Location(shifted.clamp(0, u32::MAX as i64 - 1) as u32)
+  }
+
   #[doc(hidden)]
   pub fn new_for_test(byte: u32) -> Location {
     Location(byte)
@@ -349,9 +415,29 @@ impl Location {
   }
 }
 
+/// Locations are serialized as UTF-8 byte offsets into the source text, or
+/// `null` for [Location::synthetic] locations.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Location {
+  fn serialize<S: serde::Serializer>(
+    &self,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    if self.is_synthetic() {
+      serializer.serialize_none()
+    } else {
+      serializer.serialize_u32(self.0)
+    }
+  }
+}
+
 impl Debug for Location {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-    write!(f, "@{}", self.0)
+    if self.is_synthetic() {
+      write!(f, "@synthetic")
+    } else {
+      write!(f, "@{}", self.0)
+    }
   }
 }
 
@@ -359,7 +445,7 @@ impl Add<&'_ str> for Location {
   type Output = Location;
 
   fn add(self, rhs: &'_ str) -> Self::Output {
-    Location(self.0 + rhs.len() as u32)
+    Location(self.0.saturating_add(rhs.len() as u32))
   }
 }
 
@@ -367,7 +453,7 @@ impl Add<char> for Location {
   type Output = Location;
 
   fn add(self, rhs: char) -> Self::Output {
-    Location(self.0 + rhs.len_utf8() as u32)
+    Location(self.0.saturating_add(rhs.len_utf8() as u32))
   }
 }
 
@@ -375,7 +461,7 @@ impl Add<LengthShort> for Location {
   type Output = Location;
 
   fn add(self, rhs: LengthShort) -> Self::Output {
-    Location(self.0 + rhs.0 as u32)
+    Location(self.0.saturating_add(rhs.0 as u32))
   }
 }
 
@@ -384,6 +470,7 @@ impl Add<LengthShort> for Location {
 /// The start location is inclusive, and the end location is exclusive. A span
 /// with the same start and end location is considered empty.
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Span {
   pub start: Location,
   pub end: Location,
@@ -419,6 +506,42 @@ impl Span {
   pub fn is_empty(&self) -> bool {
     self.start == self.end
   }
+
+  /// Returns the smallest span that contains both this span and the given
+  /// span, including anything between them if they are disjoint.
+  pub fn merge(&self, other: &Span) -> Span {
+    debug_assert!(self.start <= self.end && other.start <= other.end);
+    Span {
+      start: self.start.min(other.start),
+      end: self.end.max(other.end),
+    }
+  }
+
+  /// Returns the region that is contained in both this span and the given
+  /// span, or `None` if the spans are disjoint. Spans that touch (where one
+  /// ends where the other starts) intersect in an empty span at that location.
+  pub fn intersection(&self, other: &Span) -> Option<Span> {
+    debug_assert!(self.start <= self.end && other.start <= other.end);
+    let start = self.start.max(other.start);
+    let end = self.end.min(other.end);
+    (start <= end).then_some(Span { start, end })
+  }
+
+  /// Move both ends of the span by `delta` bytes. See [Location::shift].
+  pub fn shift(&self, delta: i64) -> Span {
+    Span {
+      start: self.start.shift(delta),
+      end: self.end.shift(delta),
+    }
+  }
+
+  /// Returns the length of the span in UTF-8 bytes.
+  ///
+  /// To get the length in UTF-16 code units, use
+  /// [SourceTextInfo::utf16_len], which needs access to the source text.
+  pub fn len_utf8(&self) -> u32 {
+    self.end.0 - self.start.0
+  }
 }
 
 impl Debug for Span {
@@ -433,6 +556,7 @@ pub trait Spanned {
 
 /// A short length (maximum u16)
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct LengthShort(u16);
 
 impl Debug for LengthShort {
@@ -712,6 +836,11 @@ mod tests {
       4
     );
 
+    assert_eq!(
+      super::Span::new(super::Location(8)..super::Location(12)).len_utf8(),
+      4
+    );
+
     assert_eq!(
       info.utf16_len(super::Span::new(super::Location(0)..super::Location(0))),
       0
@@ -728,5 +857,146 @@ mod tests {
       info.utf16_len(super::Span::new(super::Location(8)..super::Location(12))),
       2
     );
+
+    assert_eq!(info.full_text(), source);
+  }
+
+  #[test]
+  fn source_text_is_multiline() {
+    let mut source_text = super::SourceTextIterator::new(SOURCE);
+    while source_text.next().is_some() {}
+    let info = source_text.into_info();
+
+    let span = |start, end| {
+      super::Span::new(super::Location(start)..super::Location(end))
+    };
+    assert!(!info.is_multiline(span(0, 0)));
+    assert!(!info.is_multiline(span(0, 1)));
+    assert!(info.is_multiline(span(0, 2)));
+    assert!(!info.is_multiline(span(2, 4)));
+    assert!(info.is_multiline(span(3, 7)));
+    assert!(info.is_multiline(span(7, 8)));
+    assert!(!info.is_multiline(span(8, 20)));
+    assert!(info.is_multiline(span(0, 28)));
+  }
+
+  #[test]
+  fn source_text_info_clone() {
+    let mut source_text = super::SourceTextIterator::new(SOURCE);
+    while source_text.next().is_some() {}
+    let info = source_text.into_info();
+    let cloned = info.clone();
+    drop(info);
+
+    let loc = super::Location(SOURCE.len() as u32);
+    assert_eq!(cloned.full_text(), SOURCE);
+    assert_eq!(cloned.utf8_line_col(loc).line, 4);
+  }
+
+  #[test]
+  #[should_panic(
+    expected = "can only reset to an earlier location, but tried to reset from @1 to @3"
+  )]
+  fn source_text_reset_to_later_location() {
+    let mut source_text = super::SourceTextIterator::new(SOURCE);
+    source_text.next();
+    source_text.reset_to(super::Location(3));
+  }
+
+  #[test]
+  fn source_text_info_new() {
+    let info = super::SourceTextInfo::new("a\r\nb\rc\n");
+    let (_, _, parsed_info) = crate::parse("a\r\nb\rc\n");
+    assert_eq!(info.utf8_line_starts, parsed_info.utf8_line_starts);
+    assert_eq!(info.full_text(), "a\r\nb\rc\n");
+  }
+
+  #[test]
+  fn source_text_try_text() {
+    let mut source_text = super::SourceTextIterator::new(SOURCE);
+    while source_text.next().is_some() {}
+    let info = source_text.into_info();
+
+    let span = |start, end| {
+      super::Span::new(super::Location(start)..super::Location(end))
+    };
+    let len = SOURCE.len() as u32;
+    assert_eq!(info.try_text(span(0, 1)), Some(info.text(span(0, 1))));
+    assert_eq!(info.try_text(span(0, len)), Some(SOURCE));
+    assert_eq!(info.try_text(span(len, len)), Some(""));
+    assert_eq!(info.try_text(span(0, len + 1)), None);
+    let reversed = super::Span {
+      start: super::Location(2),
+      end: super::Location(1),
+    };
+    assert_eq!(info.try_text(reversed), None);
+    assert_eq!(info.try_text(span(8, 9)), None);
+    assert_eq!(info.try_text(span(8, 12)), Some("🍊"));
+    assert_eq!(
+      info.try_text(super::Span::new(
+        super::Location::synthetic()..super::Location::synthetic()
+      )),
+      None
+    );
+  }
+
+  #[test]
+  fn location_and_span_shift() {
+    use super::Location;
+    use super::Span;
+
+    assert_eq!(Location(5).shift(3), Location(8));
+    assert_eq!(Location(5).shift(-3), Location(2));
+    assert_eq!(Location(5).shift(-10), Location(0));
+    assert_eq!(Location(5).shift(i64::MIN), Location(0));
+    assert!(!Location(5).shift(i64::MAX).is_synthetic());
+    assert!(Location::synthetic().shift(-1).is_synthetic());
+
+    let span = Span::new(Location(2)..Location(6)).shift(-4);
+    assert_eq!((span.start, span.end), (Location(0), Location(2)));
+    let span = Span::new(Location(2)..Location(6)).shift(10);
+    assert_eq!((span.start, span.end), (Location(12), Location(16)));
+  }
+
+  #[test]
+  fn span_merge_and_intersection() {
+    use super::Location;
+    use super::Span;
+
+    let span = |start, end| Span::new(Location(start)..Location(end));
+    let range = |span: Span| (span.start.0, span.end.0);
+
+    // Disjoint
+    assert_eq!(range(span(0, 2).merge(&span(5, 7))), (0, 7));
+    assert_eq!(range(span(5, 7).merge(&span(0, 2))), (0, 7));
+    assert!(span(0, 2).intersection(&span(5, 7)).is_none());
+    assert!(span(5, 7).intersection(&span(0, 2)).is_none());
+
+    // Nested
+    assert_eq!(range(span(0, 10).merge(&span(3, 5))), (0, 10));
+    assert_eq!(
+      span(0, 10).intersection(&span(3, 5)).map(range),
+      Some((3, 5))
+    );
+    assert_eq!(
+      span(3, 5).intersection(&span(0, 10)).map(range),
+      Some((3, 5))
+    );
+
+    // Overlapping
+    assert_eq!(range(span(0, 5).merge(&span(3, 8))), (0, 8));
+    assert_eq!(
+      span(0, 5).intersection(&span(3, 8)).map(range),
+      Some((3, 5))
+    );
+
+    // Touching
+    assert_eq!(range(span(0, 3).merge(&span(3, 6))), (0, 6));
+    let intersection = span(0, 3).intersection(&span(3, 6)).unwrap();
+    assert_eq!(range(intersection), (3, 3));
+    assert!(intersection.is_empty());
+    assert_eq!(intersection.len_utf8(), 0);
+
+    assert_eq!(span(2, 9).len_utf8(), 7);
   }
 }
@@ -82,6 +82,102 @@ pub trait Visit<'ast, 'text> {
   visit!(visit_star, star, Star);
 }
 
+macro_rules! visit_mut {
+  ($fn:ident, $param:ident, $type:ident$(<$lt:lifetime>)?) => {
+    fn $fn(&mut self, $param: &mut ast::$type$(<$lt>)?) {
+      $param.apply_visitor_to_children_mut(self);
+    }
+  };
+}
+
+/// The [VisitMut] trait is used to traverse the AST and modify it in place. It
+/// is the mutable counterpart of [Visit], and visits the nodes in the same
+/// order.
+///
+/// Each method in the trait corresponds to a node type in the AST. The method
+/// is called with a mutable reference to the node. The default implementation
+/// of each method calls [VisitableMut::apply_visitor_to_children_mut] on the
+/// node, which then recursively applies the visitor to the node's children. To
+/// implement a recursive visitor, you must also call
+/// [VisitableMut::apply_visitor_to_children_mut] on any overridden visit
+/// methods.
+///
+/// String fields of the AST borrow from the source text, so they can only be
+/// replaced by strings that live at least as long as the source text. Spans are
+/// not updated when a node is modified, so they may no longer match the source
+/// text afterwards.
+///
+/// ### Example
+///
+/// ```rust
+/// use mf2_parser::ast::*;
+/// use mf2_parser::VisitMut;
+/// use mf2_parser::VisitableMut as _;
+/// use mf2_parser::parse;
+///
+/// struct RenameVariable;
+///
+/// impl<'text> VisitMut<'text> for RenameVariable {
+///   fn visit_variable_mut(&mut self, var: &mut Variable<'text>) {
+///     if var.name == "name" {
+///       var.name = "user";
+///     }
+///     var.apply_visitor_to_children_mut(self);
+///   }
+/// }
+///
+/// let (mut ast, _, _) = parse("Hello, {$name}!");
+/// ast.apply_visitor_mut(&mut RenameVariable);
+/// ```
+pub trait VisitMut<'text> {
+  visit_mut!(visit_message_mut, message, Message<'text>);
+  visit_mut!(visit_pattern_mut, msg, Pattern<'text>);
+  visit_mut!(visit_pattern_part_mut, part, PatternPart<'text>);
+  visit_mut!(visit_text_mut, text, Text<'text>);
+  visit_mut!(visit_escape_mut, escape, Escape);
+  visit_mut!(visit_expression_mut, expr, Expression<'text>);
+  visit_mut!(visit_literal_expression_mut, expr, LiteralExpression<'text>);
+  visit_mut!(visit_literal_mut, literal, Literal<'text>);
+  visit_mut!(visit_quoted_mut, quoted, Quoted<'text>);
+  visit_mut!(visit_quoted_part_mut, part, QuotedPart<'text>);
+  visit_mut!(visit_number_mut, num, Number<'text>);
+  visit_mut!(visit_annotation_mut, ann, Annotation<'text>);
+  visit_mut!(visit_identifier_mut, ident, Identifier<'text>);
+  visit_mut!(visit_fn_or_markup_option_mut, opt, FnOrMarkupOption<'text>);
+  visit_mut!(
+    visit_literal_or_variable_mut,
+    lit_or_var,
+    LiteralOrVariable<'text>
+  );
+  visit_mut!(visit_variable_mut, var, Variable<'text>);
+  visit_mut!(visit_attribute_mut, attr, Attribute<'text>);
+  visit_mut!(
+    visit_variable_expression_mut,
+    expr,
+    VariableExpression<'text>
+  );
+  visit_mut!(
+    visit_annotation_expression_mut,
+    expr,
+    AnnotationExpression<'text>
+  );
+  visit_mut!(visit_markup_mut, markup, Markup<'text>);
+  visit_mut!(visit_complex_message_mut, msg, ComplexMessage<'text>);
+  visit_mut!(visit_declaration_mut, decl, Declaration<'text>);
+  visit_mut!(visit_input_declaration_mut, decl, InputDeclaration<'text>);
+  visit_mut!(visit_local_declaration_mut, decl, LocalDeclaration<'text>);
+  visit_mut!(
+    visit_complex_message_body_mut,
+    body,
+    ComplexMessageBody<'text>
+  );
+  visit_mut!(visit_quoted_pattern_mut, pattern, QuotedPattern<'text>);
+  visit_mut!(visit_matcher_mut, matcher, Matcher<'text>);
+  visit_mut!(visit_variant_mut, variant, Variant<'text>);
+  visit_mut!(visit_key_mut, key, Key<'text>);
+  visit_mut!(visit_star_mut, star, Star);
+}
+
 /// The [Visitable] trait is used to apply a [Visit]or to an AST node.
 pub trait Visitable<'text> {
   /// Call the visitor method for this node on the visitor.
@@ -98,6 +194,19 @@ pub trait Visitable<'text> {
   );
 }
 
+/// The [VisitableMut] trait is used to apply a [VisitMut]or to an AST node.
+pub trait VisitableMut<'text> {
+  /// Call the mutable visitor method for this node on the visitor.
+  fn apply_visitor_mut<V: VisitMut<'text> + ?Sized>(&mut self, visitor: &mut V);
+
+  /// Call the mutable visitor method for each child node on the visitor. This
+  /// does not call the visitor method for this node itself.
+  fn apply_visitor_to_children_mut<V: VisitMut<'text> + ?Sized>(
+    &mut self,
+    visitor: &mut V,
+  );
+}
+
 /// The [VisitAny] trait is used to visit the AST without having to know the
 /// specific shape of each node. There are two methods, [VisitAny::before] and
 /// [VisitAny::after], which are called before and after visiting the children
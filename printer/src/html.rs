@@ -0,0 +1,339 @@
+use mf2_parser::ast::*;
+
+/// Render the pattern of a message as HTML, for previewing rich messages.
+///
+/// - Text and escape sequences are rendered as HTML-escaped text.
+/// - Markup with the name of an allowed formatting element (like `b`, `i`,
+///   `a`, or `br`) is rendered as that HTML element: `{#b}` becomes `<b>`,
+///   `{/b}` becomes `</b>`, and `{#br /}` becomes `<br/>`.
+/// - Markup options with a literal value are rendered as HTML attributes if
+///   the attribute is allowed on the element, like `href` on `a` or `title`
+///   on any element. Other options, and all markup attributes, are omitted.
+///   URLs in `href` and `src` are only rendered if they are relative or use
+///   the `http`, `https`, or `mailto` scheme, so `javascript:` URLs are
+///   omitted.
+/// - Markup with any other name, like `{#script}` or `{#ns:tag}`, is rendered
+///   as a `span` element with a `data-markup` attribute containing the name,
+///   and without any other attributes.
+/// - Placeholders are rendered as markers in braces: `{$name}` becomes
+///   `{name}`, `{|text|}` becomes `{text}`, and `{:fn}` becomes `{:fn}`.
+///
+/// The output is safe to insert into an HTML document, even if the message
+/// comes from an untrusted source.
+///
+/// The output is always balanced. A closing tag without a matching opening tag
+/// is omitted. A closing tag for an outer element closes all elements that are
+/// still open inside of it, and elements that are still open at the end of the
+/// pattern are closed there.
+///
+/// For a matcher, the fallback variant (the variant where all keys are `*`) is
+/// rendered. If there is no fallback variant, the output is empty.
+///
+/// ### Example
+///
+/// ```rust
+/// use mf2_parser::parse;
+/// use mf2_printer::render_markup_html;
+///
+/// let (ast, _, _) = parse("Hello {#b}{$name}{/b} & {#br /}welcome!");
+/// assert_eq!(
+///   render_markup_html(&ast),
+///   "Hello <b>{name}</b> &amp; <br/>welcome!"
+/// );
+/// ```
+pub fn render_markup_html(ast: &Message) -> String {
+  let pattern = match ast {
+    Message::Simple(pattern) => Some(pattern),
+    Message::Complex(complex) => match &complex.body {
+      ComplexMessageBody::QuotedPattern(quoted) => Some(&quoted.pattern),
+      ComplexMessageBody::Matcher(matcher) => matcher
+        .variants
+        .iter()
+        .find(|variant| variant.is_catch_all())
+        .map(|variant| &variant.pattern.pattern),
+    },
+  };
+
+  let mut renderer = HtmlRenderer {
+    out: String::new(),
+    open_tags: vec![],
+  };
+  if let Some(pattern) = pattern {
+    renderer.render_pattern(pattern);
+  }
+  renderer.out
+}
+
+struct HtmlRenderer {
+  out: String,
+  /// The names of the elements that are currently open, innermost last.
+  open_tags: Vec<String>,
+}
+
+impl HtmlRenderer {
+  fn render_pattern(&mut self, pattern: &Pattern) {
+    for part in &pattern.parts {
+      match part {
+        PatternPart::Text(text) => self.push_escaped(text.content),
+        PatternPart::Escape(escape) => {
+          self.push_escaped(escape.escaped_char.encode_utf8(&mut [0; 4]))
+        }
+        PatternPart::Expression(expression) => {
+          self.render_expression(expression)
+        }
+        PatternPart::Markup(markup) => self.render_markup(markup),
+      }
+    }
+
+    while let Some(tag) = self.open_tags.pop() {
+      self.push_close_tag(&tag);
+    }
+  }
+
+  fn render_expression(&mut self, expression: &Expression) {
+    self.out.push('{');
+    match expression {
+      Expression::VariableExpression(expr) => {
+        self.push_escaped(expr.variable.name)
+      }
+      Expression::LiteralExpression(expr) => {
+        self.push_escaped(&expr.literal.value())
+      }
+      Expression::AnnotationExpression(expr) => {
+        self.out.push(':');
+        self.push_escaped(&expr.annotation.id.full_name());
+      }
+    }
+    self.out.push('}');
+  }
+
+  fn render_markup(&mut self, markup: &Markup) {
+    let tag = html_tag_name(markup);
+    if markup.is_close() {
+      if let Some(index) = self.open_tags.iter().rposition(|t| *t == tag) {
+        while self.open_tags.len() > index {
+          let tag = self.open_tags.pop().unwrap();
+          self.push_close_tag(&tag);
+        }
+      }
+      return;
+    }
+
+    self.out.push('<');
+    if tag.is_empty() {
+      self.out.push_str("span data-markup=\"");
+      self.push_escaped(&markup.tag_name());
+      self.out.push('"');
+    } else {
+      self.out.push_str(&tag);
+    }
+    for option in &markup.options {
+      let LiteralOrVariable::Literal(literal) = &option.value else {
+        continue;
+      };
+      let value = literal.value();
+      if option.key.namespace.is_some()
+        || !is_allowed_attribute(&tag, option.key.name, &value)
+      {
+        continue;
+      }
+      self.out.push(' ');
+      self.out.push_str(option.key.name);
+      self.out.push_str("=\"");
+      self.push_escaped(&value);
+      self.out.push('"');
+    }
+
+    if markup.is_standalone() {
+      if tag.is_empty() {
+        self.out.push_str("></span>");
+      } else {
+        self.out.push_str("/>");
+      }
+    } else {
+      self.out.push('>');
+      self.open_tags.push(tag);
+    }
+  }
+
+  fn push_close_tag(&mut self, tag: &str) {
+    self.out.push_str("</");
+    self.out.push_str(if tag.is_empty() { "span" } else { tag });
+    self.out.push('>');
+  }
+
+  fn push_escaped(&mut self, text: &str) {
+    for ch in text.chars() {
+      match ch {
+        '&' => self.out.push_str("&amp;"),
+        '<' => self.out.push_str("&lt;"),
+        '>' => self.out.push_str("&gt;"),
+        '"' => self.out.push_str("&quot;"),
+        '\'' => self.out.push_str("&#39;"),
+        ch => self.out.push(ch),
+      }
+    }
+  }
+}
+
+/// The HTML elements that markup can be rendered as. These only affect the
+/// formatting of text, and can not run scripts or load other documents.
+const ALLOWED_TAGS: &[&str] = &[
+  "a", "abbr", "b", "bdi", "bdo", "br", "cite", "code", "del", "dfn", "em",
+  "i", "img", "ins", "kbd", "mark", "q", "s", "samp", "small", "span",
+  "strong", "sub", "sup", "time", "u", "var", "wbr",
+];
+
+/// The HTML attributes that are allowed on all elements in [ALLOWED_TAGS].
+const GLOBAL_ATTRIBUTES: &[&str] = &["dir", "lang", "title"];
+
+/// The HTML attributes that are allowed on specific elements, in addition to
+/// [GLOBAL_ATTRIBUTES].
+const ELEMENT_ATTRIBUTES: &[(&str, &[&str])] = &[
+  ("a", &["href"]),
+  ("img", &["alt", "height", "src", "width"]),
+  ("q", &["cite"]),
+  ("time", &["datetime"]),
+];
+
+/// The HTML attributes that contain a URL. See [is_safe_url].
+const URL_ATTRIBUTES: &[&str] = &["cite", "href", "src"];
+
+/// Get the HTML tag name for the markup, or an empty string if the markup is
+/// not one of the [ALLOWED_TAGS].
+fn html_tag_name(markup: &Markup) -> String {
+  let name = markup.id.name.to_ascii_lowercase();
+  if markup.id.namespace.is_none() && ALLOWED_TAGS.contains(&name.as_str()) {
+    name
+  } else {
+    String::new()
+  }
+}
+
+/// Check if the attribute with the given name and value can be rendered on an
+/// element with the given tag name.
+fn is_allowed_attribute(tag: &str, name: &str, value: &str) -> bool {
+  if tag.is_empty() {
+    return false;
+  }
+  let allowed = GLOBAL_ATTRIBUTES.contains(&name)
+    || ELEMENT_ATTRIBUTES.iter().any(|(element, attributes)| {
+      *element == tag && attributes.contains(&name)
+    });
+  allowed && (!URL_ATTRIBUTES.contains(&name) || is_safe_url(value))
+}
+
+/// Check if the URL is relative or uses one of the `http`, `https`, or
+/// `mailto` schemes. Browsers ignore ASCII whitespace and control characters
+/// in the scheme (`java\tscript:` is `javascript:`), so they are ignored when
+/// looking for the scheme too.
+fn is_safe_url(url: &str) -> bool {
+  let url = url
+    .chars()
+    .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+    .collect::<String>();
+  match url.find([':', '/', '?', '#']) {
+    Some(index) if url[index..].starts_with(':') => {
+      let scheme = url[..index].to_ascii_lowercase();
+      matches!(scheme.as_str(), "http" | "https" | "mailto")
+    }
+    _ => true,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn render(message: &str) -> String {
+    let (ast, _, _) = mf2_parser::parse(message);
+    render_markup_html(&ast)
+  }
+
+  #[test]
+  fn text_is_escaped() {
+    assert_eq!(
+      render("a < b & \\{c\\} \"d\""),
+      "a &lt; b &amp; {c} &quot;d&quot;"
+    );
+  }
+
+  #[test]
+  fn placeholders() {
+    assert_eq!(
+      render("{$a} {|<b>| :string} {1.5} {:ns:fn}"),
+      "{a} {&lt;b&gt;} {1.5} {:ns:fn}"
+    );
+  }
+
+  #[test]
+  fn markup() {
+    assert_eq!(
+      render("{#a href=|/x?a&b| target=$t @x}link{/a}{#br /}{#IMG src=i /}"),
+      "<a href=\"/x?a&amp;b\">link</a><br/><img src=\"i\"/>"
+    );
+  }
+
+  #[test]
+  fn script_markup() {
+    assert_eq!(
+      render("{#script}alert(1){/script}{#SCRIPT src=|x.js| /}"),
+      "<span data-markup=\"script\">alert(1)</span>\
+       <span data-markup=\"SCRIPT\"></span>"
+    );
+  }
+
+  #[test]
+  fn event_handler_options() {
+    assert_eq!(
+      render("{#img src=|x| onerror=|alert(1)| ONLOAD=|alert(1)| /}"),
+      "<img src=\"x\"/>"
+    );
+    assert_eq!(
+      render("{#b onclick=|alert(1)| title=|t|}x{/b}"),
+      "<b title=\"t\">x</b>"
+    );
+  }
+
+  #[test]
+  fn javascript_urls() {
+    assert_eq!(render("{#a href=|javascript:alert(1)|}x{/a}"), "<a>x</a>");
+    assert_eq!(render("{#a href=| JavaScript:alert(1)|}x{/a}"), "<a>x</a>");
+    assert_eq!(render("{#a href=|java\tscript:alert(1)|}x{/a}"), "<a>x</a>");
+    assert_eq!(render("{#img src=|data:text/html,x| /}"), "<img/>");
+    assert_eq!(
+      render("{#a href=|https://example.com/a:b|}x{/a}"),
+      "<a href=\"https://example.com/a:b\">x</a>"
+    );
+    assert_eq!(
+      render("{#a href=|/a:b|}x{/a}{#a href=|mailto:a@b.c|}y{/a}"),
+      "<a href=\"/a:b\">x</a><a href=\"mailto:a@b.c\">y</a>"
+    );
+  }
+
+  #[test]
+  fn unknown_markup() {
+    assert_eq!(
+      render("{#ns:b}x{/ns:b}{#foo.bar /}"),
+      "<span data-markup=\"ns:b\">x</span><span data-markup=\"foo.bar\"></span>"
+    );
+  }
+
+  #[test]
+  fn unbalanced_markup() {
+    assert_eq!(render("{/b}a{#b}b"), "a<b>b</b>");
+    assert_eq!(render("{#b}{#i}a{/b}b{/i}"), "<b><i>a</i></b>b");
+  }
+
+  #[test]
+  fn complex_messages() {
+    assert_eq!(render(".local $x = {1}\n{{{#b}{$x}{/b}}}"), "<b>{x}</b>");
+    assert_eq!(
+      render(
+        ".input {$n :number}\n.match $n\none {{one}}\n* {{{#i}other{/i}}}"
+      ),
+      "<i>other</i>"
+    );
+    assert_eq!(render(".input {$n :number}\n.match $n\none {{one}}"), "");
+  }
+}
@@ -1,14 +1,370 @@
 #![doc = include_str!("../README.md")]
 
+#[cfg(feature = "html")]
+mod html;
 mod printer;
 
+use std::ops::Range;
+
 use mf2_parser::ast::Message;
+use mf2_parser::Diagnostic;
+use mf2_parser::Scope;
 use mf2_parser::SourceTextInfo;
+use mf2_parser::Span;
 use printer::Printer;
 
+#[cfg(feature = "html")]
+pub use html::render_markup_html;
+
+/// Options that control how a message is printed.
+#[derive(Debug, Clone, Default)]
+pub struct PrintOptions {
+  /// Omit `.input` declarations that are redundant.
+  ///
+  /// An `.input` declaration is considered redundant if all of the following
+  /// are true:
+  ///
+  /// - the declaration has no annotation and no attributes (`.input {$x}`),
+  /// - it is the only declaration of the variable in the message,
+  /// - the variable is referenced at least once elsewhere in the message, and
+  ///   all of those references come after the declaration.
+  ///
+  /// Removing such a declaration does not change the meaning of the message,
+  /// because the variable is an implicit external input anyway.
+  pub drop_redundant_inputs: bool,
+  /// Replace line breaks in the text of patterns with a single space, so that
+  /// the pattern is printed on a single line.
+  ///
+  /// The line breaks that are replaced are `\r\n`, `\n`, `\r`, U+2028 LINE
+  /// SEPARATOR, and U+2029 PARAGRAPH SEPARATOR. Each line break is replaced
+  /// by exactly one space, so `\r\n` becomes a single space. Line breaks in
+  /// quoted literals (like `{|a\nb|}`) are not pattern text, and are always
+  /// preserved.
+  ///
+  /// Line breaks in pattern text are part of the message, so this option
+  /// changes the formatted output of messages that contain them. Only enable
+  /// it if line breaks are not meaningful to the consumer of the messages.
+  pub collapse_newlines: bool,
+  /// Sort the options of functions and markup by their key.
+  ///
+  /// Keys are compared by namespace first and name second, comparing strings
+  /// by their Unicode scalar values. Keys without a namespace come before keys
+  /// with a namespace. The relative order of options with the same key is
+  /// kept.
+  ///
+  /// Attributes are always printed in their original order, because their
+  /// order can be meaningful to the consumer of the message.
+  pub sort_options: bool,
+  /// Print literals and text with as little quoting and escaping as possible.
+  ///
+  /// Quoted literals whose value is a valid unquoted literal (a name or a
+  /// number literal) are printed without quotes, so `|foo|` is printed as
+  /// `foo`. Escapes of characters that are allowed unescaped are printed as
+  /// the plain character: `\|` in pattern text, and `\{` and `\}` in quoted
+  /// literals.
+  pub minimal_quoting: bool,
+  /// How to print the sign of positive exponents in number literals, like
+  /// `1e5` or `1e+5`. Negative exponents always keep their `-`, and the value
+  /// of the number never changes.
+  pub exponent_sign: ExponentSignStyle,
+  /// Print the body of a matcher variant on its own line, indented one level
+  /// deeper than the keys of the variant, if the body spans multiple lines:
+  ///
+  /// ```text
+  /// .match $count
+  ///   one
+  ///     {{You have one message.
+  /// Open it now?}}
+  ///   *   {{You have {$count} messages.}}
+  /// ```
+  ///
+  /// Variants with a single line body are printed on one line, as usual.
+  ///
+  /// Whitespace inside of a quoted pattern is part of the message, so the
+  /// `{{` and `}}` stay attached to the pattern text, and the lines of the
+  /// body are not re-indented. Only the whitespace between the keys and the
+  /// body changes, so the message stays the same.
+  pub indent_multiline_variants: bool,
+  /// Print the message with as little optional whitespace as possible, for
+  /// use in size-sensitive contexts:
+  ///
+  /// - The keys and selectors of a matcher are separated by a single space,
+  ///   instead of being aligned in columns, and multiple selectors are
+  ///   printed on the same line as `.match`.
+  /// - No empty lines are printed between declarations, between the
+  ///   declarations and the body, or between the selectors and the variants
+  ///   of a matcher, even if [SourceTextInfo] is provided.
+  ///
+  /// Placeholders are always printed without padding inside the braces (like
+  /// `{$name}`), so they are the same in both modes. The compact output
+  /// parses to the same message as the regular output.
+  pub compact: bool,
+}
+
+/// How to print the sign of positive exponents in number literals. See
+/// [PrintOptions::exponent_sign].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExponentSignStyle {
+  /// Print the exponent as written, so `1e5` and `1e+5` are both kept.
+  #[default]
+  Preserve,
+  /// Always print a `+` for positive exponents, so `1e5` becomes `1e+5`.
+  AlwaysPlus,
+  /// Never print a `+` for positive exponents, so `1e+5` becomes `1e5`.
+  NeverPlus,
+}
+
 /// Print the given message as a string. If [SourceTextInfo] is provided, the
 /// printer will use it to attempt to preserve some original empty line
 /// placements.
+///
+/// The only difference between printing with and without [SourceTextInfo] is
+/// the placement of empty lines after the declarations of a complex message:
+///
+/// - With [SourceTextInfo], an empty line is printed after a declaration if
+///   there was at least one empty line between it and the next declaration (or
+///   the body) in the source text.
+/// - Without [SourceTextInfo], an empty line is printed between the last
+///   declaration and the body, and never between two declarations.
+///
+/// In both cases, the output only depends on the inputs, so printing the same
+/// message twice always produces the same output.
+///
+/// The [SourceTextInfo] is ignored if it does not belong to the message, like
+/// when the message was built programmatically using
+/// [Location::synthetic](mf2_parser::Location::synthetic) locations.
 pub fn print(ast: &Message, info: Option<&SourceTextInfo>) -> String {
-  Printer::new(ast, info).print()
+  Printer::new(ast, info, None, &PrintOptions::default()).print()
+}
+
+/// Print the given message as a string like [print], and also return a mapping
+/// from the span of each printed node in the input to the byte range in the
+/// output that the node was printed to.
+///
+/// Mappings are ordered by their position in the output, and the mapping of a
+/// node always comes before the mappings of its children. Nodes that are not
+/// printed (like redundant declarations) have no mapping.
+///
+/// ### Example
+///
+/// ```rust
+/// use mf2_printer::print_with_mappings;
+///
+/// let (ast, _, info) = mf2_parser::parse("Hello {  $name }!");
+/// let (output, mappings) = print_with_mappings(&ast, Some(&info));
+/// assert_eq!(output, "Hello {$name}!");
+/// let (_, range) = mappings
+///   .iter()
+///   .find(|(span, _)| info.text(*span) == "$name")
+///   .unwrap();
+/// assert_eq!(&output[range.clone()], "$name");
+/// ```
+pub fn print_with_mappings(
+  ast: &Message,
+  info: Option<&SourceTextInfo>,
+) -> (String, Vec<(Span, Range<usize>)>) {
+  Printer::new(ast, info, None, &PrintOptions::default()).print_with_mappings()
+}
+
+/// Print the given message as a string, using the given [PrintOptions]. See
+/// [print] for how [SourceTextInfo] affects the output.
+///
+/// Some options require information about the variables in the message. If a
+/// [Scope] is provided, it is used for this purpose, otherwise the scope is
+/// computed from the AST when needed.
+pub fn print_with_options(
+  ast: &Message,
+  info: Option<&SourceTextInfo>,
+  scope: Option<&Scope>,
+  options: &PrintOptions,
+) -> String {
+  if scope.is_none() && options.drop_redundant_inputs {
+    let scope = mf2_parser::analyze_semantics(ast, &mut vec![]);
+    return Printer::new(ast, info, Some(&scope), options).print();
+  }
+  Printer::new(ast, info, scope, options).print()
+}
+
+/// Parse the given message and print it in a canonical form. Two messages that
+/// only differ in formatting, option order, or quoting have the same canonical
+/// form, which makes it useful to store or compare messages.
+///
+/// Returns all diagnostics if the message has any fatal diagnostics.
+///
+/// The canonical form is the output of [print_with_options] without
+/// [SourceTextInfo], with the following options enabled:
+///
+/// - [PrintOptions::drop_redundant_inputs]
+/// - [PrintOptions::sort_options]
+/// - [PrintOptions::minimal_quoting]
+///
+/// Numbers are not canonicalized: number literals are printed as written,
+/// because the text of a literal is its value. `{1.50}` and `{1.5}`, or
+/// `{1e+5}` and `{1e5}`, are formatted differently.
+///
+/// Normalizing is idempotent: normalizing a canonical form returns it as is.
+///
+/// ### Example
+///
+/// ```rust
+/// use mf2_printer::normalize;
+///
+/// let normalized = normalize(".input {$x}\n{{{|1| :ns:fn b=|b| a=1}{$x}}}");
+/// assert_eq!(normalized.unwrap(), "{{{1 :ns:fn a=1 b=b}{$x}}}\n");
+/// ```
+pub fn normalize(source: &str) -> Result<String, Vec<Diagnostic<'_>>> {
+  let (ast, mut diagnostics, _) = mf2_parser::parse(source);
+  if diagnostics.iter().any(|diagnostic| diagnostic.fatal()) {
+    return Err(diagnostics);
+  }
+
+  let scope = mf2_parser::analyze_semantics(&ast, &mut diagnostics);
+  let options = PrintOptions {
+    drop_redundant_inputs: true,
+    sort_options: true,
+    minimal_quoting: true,
+    ..PrintOptions::default()
+  };
+  Ok(print_with_options(&ast, None, Some(&scope), &options))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MESSAGE: &str =
+    ".input {$a}\n\n.input {$b}\n.local $c = {$a}\n{{{$b} {$c}}}";
+
+  #[test]
+  fn print_with_source_text_info() {
+    let (ast, _, info) = mf2_parser::parse(MESSAGE);
+    assert_eq!(
+      print(&ast, Some(&info)),
+      ".input {$a}\n\n.input {$b}\n.local $c = {$a}\n{{{$b} {$c}}}\n"
+    );
+  }
+
+  #[test]
+  fn print_without_source_text_info() {
+    let (ast, _, _) = mf2_parser::parse(MESSAGE);
+    assert_eq!(
+      print(&ast, None),
+      ".input {$a}\n.input {$b}\n.local $c = {$a}\n\n{{{$b} {$c}}}\n"
+    );
+  }
+
+  #[test]
+  fn print_with_source_text_info_of_other_message() {
+    let (ast, _, _) = mf2_parser::parse(MESSAGE);
+    let (_, _, info) = mf2_parser::parse("{{}}");
+    assert_eq!(print(&ast, Some(&info)), print(&ast, None));
+  }
+
+  #[test]
+  fn print_with_source_text_info_of_longer_message() {
+    let (ast, _, _) = mf2_parser::parse(MESSAGE);
+    // The same text, but with the empty line moved, is long enough to contain
+    // the spans of the message.
+    let other =
+      ".input {$a}\n.input {$b}\n\n.local $c = {$a}\n{{{$b} {$c}}}\n\n\n";
+    let (_, _, info) = mf2_parser::parse(other);
+    assert_eq!(print(&ast, Some(&info)), print(&ast, None));
+  }
+
+  #[test]
+  fn print_matcher_with_fatal_diagnostics() {
+    let (ast, diagnostics, info) = mf2_parser::parse(
+      ".input {$a :x} .match $a {{a}}\n1 2 {{b}} * {{c}} .local $b = {1}",
+    );
+    assert!(diagnostics.iter().any(|d| d.fatal()));
+    assert_eq!(
+      print(&ast, Some(&info)),
+      ".input {$a :x}\n.local $b = {1}\n\n.match\n  $a\n       {{a}}\n  1  2 {{b}}\n  *    {{c}}\n"
+    );
+  }
+
+  #[test]
+  fn print_with_mappings_maps_nodes_to_output() {
+    let message = ".input {$a :string}\n.match $a\none {{x {$a}}}\n* {{|y|}}";
+    let (ast, _, info) = mf2_parser::parse(message);
+    let (output, mappings) = print_with_mappings(&ast, Some(&info));
+    assert_eq!(output, print(&ast, Some(&info)));
+    assert_eq!(mappings[0].1, 0..output.len());
+
+    let mapped = mappings
+      .iter()
+      .map(|(span, range)| (info.text(*span), &output[range.clone()]))
+      .collect::<Vec<_>>();
+    for expected in
+      ["$a", "one", "*", "{{x {$a}}}", "one {{x {$a}}}", ":string"]
+    {
+      assert!(
+        mapped.contains(&(expected, expected)),
+        "missing mapping for {expected:?}"
+      );
+    }
+  }
+
+  #[test]
+  fn matcher_columns_align_by_display_width() {
+    use unicode_width::UnicodeWidthStr as _;
+
+    let (ast, diagnostics, _) = mf2_parser::parse(
+      ".input {$a :string} .input {$b :string} .match $a $b 一 x {{1}} abc |二三| {{2}} * * {{3}}",
+    );
+    assert!(diagnostics.is_empty());
+    let printed = print(&ast, None);
+    let columns = printed
+      .lines()
+      .filter_map(|line| line.find("{{").map(|i| line[..i].width()))
+      .collect::<Vec<_>>();
+    assert_eq!(columns.len(), 3);
+    assert!(columns.iter().all(|&column| column == columns[0]));
+  }
+
+  #[test]
+  fn normalize_numbers() {
+    assert_eq!(
+      normalize("{1e+5 :ns:fn a=1.50E+2 b=|1e+5|}").unwrap(),
+      "{1e+5 :ns:fn a=1.50E+2 b=1e+5}"
+    );
+  }
+
+  #[test]
+  fn print_synthetic_locations() {
+    use mf2_parser::ast::*;
+    use mf2_parser::Location;
+    use mf2_parser::Span;
+
+    let span = Span::new(Location::synthetic()..Location::synthetic());
+    let input = |name| {
+      Declaration::InputDeclaration(InputDeclaration {
+        start: Location::synthetic(),
+        expression: VariableExpression {
+          span,
+          variable: Variable { span, name },
+          annotation: None,
+          attributes: vec![],
+        },
+      })
+    };
+    let ast = Message::Complex(ComplexMessage {
+      span,
+      declarations: vec![input("a"), input("b")],
+      body: ComplexMessageBody::QuotedPattern(QuotedPattern {
+        span,
+        pattern: Pattern {
+          parts: vec![PatternPart::Text(Text {
+            start: Location::synthetic(),
+            content: "Hello",
+          })],
+        },
+      }),
+    });
+
+    let expected = ".input {$a}\n.input {$b}\n\n{{Hello}}\n";
+    assert_eq!(print(&ast, None), expected);
+    let (_, _, info) = mf2_parser::parse(MESSAGE);
+    assert_eq!(print(&ast, Some(&info)), expected);
+  }
 }
@@ -1,26 +1,54 @@
+use std::ops::Range;
+
 use mf2_parser::ast::*;
+use mf2_parser::is_valid_name;
+use mf2_parser::is_valid_number_literal;
+use mf2_parser::unescape_text_parts;
 use mf2_parser::LineColUtf8;
 use mf2_parser::Location;
+use mf2_parser::Scope;
 use mf2_parser::SourceTextInfo;
+use mf2_parser::Span;
 use mf2_parser::Spanned;
 use mf2_parser::Visit;
 use mf2_parser::Visitable;
+use unicode_width::UnicodeWidthStr as _;
+
+use crate::ExponentSignStyle;
+use crate::PrintOptions;
 
 pub struct Printer<'ast, 'text> {
   ast: &'ast Message<'text>,
   info: Option<&'text SourceTextInfo<'text>>,
+  scope: Option<&'ast Scope<'text>>,
+  options: &'ast PrintOptions,
   out: String,
+  mappings: Option<Vec<(Span, Range<usize>)>>,
+}
+
+/// Output that was printed into a separate buffer, together with the mappings
+/// recorded while printing it. The output ranges of the mappings are relative
+/// to the start of `text`.
+#[derive(Default, Clone)]
+struct Captured {
+  text: String,
+  mappings: Vec<(Span, Range<usize>)>,
 }
 
 impl<'ast, 'text> Printer<'ast, 'text> {
   pub fn new(
     ast: &'ast Message<'text>,
     info: Option<&'text SourceTextInfo<'text>>,
+    scope: Option<&'ast Scope<'text>>,
+    options: &'ast PrintOptions,
   ) -> Self {
     Self {
       ast,
-      info,
+      info: info.filter(|info| info_matches(ast, info)),
+      scope,
+      options,
       out: String::new(),
+      mappings: None,
     }
   }
 
@@ -29,6 +57,52 @@ impl<'ast, 'text> Printer<'ast, 'text> {
     self.out
   }
 
+  pub fn print_with_mappings(mut self) -> (String, Vec<(Span, Range<usize>)>) {
+    self.mappings = Some(vec![]);
+    self.ast.apply_visitor(&mut self);
+    (self.out, self.mappings.unwrap_or_default())
+  }
+
+  /// Print a node using `f`, recording the output range it produced if
+  /// mappings are enabled. The mapping is added before the mappings of any
+  /// child nodes, so that parents always come before their children.
+  fn mapped(&mut self, span: Span, f: impl FnOnce(&mut Self)) {
+    let start = self.out.len();
+    let index = self.mappings.as_mut().map(|mappings| {
+      mappings.push((span, start..start));
+      mappings.len() - 1
+    });
+    f(self);
+    if let (Some(mappings), Some(index)) = (&mut self.mappings, index) {
+      mappings[index].1.end = self.out.len();
+    }
+  }
+
+  /// Print into a separate buffer using `f`, without changing the output.
+  fn capture(&mut self, f: impl FnOnce(&mut Self)) -> Captured {
+    let out = std::mem::take(&mut self.out);
+    let mappings = self.mappings.as_mut().map(std::mem::take);
+    f(self);
+    let text = std::mem::replace(&mut self.out, out);
+    let captured = std::mem::replace(&mut self.mappings, mappings);
+    Captured {
+      text,
+      mappings: captured.unwrap_or_default(),
+    }
+  }
+
+  /// Push output that was previously captured with [Printer::capture], moving
+  /// its mappings to where it ends up in the output.
+  fn push_captured(&mut self, captured: &Captured) {
+    let offset = self.out.len();
+    if let Some(mappings) = &mut self.mappings {
+      mappings.extend(captured.mappings.iter().map(|(span, range)| {
+        (*span, range.start + offset..range.end + offset)
+      }));
+    }
+    self.push_str(&captured.text);
+  }
+
   fn push(&mut self, ch: char) {
     self.out.push(ch);
   }
@@ -47,7 +121,7 @@ impl<'ast, 'text> Printer<'ast, 'text> {
     &mut self,
     body: T,
     annotation: Option<&'ast Annotation<'text>>,
-    attributes: &'ast Vec<Attribute<'text>>,
+    attributes: &'ast [Attribute<'text>],
     cb: F,
   ) where
     F: FnOnce(&mut Self, T),
@@ -71,19 +145,30 @@ impl<'ast, 'text> Printer<'ast, 'text> {
     self.push('}');
   }
 
-  fn try_visit_match_key(&mut self, key: &'ast Key<'text>) -> String {
-    let Key::Literal(key) = key else {
-      assert!(matches!(key, Key::Star(_)));
-      return "*".to_string();
-    };
-
-    let backup = std::mem::take(&mut self.out);
-
-    key.apply_visitor(self);
+  /// Returns the items in the order they should be printed in, which is sorted
+  /// by key if [PrintOptions::sort_options] is enabled.
+  fn sorted<'a, T>(
+    &self,
+    items: &'a [T],
+    key: impl Fn(&T) -> &Identifier,
+  ) -> Vec<&'a T> {
+    let mut items = items.iter().collect::<Vec<_>>();
+    if self.options.sort_options {
+      items.sort_by(|a, b| {
+        let (a, b) = (key(a), key(b));
+        (a.namespace, a.name).cmp(&(b.namespace, b.name))
+      });
+    }
+    items
+  }
 
-    std::mem::replace(&mut self.out, backup)
+  fn try_visit_match_key(&mut self, key: &'ast Key<'text>) -> Captured {
+    self.capture(|this| key.apply_visitor(this))
   }
 
+  /// Check if there was an empty line between `start` and `end` in the source
+  /// text. Without source text info, or if either location does not point into
+  /// the source text, `default` is returned instead.
   fn had_empty_line(
     &self,
     start: Location,
@@ -93,6 +178,10 @@ impl<'ast, 'text> Printer<'ast, 'text> {
     let Some(info) = self.info else {
       return default;
     };
+    // Synthetic locations are always after the end of the source text.
+    if start > end || end > info.span().end {
+      return default;
+    }
 
     let LineColUtf8 {
       line: start_line, ..
@@ -101,215 +190,510 @@ impl<'ast, 'text> Printer<'ast, 'text> {
 
     end_line > start_line + 1
   }
+
+  /// Check if a number literal is printed as written with the configured
+  /// [ExponentSignStyle], like `1e5` with [ExponentSignStyle::NeverPlus].
+  fn exponent_sign_is_canonical(&self, number: &str) -> bool {
+    let Some(index) = number.find(['e', 'E']) else {
+      return true;
+    };
+    let sign = number[index + 1..].chars().next();
+    match self.options.exponent_sign {
+      ExponentSignStyle::Preserve => true,
+      ExponentSignStyle::AlwaysPlus => matches!(sign, Some('+' | '-')),
+      ExponentSignStyle::NeverPlus => sign != Some('+'),
+    }
+  }
+
+  fn is_redundant_declaration(
+    &self,
+    decl: &Declaration<'text>,
+    message: &ComplexMessage<'text>,
+  ) -> bool {
+    if !self.options.drop_redundant_inputs {
+      return false;
+    }
+    let (Declaration::InputDeclaration(decl), Some(scope)) = (decl, self.scope)
+    else {
+      return false;
+    };
+    let expr = &decl.expression;
+    if expr.annotation.is_some() || !expr.attributes.is_empty() {
+      return false;
+    }
+
+    let name = expr.variable.name;
+    let declared_once = message
+      .declarations
+      .iter()
+      .filter(|other| match other {
+        Declaration::InputDeclaration(other) => {
+          other.expression.variable.name == name
+        }
+        Declaration::LocalDeclaration(other) => other.variable.name == name,
+      })
+      .count()
+      == 1;
+    if !declared_once {
+      return false;
+    }
+
+    // Selectors must be declared, so dropping the declaration of a selector
+    // would make the message invalid.
+    if let ComplexMessageBody::Matcher(matcher) = &message.body {
+      if matcher
+        .selectors
+        .iter()
+        .any(|selector| selector.name == name)
+      {
+        return false;
+      }
+    }
+
+    let decl_start = expr.variable.span().start;
+    let Some(spans) = scope.get_spans(name) else {
+      return false;
+    };
+    spans.len() > 1 && spans.iter().all(|span| span.start >= decl_start)
+  }
 }
 
 impl<'ast, 'text> Visit<'ast, 'text> for Printer<'ast, 'text> {
+  fn visit_pattern_part(&mut self, part: &'ast PatternPart<'text>) {
+    match part {
+      PatternPart::Escape(
+        escape @ Escape {
+          escaped_char: '|', ..
+        },
+      ) if self.options.minimal_quoting => {
+        self.mapped(escape.span(), |this| this.push('|'))
+      }
+      PatternPart::Text(text) if self.options.collapse_newlines => {
+        self.mapped(text.span(), |this| {
+          let mut chars = text.content.chars().peekable();
+          while let Some(ch) = chars.next() {
+            match ch {
+              '\r' => {
+                chars.next_if_eq(&'\n');
+                this.push(' ');
+              }
+              '\n' | '\u{2028}' | '\u{2029}' => this.push(' '),
+              ch => this.push(ch),
+            }
+          }
+        })
+      }
+      _ => part.apply_visitor_to_children(self),
+    }
+  }
+
+  fn visit_pattern(&mut self, pattern: &'ast Pattern<'text>) {
+    self.mapped(pattern.span(), |this| {
+      pattern.apply_visitor_to_children(this);
+    });
+  }
+
   fn visit_text(&mut self, text: &Text) {
-    self.push_str(text.content);
+    self.mapped(text.span(), |this| {
+      this.push_str(text.content);
+    });
   }
 
   fn visit_escape(&mut self, escape: &Escape) {
-    self.push('\\');
-    self.push(escape.escaped_char);
+    self.mapped(escape.span(), |this| {
+      this.push('\\');
+      this.push(escape.escaped_char);
+    });
+  }
+
+  fn visit_star(&mut self, star: &Star) {
+    self.mapped(star.span(), |this| this.push('*'));
   }
 
   fn visit_annotation_expression(
     &mut self,
     expr: &'ast AnnotationExpression<'text>,
   ) {
-    self.helper_visit_expression(
-      None::<()>,
-      Some(&expr.annotation),
-      &expr.attributes,
-      |_, _| {},
-    );
+    self.mapped(expr.span(), |this| {
+      this.helper_visit_expression(
+        None::<()>,
+        Some(&expr.annotation),
+        &expr.attributes,
+        |_, _| {},
+      );
+    });
   }
 
   fn visit_literal_expression(&mut self, expr: &'ast LiteralExpression<'text>) {
-    self.helper_visit_expression(
-      &expr.literal,
-      expr.annotation.as_ref(),
-      &expr.attributes,
-      Self::visit_literal,
-    );
+    self.mapped(expr.span(), |this| {
+      this.helper_visit_expression(
+        &expr.literal,
+        expr.annotation.as_ref(),
+        &expr.attributes,
+        Self::visit_literal,
+      );
+    });
   }
 
   fn visit_variable_expression(
     &mut self,
     expr: &'ast VariableExpression<'text>,
   ) {
-    self.helper_visit_expression(
-      &expr.variable,
-      expr.annotation.as_ref(),
-      &expr.attributes,
-      Self::visit_variable,
-    );
+    self.mapped(expr.span(), |this| {
+      this.helper_visit_expression(
+        &expr.variable,
+        expr.annotation.as_ref(),
+        &expr.attributes,
+        Self::visit_variable,
+      );
+    });
   }
 
   fn visit_annotation(&mut self, ann: &'ast Annotation<'text>) {
-    self.push(':');
-    ann.apply_visitor_to_children(self);
+    self.mapped(ann.span(), |this| {
+      this.push(':');
+      ann.id.apply_visitor(this);
+      for option in this.sorted(&ann.options, |option| &option.key) {
+        option.apply_visitor(this);
+      }
+    });
   }
 
   fn visit_identifier(&mut self, id: &Identifier) {
-    if let Some(namespace) = id.namespace {
-      self.push_str(namespace);
-      self.push(':');
-    }
-    self.push_str(id.name);
+    self.mapped(id.span(), |this| {
+      if let Some(namespace) = id.namespace {
+        this.push_str(namespace);
+        this.push(':');
+      }
+      this.push_str(id.name);
+    });
   }
 
   fn visit_fn_or_markup_option(
     &mut self,
     option: &'ast FnOrMarkupOption<'text>,
   ) {
-    self.push(' ');
-    option.key.apply_visitor(self);
-    self.push('=');
-    option.value.apply_visitor(self);
+    self.mapped(option.span(), |this| {
+      this.push(' ');
+      option.key.apply_visitor(this);
+      this.push('=');
+      option.value.apply_visitor(this);
+    });
   }
 
   fn visit_quoted(&mut self, quoted: &'ast Quoted<'text>) {
-    self.push('|');
-    quoted.apply_visitor_to_children(self);
-    self.push('|');
+    self.mapped(quoted.span(), |this| {
+      if !this.options.minimal_quoting {
+        this.push('|');
+        quoted.apply_visitor_to_children(this);
+        this.push('|');
+        return;
+      }
+
+      // A number is only unquoted if printing it as a number would not change
+      // its exponent sign, so that formatting the output again is stable.
+      let value = unescape_text_parts(&quoted.parts);
+      if is_valid_name(&value)
+        || (is_valid_number_literal(&value)
+          && this.exponent_sign_is_canonical(&value))
+      {
+        this.push_str(&value);
+        return;
+      }
+
+      this.push('|');
+      for part in &quoted.parts {
+        match part {
+          QuotedPart::Escape(Escape {
+            escaped_char: c @ ('{' | '}'),
+            ..
+          }) => this.push(*c),
+          part => part.apply_visitor(this),
+        }
+      }
+      this.push('|');
+    });
   }
 
   fn visit_number(&mut self, num: &Number) {
-    self.push_str(num.raw);
+    self.mapped(num.span(), |this| {
+      let Some((sign, digits)) = num.exponent_part() else {
+        this.push_str(num.raw);
+        return;
+      };
+
+      let sign = match (this.options.exponent_sign, sign) {
+        (_, ExponentSign::Minus) => "-",
+        (ExponentSignStyle::AlwaysPlus, _) => "+",
+        (ExponentSignStyle::NeverPlus, _) => "",
+        (ExponentSignStyle::Preserve, ExponentSign::Plus) => "+",
+        (ExponentSignStyle::Preserve, ExponentSign::None) => "",
+      };
+      // Everything up to and including the `e` is printed as written.
+      let mantissa =
+        num.raw[..num.raw.len() - digits.len()].trim_end_matches(['+', '-']);
+      this.push_str(mantissa);
+      this.push_str(sign);
+      this.push_str(digits);
+    });
   }
 
   fn visit_variable(&mut self, var: &Variable) {
-    self.push('$');
-    self.push_str(var.name);
+    self.mapped(var.span(), |this| {
+      this.push('$');
+      this.push_str(var.name);
+    });
   }
 
   fn visit_attribute(&mut self, attr: &'ast Attribute<'text>) {
-    self.push(' ');
-    self.push('@');
-    attr.key.apply_visitor(self);
-
-    if let Some(value) = &attr.value {
-      self.push('=');
-      value.apply_visitor(self);
-    }
+    self.mapped(attr.span(), |this| {
+      this.push(' ');
+      this.push('@');
+      attr.key.apply_visitor(this);
+
+      if let Some(value) = &attr.value {
+        this.push('=');
+        value.apply_visitor(this);
+      }
+    });
   }
 
   fn visit_markup(&mut self, markup: &'ast Markup<'text>) {
-    self.push('{');
-    if let MarkupKind::Close = markup.kind {
-      self.push('/');
-    } else {
-      self.push('#');
-    }
+    self.mapped(markup.span(), |this| {
+      this.push('{');
+      if markup.is_close() {
+        this.push('/');
+      } else {
+        this.push('#');
+      }
 
-    markup.apply_visitor_to_children(self);
+      if this.options.sort_options {
+        markup.id.apply_visitor(this);
+        for option in this.sorted(&markup.options, |option| &option.key) {
+          option.apply_visitor(this);
+        }
+        for attr in &markup.attributes {
+          attr.apply_visitor(this);
+        }
+      } else {
+        markup.apply_visitor_to_children(this);
+      }
 
-    if let MarkupKind::Standalone = markup.kind {
-      self.push(' ');
-      self.push('/');
-    }
-    self.push('}');
+      if markup.is_standalone() {
+        this.push(' ');
+        this.push('/');
+      }
+      this.push('}');
+    });
   }
 
   fn visit_complex_message(&mut self, message: &'ast ComplexMessage<'text>) {
-    for (i, decl) in message.declarations.iter().enumerate() {
-      decl.apply_visitor(self);
-      self.push('\n');
-
-      let next_decl =
-        message.declarations.get(i + 1).map(|x| x as &dyn Spanned);
-      let next_start = next_decl
-        .unwrap_or(&message.body as &dyn Spanned)
-        .span()
-        .start;
-
-      if self.had_empty_line(decl.span().end, next_start, next_decl.is_none()) {
-        self.push('\n');
+    self.mapped(message.span(), |this| {
+      let declarations = message
+        .declarations
+        .iter()
+        .filter(|decl| !this.is_redundant_declaration(decl, message))
+        .collect::<Vec<_>>();
+
+      for (i, decl) in declarations.iter().enumerate() {
+        decl.apply_visitor(this);
+        this.push('\n');
+
+        let next_decl = declarations.get(i + 1).map(|x| *x as &dyn Spanned);
+        let next_start = next_decl
+          .unwrap_or(&message.body as &dyn Spanned)
+          .span()
+          .start;
+
+        if !this.options.compact
+          && this.had_empty_line(
+            decl.span().end,
+            next_start,
+            next_decl.is_none(),
+          )
+        {
+          this.push('\n');
+        }
       }
-    }
 
-    message.body.apply_visitor(self);
+      message.body.apply_visitor(this);
 
-    self.push('\n');
+      this.push('\n');
+    });
   }
 
   fn visit_input_declaration(&mut self, decl: &'ast InputDeclaration<'text>) {
-    self.push_str(".input ");
-    decl.expression.apply_visitor(self);
+    self.mapped(decl.span(), |this| {
+      this.push_str(".input ");
+      decl.expression.apply_visitor(this);
+    });
   }
 
   fn visit_local_declaration(&mut self, decl: &'ast LocalDeclaration<'text>) {
-    self.push_str(".local ");
-    decl.variable.apply_visitor(self);
-    self.push_str(" = ");
-    decl.expression.apply_visitor(self);
+    self.mapped(decl.span(), |this| {
+      this.push_str(".local ");
+      decl.variable.apply_visitor(this);
+      this.push_str(" = ");
+      decl.expression.apply_visitor(this);
+    });
   }
 
   fn visit_quoted_pattern(&mut self, pattern: &'ast QuotedPattern<'text>) {
-    self.push_str("{{");
-    pattern.pattern.apply_visitor(self);
-    self.push_str("}}");
+    self.mapped(pattern.span(), |this| {
+      // No padding is added inside the braces: whitespace in a quoted pattern is
+      // part of the pattern text, so `{{ a }}` and `{{a}}` are different
+      // messages. Any spacing the author wrote is preserved as text.
+      this.push_str("{{");
+      pattern.pattern.apply_visitor(this);
+      this.push_str("}}");
+    });
   }
 
   fn visit_matcher(&mut self, matcher: &'ast Matcher<'text>) {
-    self.push_str(".match");
-
-    let selectors_count = matcher
-      .variants
-      .iter()
-      .map(|v| v.keys.len())
-      .chain(std::iter::once(matcher.selectors.len()))
-      .max()
-      .expect("There is at least matcher.selectors.len()");
-    let mut max_lengths = vec![0; selectors_count];
-
-    assert!(matcher.selectors.len() <= selectors_count);
-    for (i, selector) in matcher.selectors.iter().enumerate() {
-      max_lengths[i] = selector.name.len() + 1;
-    }
-
-    if max_lengths.len() > 1 {
-      self.push_str("\n  ");
-    } else {
-      self.push(' ');
-    }
+    self.mapped(matcher.span(), |this| {
+      this.push_str(".match");
+
+      // Variants may have more keys than there are selectors (or there may be no
+      // selectors at all) if the message has non-fatal diagnostics, so the
+      // number of columns is the maximum of all of them.
+      let selectors_count = matcher
+        .variants
+        .iter()
+        .map(|v| v.keys.len())
+        .fold(matcher.selectors.len(), usize::max);
+      let mut max_lengths = vec![0; selectors_count];
+
+      // Columns are aligned by display width, so that keys with wide characters
+      // (like CJK) line up with the other keys in the same column.
+      for (i, selector) in matcher.selectors.iter().enumerate() {
+        max_lengths[i] = selector.name.width() + 1;
+      }
 
-    let mut printed_keys =
-      Vec::with_capacity(selectors_count * matcher.variants.len());
+      if matcher.selectors.is_empty() {
+        // Nothing to separate from the `.match` keyword.
+      } else if max_lengths.len() > 1 && !this.options.compact {
+        this.push_str("\n  ");
+      } else {
+        this.push(' ');
+      }
 
-    for variant in &matcher.variants {
-      assert!(variant.keys.len() <= selectors_count);
+      // The keys are printed first, so that the width of each column is known
+      // before anything is written. Zipping with `max_lengths` (instead of
+      // indexing) ensures that stray keys can never be out of bounds.
+      let mut printed_keys = Vec::with_capacity(matcher.variants.len());
+      for variant in &matcher.variants {
+        let mut row = Vec::with_capacity(selectors_count);
+        for (key, max_length) in variant.keys.iter().zip(&mut max_lengths) {
+          let printed = this.try_visit_match_key(key);
+          *max_length = (*max_length).max(printed.text.width());
+          row.push(printed);
+        }
+        row.resize(selectors_count, Captured::default());
+        printed_keys.push(row);
+      }
 
-      for (i, key) in variant.keys.iter().enumerate() {
-        let printed = self.try_visit_match_key(key);
-        max_lengths[i] = max_lengths[i].max(printed.len());
-        printed_keys.push(printed);
+      for (i, (selector, max_length)) in
+        matcher.selectors.iter().zip(&max_lengths).enumerate()
+      {
+        selector.apply_visitor(this);
+        if i + 1 < matcher.selectors.len() {
+          if this.options.compact {
+            this.push(' ');
+          } else {
+            this.push_n(' ', max_length.saturating_sub(selector.name.width()));
+          }
+        }
       }
-      for _ in variant.keys.len()..selectors_count {
-        printed_keys.push("".to_string());
+
+      if let Some(first) = matcher.variants.first() {
+        let selectors_end = matcher.selectors_end_location();
+        if !this.options.compact
+          && this.had_empty_line(selectors_end, first.span().start, false)
+        {
+          this.push('\n');
+        }
       }
-    }
-    assert_eq!(printed_keys.len(), printed_keys.capacity());
 
-    for (i, selector) in matcher.selectors.iter().enumerate() {
-      selector.apply_visitor(self);
-      if i < selectors_count - 1 {
-        self.push_n(' ', max_lengths[i] - selector.name.len());
+      for (variant, row) in matcher.variants.iter().zip(&printed_keys) {
+        this.push_str("\n  ");
+
+        this.mapped(variant.span(), |this| {
+          let keys = this.capture(|this| {
+            for (printed_key, max_length) in row.iter().zip(&max_lengths) {
+              this.push_captured(printed_key);
+              if !this.options.compact {
+                let width = printed_key.text.width();
+                this.push_n(' ', max_length.saturating_sub(width));
+              }
+              this.push(' ');
+            }
+          });
+
+          let pattern =
+            this.capture(|this| variant.pattern.apply_visitor(this));
+
+          this.push_captured(&keys);
+          if this.options.indent_multiline_variants
+            && !variant.keys.is_empty()
+            && pattern.text.contains(['\n', '\r', '\u{2028}', '\u{2029}'])
+          {
+            // Only the padding after the last key is removed, which is never
+            // part of a mapped node.
+            let trimmed = this.out.trim_end_matches(' ').len();
+            this.out.truncate(trimmed);
+            this.push_str("\n    ");
+          }
+          this.push_captured(&pattern);
+        });
       }
-    }
+    });
+  }
+}
 
-    for (j, variant) in matcher.variants.iter().enumerate() {
-      self.push_str("\n  ");
+/// Check if the source text info can be used to look up locations of the
+/// message. This is not the case if the message contains synthetic locations,
+/// or if the info belongs to a different source text.
+///
+/// The text and identifiers of a parsed message borrow from the source text,
+/// so the first of them must point into the text of the info, at the offset of
+/// its location. A different text that happens to be long enough is rejected.
+fn info_matches(ast: &Message, info: &SourceTextInfo) -> bool {
+  let span = ast.span();
+  if span.start.is_synthetic()
+    || span.end.is_synthetic()
+    || !info.span().contains(&span)
+  {
+    return false;
+  }
 
-      for i in 0..selectors_count {
-        let printed_key = &printed_keys[j * selectors_count + i];
-        self.push_str(printed_key);
-        self.push_n(' ', max_lengths[i] - printed_key.len());
-        self.push(' ');
-      }
+  let mut finder = FirstSlice(None);
+  ast.apply_visitor(&mut finder);
+  let Some((start, slice)) = finder.0 else {
+    return true;
+  };
+  let span = Span::new(start..start + slice);
+  info.try_text(span).map(str::as_ptr) == Some(slice.as_ptr())
+}
+
+/// Finds the first non-empty text or identifier in the message, together with
+/// the location that its string starts at. Empty strings are skipped, because
+/// the parser does not always slice them from the source text.
+struct FirstSlice<'text>(Option<(Location, &'text str)>);
 
-      variant.pattern.apply_visitor(self);
+impl<'text> FirstSlice<'text> {
+  fn found(&mut self, start: Location, slice: &'text str) {
+    if self.0.is_none() && !slice.is_empty() {
+      self.0 = Some((start, slice));
     }
   }
 }
+
+impl<'ast, 'text> Visit<'ast, 'text> for FirstSlice<'text> {
+  fn visit_text(&mut self, text: &'ast Text<'text>) {
+    self.found(text.start, text.content);
+  }
+
+  fn visit_identifier(&mut self, id: &'ast Identifier<'text>) {
+    self.found(id.start, id.namespace.unwrap_or(id.name));
+  }
+}
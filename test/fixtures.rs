@@ -10,17 +10,27 @@ use file_test_runner::collection::CollectOptions;
 use file_test_runner::collection::CollectedTest;
 use file_test_runner::RunOptions;
 use file_test_runner::TestResult;
+use mf2_parser::analyze_semantics;
 use mf2_parser::ast;
 use mf2_parser::ast::Message;
-use mf2_parser::parse;
+use mf2_parser::parse_with_options;
+use mf2_parser::sort_by_span;
+use mf2_parser::validate_annotations;
+use mf2_parser::validate_matcher;
 use mf2_parser::Diagnostic;
+use mf2_parser::FunctionRegistry;
 use mf2_parser::Location;
+use mf2_parser::ParseOptions;
 use mf2_parser::SourceTextInfo;
 use mf2_parser::Span;
 use mf2_parser::Spanned;
 use mf2_parser::Visit;
 use mf2_parser::Visitable;
+use mf2_printer::normalize;
 use mf2_printer::print;
+use mf2_printer::print_with_options;
+use mf2_printer::ExponentSignStyle;
+use mf2_printer::PrintOptions;
 use unicode_width::UnicodeWidthStr;
 
 fn main() {
@@ -42,6 +52,7 @@ fn main() {
 fn run_test(test: &CollectedTest) {
   let file_text = test.read_to_string().unwrap();
 
+  let options_marker = "\n=== options ===\n";
   let spans_marker = "\n=== spans ===\n";
   let diagnostics_marker = "\n=== diagnostics ===\n";
   let fixed_marker = "\n=== fixed ===\n";
@@ -53,6 +64,15 @@ fn run_test(test: &CollectedTest) {
   let (message, rest_str) = file_text
     .split_once(spans_marker)
     .unwrap_or((&*file_text, ""));
+  let (message, options_str) =
+    message.split_once(options_marker).unwrap_or((message, ""));
+  let (
+    parse_options,
+    options,
+    options_preserve_ast,
+    validate_functions,
+    check_semantics,
+  ) = parse_fixture_options(options_str);
   let (expected_spans, rest_str) = rest_str
     .split_once(diagnostics_marker)
     .unwrap_or(("", rest_str));
@@ -72,7 +92,8 @@ fn run_test(test: &CollectedTest) {
     .map(|s| s.ends_with(".panic"))
     .unwrap_or(false)
   {
-    let result = panic::catch_unwind(|| parse(message));
+    let result =
+      panic::catch_unwind(|| parse_with_options(message, &parse_options));
     if result.is_ok() {
       panic!("expected panic, but parsing didn't");
     }
@@ -81,7 +102,15 @@ fn run_test(test: &CollectedTest) {
 
   let normalized_message = normalize_message(message);
 
-  let (actual_ast, diagnostics, info) = parse(message);
+  let (actual_ast, mut diagnostics, info) =
+    parse_with_options(message, &parse_options);
+  if validate_functions {
+    validate_functions_with_builtins(&actual_ast, &mut diagnostics);
+  }
+  if check_semantics {
+    analyze_semantics(&actual_ast, &mut diagnostics);
+    sort_by_span(&mut diagnostics);
+  }
   let has_fatal_diag = diagnostics.iter().any(|d| d.fatal());
 
   let actual_ast_dbg = generated_actual_ast_dbg(&actual_ast);
@@ -93,7 +122,7 @@ fn run_test(test: &CollectedTest) {
   let actual_formatted = if has_fatal_diag {
     cannot_format
   } else {
-    print(&actual_ast, Some(&info))
+    print_with_options(&actual_ast, Some(&info), None, &options)
   };
 
   let mut need_update = std::env::var("UPDATE").is_ok();
@@ -146,20 +175,51 @@ fn run_test(test: &CollectedTest) {
   }
 
   if need_update {
+    let options_section = if options_str.is_empty() {
+      "".to_string()
+    } else {
+      format!("{options_marker}{options_str}")
+    };
     std::fs::write(
       &test.path,
       format!(
-        "{message}{spans_marker}{actual_spans}{diagnostics_marker}{actual_diags}{fixed_marker}{actual_fixed}{formatted_marker}{actual_formatted}{ast_marker}{actual_ast_dbg}"
+        "{message}{options_section}{spans_marker}{actual_spans}{diagnostics_marker}{actual_diags}{fixed_marker}{actual_fixed}{formatted_marker}{actual_formatted}{ast_marker}{actual_ast_dbg}"
       ),
     )
     .unwrap();
   }
 
+  if let Ok(normalized) = normalize(message) {
+    pretty_assertions::assert_eq!(
+      normalize(&normalized).ok(),
+      Some(normalized),
+      "Normalizing is idempotent"
+    );
+  }
+
   if has_fatal_diag {
     // test that it does not panic
     print(&actual_ast, Some(&info));
+  } else if !options_preserve_ast {
+    let (new_ast, _, new_info) =
+      parse_with_options(&actual_formatted, &parse_options);
+    let new_formatted =
+      print_with_options(&new_ast, Some(&new_info), None, &options);
+    pretty_assertions::assert_eq!(
+      actual_formatted,
+      new_formatted,
+      "Formatting is stable"
+    );
   } else {
-    let (new_ast, new_diagnostics, new_info) = parse(&actual_formatted);
+    let (new_ast, mut new_diagnostics, new_info) =
+      parse_with_options(&actual_formatted, &parse_options);
+    if validate_functions {
+      validate_functions_with_builtins(&new_ast, &mut new_diagnostics);
+    }
+    if check_semantics {
+      analyze_semantics(&new_ast, &mut new_diagnostics);
+      sort_by_span(&mut new_diagnostics);
+    }
 
     let new_ast_dbg = generated_actual_ast_dbg(&new_ast);
     let re = regex::Regex::new(r"(span|start): @[\d\.]+").unwrap();
@@ -185,7 +245,8 @@ fn run_test(test: &CollectedTest) {
       );
     }
 
-    let new_formatted = print(&new_ast, Some(&new_info));
+    let new_formatted =
+      print_with_options(&new_ast, Some(&new_info), None, &options);
     pretty_assertions::assert_eq!(
       actual_formatted,
       new_formatted,
@@ -194,6 +255,94 @@ fn run_test(test: &CollectedTest) {
   }
 }
 
+/// Parses the `=== options ===` section of a fixture. Each line is the name of
+/// a parser or printer option to enable, `validate_functions` to also report
+/// the diagnostics of validating the message against the builtin function
+/// registry, or `analyze_semantics` to also report the diagnostics of semantic
+/// analysis. Returns the options, whether formatting with these options is
+/// expected to preserve the AST, whether to validate functions, and whether to
+/// analyze semantics.
+fn parse_fixture_options(
+  options_str: &str,
+) -> (ParseOptions, PrintOptions, bool, bool, bool) {
+  let mut parse_options = ParseOptions::default();
+  let mut options = PrintOptions::default();
+  let mut preserves_ast = true;
+  let mut validate_functions = false;
+  let mut check_semantics = false;
+  for line in options_str.lines().map(str::trim).filter(|l| !l.is_empty()) {
+    match line {
+      "validate_functions" => {
+        validate_functions = true;
+      }
+      "analyze_semantics" => {
+        check_semantics = true;
+      }
+      "ideographic_space_is_content" => {
+        parse_options.ideographic_space_is_content = true;
+      }
+      "leading_dot_requires_keyword" => {
+        parse_options.leading_dot_requires_keyword = true;
+      }
+      "drop_redundant_inputs" => {
+        options.drop_redundant_inputs = true;
+        preserves_ast = false;
+      }
+      "collapse_newlines" => {
+        options.collapse_newlines = true;
+        preserves_ast = false;
+      }
+      "sort_options" => {
+        options.sort_options = true;
+        preserves_ast = false;
+      }
+      "minimal_quoting" => {
+        options.minimal_quoting = true;
+        preserves_ast = false;
+      }
+      "exponent_sign_always_plus" => {
+        options.exponent_sign = ExponentSignStyle::AlwaysPlus;
+        preserves_ast = false;
+      }
+      "exponent_sign_never_plus" => {
+        options.exponent_sign = ExponentSignStyle::NeverPlus;
+        preserves_ast = false;
+      }
+      "indent_multiline_variants" => {
+        options.indent_multiline_variants = true;
+      }
+      "compact" => {
+        options.compact = true;
+      }
+      _ => panic!("Unknown option: {line}"),
+    }
+  }
+  (
+    parse_options,
+    options,
+    preserves_ast,
+    validate_functions,
+    check_semantics,
+  )
+}
+
+fn validate_functions_with_builtins<'text>(
+  ast: &Message<'text>,
+  diagnostics: &mut Vec<Diagnostic<'text>>,
+) {
+  let registry = FunctionRegistry::with_builtins();
+  validate_annotations(ast, &registry, diagnostics);
+  if let Message::Complex(ast::ComplexMessage {
+    declarations,
+    body: ast::ComplexMessageBody::Matcher(matcher),
+    ..
+  }) = ast
+  {
+    validate_matcher(matcher, declarations, &registry, diagnostics);
+  }
+  sort_by_span(diagnostics);
+}
+
 fn normalize_message(message: &str) -> String {
   message
     .chars()
@@ -216,29 +365,57 @@ fn generate_actual_diagnostics(
 ) -> String {
   let mut formatted_diagnostics = "".to_string();
   for (i, diag) in diagnostics.iter().enumerate() {
-    let span = diag.span();
-    let span_start = span.start.inner_byte_index_for_test() as usize;
-    let span_end = span.end.inner_byte_index_for_test() as usize;
-
-    let prefix = &input_message[0..span_start];
-    let contents = &input_message[span_start..span_end];
-
     if i != 0 {
       formatted_diagnostics.push('\n');
     }
     writeln!(formatted_diagnostics, "{}", diag).unwrap();
-    formatted_diagnostics.push(' ');
-    formatted_diagnostics.push(' ');
-    formatted_diagnostics.push_str(normalized_message);
-    formatted_diagnostics.push('\n');
-    iter::repeat(' ')
-      .take(prefix.width_cjk() + 2)
-      .chain(iter::repeat('^').take(contents.width_cjk()))
-      .for_each(|c| formatted_diagnostics.push(c));
+    push_span_underline(
+      &mut formatted_diagnostics,
+      diag.span(),
+      input_message,
+      normalized_message,
+      2,
+    );
+    for (span, label) in diag.related_spans() {
+      write!(
+        formatted_diagnostics,
+        "\n  related: {label} (at {span:?})\n"
+      )
+      .unwrap();
+      push_span_underline(
+        &mut formatted_diagnostics,
+        span,
+        input_message,
+        normalized_message,
+        4,
+      );
+    }
   }
   formatted_diagnostics
 }
 
+fn push_span_underline(
+  output: &mut String,
+  span: Span,
+  input_message: &str,
+  normalized_message: &str,
+  indent: usize,
+) {
+  let span_start = span.start.inner_byte_index_for_test() as usize;
+  let span_end = span.end.inner_byte_index_for_test() as usize;
+
+  let prefix = &input_message[0..span_start];
+  let contents = &input_message[span_start..span_end];
+
+  output.push_str(&" ".repeat(indent));
+  output.push_str(normalized_message);
+  output.push('\n');
+  iter::repeat(' ')
+    .take(prefix.width_cjk() + indent)
+    .chain(iter::repeat('^').take(contents.width_cjk()))
+    .for_each(|c| output.push(c));
+}
+
 fn generate_actual_fixed(
   diagnostics: &[Diagnostic],
   input_message: &str,